@@ -3,8 +3,35 @@ mod error;
 pub use error::{VorbisError, VorbisLibrary, VorbisLibraryError, VorbisLibraryErrorKind};
 
 mod ogg;
-pub(crate) use ogg::{OggPacket, OggStream};
+pub(crate) use ogg::{
+	OggPacket, OggPage, OggPageSeek, OggStream, OggSync, read_variable_length,
+	write_variable_length
+};
 
 mod vorbis;
 // self:: must be used in order to clear up ambiguity
-pub(crate) use self::vorbis::{VorbisComments, VorbisInfo};
+pub(crate) use self::vorbis::{RawVorbisComments, VorbisInfo};
+
+mod comments;
+pub use comments::{VorbisComments, VorbisCommentsIter, VorbisPicture, VorbisPictureType};
+
+mod channels;
+pub use channels::{VorbisChannel, VorbisChannelLayout, VorbisChannelOrder};
+
+mod mixing;
+pub(crate) use mixing::ChannelMixer;
+pub use mixing::VorbisChannelMatrix;
+
+mod loudness;
+pub use loudness::VorbisLoudnessMeter;
+
+mod replay_gain;
+pub use replay_gain::{VorbisReplayGain, VorbisReplayGainMode};
+
+#[cfg(feature = "resampling")]
+mod resampler;
+#[cfg(feature = "resampling")]
+pub(crate) use resampler::AudioResampler;
+
+#[cfg(test)]
+pub(crate) mod test_util;
@@ -0,0 +1,267 @@
+use std::{
+	fmt::{self, Display, Formatter},
+	num::NonZeroU8
+};
+
+/// A speaker position that a channel of an audio signal is meant to be played on.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum VorbisChannel {
+	/// Front left speaker.
+	FrontLeft,
+	/// Front center speaker.
+	FrontCenter,
+	/// Front right speaker.
+	FrontRight,
+	/// Side left speaker.
+	SideLeft,
+	/// Side right speaker.
+	SideRight,
+	/// Rear (back) left speaker.
+	RearLeft,
+	/// Rear (back) center speaker.
+	RearCenter,
+	/// Rear (back) right speaker.
+	RearRight,
+	/// Low-frequency effects (LFE) speaker, also known as subwoofer.
+	LowFrequencyEffects
+}
+
+/// The layout of the channels of a Vorbis audio signal, which determines the speaker
+/// position each channel is meant to be played on.
+///
+/// The Vorbis I specification defines a layout for signals with up to eight channels,
+/// which are the only layouts these bindings can remap to other channel orders. Signals
+/// with more channels use application-defined layouts.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum VorbisChannelLayout {
+	/// One channel: front center.
+	Mono,
+	/// Two channels: front left and front right.
+	Stereo,
+	/// Three channels, also known as linear surround: front left, front center and front
+	/// right.
+	ThreePointZero,
+	/// Four channels, also known as quadraphonic: front left, front right, rear left and
+	/// rear right.
+	Quadraphonic,
+	/// Five channels: front left, front center, front right, rear left and rear right.
+	FivePointZero,
+	/// Six channels: front left, front center, front right, rear left, rear right and LFE.
+	FivePointOne,
+	/// Seven channels: front left, front center, front right, side left, side right, rear
+	/// center and LFE.
+	SixPointOne,
+	/// Eight channels: front left, front center, front right, side left, side right, rear
+	/// left, rear right and LFE.
+	SevenPointOne,
+	/// An application-defined layout with the specified number of channels, which must be
+	/// more than eight.
+	ApplicationDefined(NonZeroU8)
+}
+
+impl VorbisChannelLayout {
+	/// Returns the layout defined by the Vorbis I specification for the specified number
+	/// of channels.
+	pub fn from_channel_count(channels: NonZeroU8) -> Self {
+		match channels.get() {
+			1 => Self::Mono,
+			2 => Self::Stereo,
+			3 => Self::ThreePointZero,
+			4 => Self::Quadraphonic,
+			5 => Self::FivePointZero,
+			6 => Self::FivePointOne,
+			7 => Self::SixPointOne,
+			8 => Self::SevenPointOne,
+			_ => Self::ApplicationDefined(channels)
+		}
+	}
+
+	/// Returns the number of channels of this layout.
+	pub fn channel_count(self) -> NonZeroU8 {
+		match self {
+			Self::ApplicationDefined(channels) => channels,
+			_ => {
+				// SAFETY: every layout defined by the Vorbis I specification has channels
+				unsafe { NonZeroU8::new_unchecked(self.vorbis_channels().len() as u8) }
+			}
+		}
+	}
+
+	/// Returns the speaker positions of the channels of this layout, in the specified
+	/// order. `None` is returned for application-defined layouts.
+	pub fn channels(self, order: VorbisChannelOrder) -> Option<Vec<VorbisChannel>> {
+		if let Self::ApplicationDefined(_) = self {
+			return None;
+		}
+
+		let mut channels = self.vorbis_channels().to_vec();
+		channels.sort_by_key(|channel| order.priority(*channel));
+		Some(channels)
+	}
+
+	/// Returns the index, in the specified order, of the channel at the specified index in
+	/// the Vorbis channel order. Channels of application-defined layouts are never remapped.
+	pub(crate) fn channel_index(self, order: VorbisChannelOrder, vorbis_index: usize) -> usize {
+		let channels = self.vorbis_channels();
+		let Some(channel) = channels.get(vorbis_index) else {
+			return vorbis_index;
+		};
+
+		channels
+			.iter()
+			.filter(|other_channel| order.priority(**other_channel) < order.priority(*channel))
+			.count()
+	}
+
+	/// Returns the speaker positions of the channels of this layout, in the Vorbis channel
+	/// order. The returned slice is empty for application-defined layouts.
+	fn vorbis_channels(self) -> &'static [VorbisChannel] {
+		use VorbisChannel::*;
+
+		match self {
+			Self::Mono => &[FrontCenter],
+			Self::Stereo => &[FrontLeft, FrontRight],
+			Self::ThreePointZero => &[FrontLeft, FrontCenter, FrontRight],
+			Self::Quadraphonic => &[FrontLeft, FrontRight, RearLeft, RearRight],
+			Self::FivePointZero => &[FrontLeft, FrontCenter, FrontRight, RearLeft, RearRight],
+			Self::FivePointOne => &[
+				FrontLeft,
+				FrontCenter,
+				FrontRight,
+				RearLeft,
+				RearRight,
+				LowFrequencyEffects
+			],
+			Self::SixPointOne => &[
+				FrontLeft,
+				FrontCenter,
+				FrontRight,
+				SideLeft,
+				SideRight,
+				RearCenter,
+				LowFrequencyEffects
+			],
+			Self::SevenPointOne => &[
+				FrontLeft,
+				FrontCenter,
+				FrontRight,
+				SideLeft,
+				SideRight,
+				RearLeft,
+				RearRight,
+				LowFrequencyEffects
+			],
+			Self::ApplicationDefined(_) => &[]
+		}
+	}
+}
+
+impl Display for VorbisChannelLayout {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Mono => f.write_str("mono"),
+			Self::Stereo => f.write_str("stereo"),
+			Self::ThreePointZero => f.write_str("3.0"),
+			Self::Quadraphonic => f.write_str("quadraphonic"),
+			Self::FivePointZero => f.write_str("5.0"),
+			Self::FivePointOne => f.write_str("5.1"),
+			Self::SixPointOne => f.write_str("6.1"),
+			Self::SevenPointOne => f.write_str("7.1"),
+			Self::ApplicationDefined(channels) => write!(f, "{channels} channels")
+		}
+	}
+}
+
+/// An order for the channels of an audio signal, used to remap the channels of a
+/// [`VorbisChannelLayout`] from and to the order other formats and APIs use.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum VorbisChannelOrder {
+	/// The order defined by the Vorbis I specification, which is also used by AC-3 and
+	/// most film sound formats. No channel remapping is done for this order.
+	#[default]
+	Vorbis,
+	/// The order defined by SMPTE 2036-2 and used by WAV files, the `WAVEFORMATEXTENSIBLE`
+	/// channel masks, FLAC and most operating system audio APIs: front left, front right,
+	/// front center, LFE, rear left, rear right, rear center, side left and side right.
+	Smpte,
+	/// The order used by ALSA: front left, front right, rear left, rear right, front
+	/// center, LFE, side left, side right and rear center.
+	Alsa
+}
+
+impl VorbisChannelOrder {
+	/// Returns the position of the specified channel in this order, relative to other
+	/// channels. Lower values come first.
+	fn priority(self, channel: VorbisChannel) -> u8 {
+		use VorbisChannel::*;
+
+		match self {
+			Self::Vorbis => match channel {
+				FrontLeft => 0,
+				FrontCenter => 1,
+				FrontRight => 2,
+				SideLeft => 3,
+				SideRight => 4,
+				RearLeft => 5,
+				RearCenter => 6,
+				RearRight => 7,
+				LowFrequencyEffects => 8
+			},
+			Self::Smpte => match channel {
+				FrontLeft => 0,
+				FrontRight => 1,
+				FrontCenter => 2,
+				LowFrequencyEffects => 3,
+				RearLeft => 4,
+				RearRight => 5,
+				RearCenter => 6,
+				SideLeft => 7,
+				SideRight => 8
+			},
+			Self::Alsa => match channel {
+				FrontLeft => 0,
+				FrontRight => 1,
+				RearLeft => 2,
+				RearRight => 3,
+				FrontCenter => 4,
+				LowFrequencyEffects => 5,
+				SideLeft => 6,
+				SideRight => 7,
+				RearCenter => 8
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::num::NonZeroU8;
+
+	use super::{VorbisChannel::*, VorbisChannelLayout, VorbisChannelOrder};
+
+	#[test]
+	fn five_point_one_is_remapped_to_smpte_order() {
+		let layout = VorbisChannelLayout::from_channel_count(NonZeroU8::new(6).unwrap());
+
+		assert_eq!(layout, VorbisChannelLayout::FivePointOne);
+		assert_eq!(
+			layout.channels(VorbisChannelOrder::Smpte).unwrap(),
+			[
+				FrontLeft,
+				FrontRight,
+				FrontCenter,
+				LowFrequencyEffects,
+				RearLeft,
+				RearRight
+			]
+		);
+		assert_eq!(
+			(0..6)
+				.map(|i| layout.channel_index(VorbisChannelOrder::Smpte, i))
+				.collect::<Vec<_>>(),
+			[0, 2, 1, 4, 5, 3]
+		);
+	}
+}
@@ -0,0 +1,547 @@
+use std::{
+	borrow::Cow,
+	ffi::CString,
+	fmt::{self, Display, Formatter},
+	slice
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+
+use crate::common::VorbisError;
+
+/// The name of the Vorbis comment field that stores embedded pictures, as defined by the
+/// [Xiph.Org Vorbis comment field recommendations].
+///
+/// [Xiph.Org Vorbis comment field recommendations]: https://wiki.xiph.org/VorbisComment#Cover_art
+const PICTURE_TAG: &str = "METADATA_BLOCK_PICTURE";
+
+/// A list of Vorbis user comments, which is the metadata storage mechanism defined
+/// by the [Vorbis I specification]. Each comment is a tag-value pair, where tags are
+/// case-insensitive ASCII field names that may appear more than once, and values are
+/// arbitrary UTF-8 strings. The list also contains a vendor string, which identifies
+/// the software that generated the stream.
+///
+/// This type is used both to configure the comments written by a
+/// [`VorbisEncoderBuilder`](crate::VorbisEncoderBuilder) and to expose the comments read
+/// by a [`VorbisDecoder`](crate::VorbisDecoder), so comments can be carried over from an
+/// input stream to an output stream when transcoding. The order of comments is preserved.
+///
+/// [Vorbis I specification]: https://xiph.org/vorbis/doc/v-comment.html
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct VorbisComments {
+	vendor: String,
+	comments: Vec<(String, String)>
+}
+
+impl VorbisComments {
+	/// Creates a new, empty list of Vorbis user comments, with an empty vendor string.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Returns the vendor string of this list of comments.
+	///
+	/// When this list was read by a decoder, the vendor string usually identifies the
+	/// encoder that generated the stream.
+	pub fn vendor(&self) -> &str {
+		&self.vendor
+	}
+
+	/// Sets the vendor string of this list of comments.
+	///
+	/// `libvorbis` always writes its own vendor string when encoding, so the vendor
+	/// string is ignored by encoders.
+	pub fn set_vendor(&mut self, vendor: impl Into<String>) -> &mut Self {
+		self.vendor = vendor.into();
+		self
+	}
+
+	/// Appends the specified tag-value pair to this list of comments, keeping any
+	/// previous comments with the same tag.
+	///
+	/// An error will be returned if the tag is not a valid Vorbis comment field name
+	/// (i.e., it contains characters outside of the 0x20 through 0x7D ASCII range, or
+	/// an `=` character), or if either the tag or value contain NUL characters.
+	pub fn add<'tag, 'value>(
+		&mut self,
+		tag: impl Into<Cow<'tag, str>>,
+		value: impl Into<Cow<'value, str>>
+	) -> Result<&mut Self, VorbisError> {
+		self.comments
+			.push(validate_comment(tag.into(), value.into())?);
+		Ok(self)
+	}
+
+	/// Removes every comment with the specified tag, compared case-insensitively, from this
+	/// list of comments. Returns the number of removed comments.
+	pub fn remove(&mut self, tag: &str) -> usize {
+		let previous_len = self.comments.len();
+		self.comments
+			.retain(|(comment_tag, _)| !comment_tag.eq_ignore_ascii_case(tag));
+		previous_len - self.comments.len()
+	}
+
+	/// Replaces every comment with the specified tag, compared case-insensitively, by a single
+	/// comment with the specified value. The new comment takes the place of the first replaced
+	/// comment, or is appended if there was no comment with such tag.
+	///
+	/// The same validation rules as in [`add`](Self::add) apply.
+	pub fn replace<'tag, 'value>(
+		&mut self,
+		tag: impl Into<Cow<'tag, str>>,
+		value: impl Into<Cow<'value, str>>
+	) -> Result<&mut Self, VorbisError> {
+		let comment = validate_comment(tag.into(), value.into())?;
+
+		let first_position = self.position(&comment.0);
+		self.remove(&comment.0);
+		match first_position {
+			Some(position) => self.comments.insert(position, comment),
+			None => self.comments.push(comment)
+		}
+
+		Ok(self)
+	}
+
+	/// Returns the value of the first comment with the specified tag, compared
+	/// case-insensitively, if any.
+	pub fn get(&self, tag: &str) -> Option<&str> {
+		self.get_all(tag).next()
+	}
+
+	/// Returns an iterator over the values of every comment with the specified tag,
+	/// compared case-insensitively, in the order they appear in the list.
+	pub fn get_all<'this>(&'this self, tag: &str) -> impl Iterator<Item = &'this str> {
+		self.comments
+			.iter()
+			.filter(move |(comment_tag, _)| comment_tag.eq_ignore_ascii_case(tag))
+			.map(|(_, value)| value.as_str())
+	}
+
+	/// Checks whether this list contains any comment with the specified tag, compared
+	/// case-insensitively.
+	pub fn contains(&self, tag: &str) -> bool {
+		self.position(tag).is_some()
+	}
+
+	/// Returns an iterator over the tag-value pairs of every comment in this list, in order.
+	pub fn iter(&self) -> VorbisCommentsIter<'_> {
+		VorbisCommentsIter {
+			comments: self.comments.iter()
+		}
+	}
+
+	/// Returns the number of comments in this list.
+	pub fn len(&self) -> usize {
+		self.comments.len()
+	}
+
+	/// Checks whether this list contains no comments. The vendor string is not considered.
+	pub fn is_empty(&self) -> bool {
+		self.comments.is_empty()
+	}
+
+	/// Removes every comment from this list, keeping the vendor string.
+	pub fn clear(&mut self) {
+		self.comments.clear();
+	}
+
+	/// Returns an iterator over the pictures embedded in this list of comments as base64-encoded
+	/// `METADATA_BLOCK_PICTURE` comments, in order. Malformed pictures are yielded as errors.
+	pub fn pictures(&self) -> impl Iterator<Item = Result<VorbisPicture, VorbisError>> {
+		self.get_all(PICTURE_TAG).map(VorbisPicture::from_base64)
+	}
+
+	/// Embeds the specified picture in this list of comments, appending a base64-encoded
+	/// `METADATA_BLOCK_PICTURE` comment for it. This is the recommended way of storing
+	/// cover art and other pictures in Vorbis streams.
+	pub fn add_picture(&mut self, picture: &VorbisPicture) -> Result<&mut Self, VorbisError> {
+		self.add(PICTURE_TAG, picture.to_base64()?)
+	}
+
+	/// Removes every picture embedded in this list of comments. Returns the number of
+	/// removed pictures.
+	pub fn remove_pictures(&mut self) -> usize {
+		self.remove(PICTURE_TAG)
+	}
+
+	/// Returns the index of the first comment with the specified tag, compared
+	/// case-insensitively, if any.
+	fn position(&self, tag: &str) -> Option<usize> {
+		self.comments
+			.iter()
+			.position(|(comment_tag, _)| comment_tag.eq_ignore_ascii_case(tag))
+	}
+
+	/// Appends a comment without validating it. This is meant to be used for comments
+	/// read from a stream, which may not follow the specification to the letter, but
+	/// are still worth preserving.
+	pub(crate) fn push_unchecked(&mut self, tag: String, value: String) {
+		self.comments.push((tag, value));
+	}
+}
+
+impl<'this> IntoIterator for &'this VorbisComments {
+	type Item = (&'this str, &'this str);
+	type IntoIter = VorbisCommentsIter<'this>;
+
+	fn into_iter(self) -> Self::IntoIter {
+		self.iter()
+	}
+}
+
+/// An iterator over the tag-value pairs of a [`VorbisComments`] list.
+#[derive(Clone, Debug)]
+pub struct VorbisCommentsIter<'comments> {
+	comments: slice::Iter<'comments, (String, String)>
+}
+
+impl<'comments> Iterator for VorbisCommentsIter<'comments> {
+	type Item = (&'comments str, &'comments str);
+
+	fn next(&mut self) -> Option<Self::Item> {
+		self.comments
+			.next()
+			.map(|(tag, value)| (tag.as_str(), value.as_str()))
+	}
+
+	fn size_hint(&self) -> (usize, Option<usize>) {
+		self.comments.size_hint()
+	}
+}
+
+impl DoubleEndedIterator for VorbisCommentsIter<'_> {
+	fn next_back(&mut self) -> Option<Self::Item> {
+		self.comments
+			.next_back()
+			.map(|(tag, value)| (tag.as_str(), value.as_str()))
+	}
+}
+
+impl ExactSizeIterator for VorbisCommentsIter<'_> {}
+
+/// Checks that the specified tag is a valid Vorbis comment field name, as defined by the
+/// Vorbis I specification, and that the value can be handed over to C code, returning
+/// an owned tag-value pair if so.
+fn validate_comment(
+	tag: Cow<'_, str>,
+	value: Cow<'_, str>
+) -> Result<(String, String), VorbisError> {
+	if !tag
+		.bytes()
+		.all(|byte| (0x20..=0x7D).contains(&byte) && byte != b'=')
+	{
+		return Err(VorbisError::InvalidCommentTag(tag.into_owned()));
+	}
+
+	let value = value.into_owned();
+	// libvorbis handles comments as NUL-terminated C strings
+	if value.contains('\0') {
+		return Err(CString::new(value).unwrap_err().into());
+	}
+
+	Ok((tag.into_owned(), value))
+}
+
+/// A picture embedded in a Vorbis comments list, such as a front cover.
+///
+/// Pictures are stored as base64-encoded FLAC `METADATA_BLOCK_PICTURE` structures, which
+/// is the [recommended approach] for Ogg Vorbis streams. The legacy `COVERART` comment
+/// field is not supported.
+///
+/// [recommended approach]: https://wiki.xiph.org/VorbisComment#Cover_art
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct VorbisPicture {
+	/// The type of the picture, indicating what it depicts.
+	pub picture_type: VorbisPictureType,
+	/// The MIME type of the picture data, such as `image/jpeg` or `image/png`. The special
+	/// `-->` MIME type signals that the data is an URL to the picture, rather than the
+	/// picture itself.
+	pub mime_type: String,
+	/// A textual description of the picture.
+	pub description: String,
+	/// The width of the picture, in pixels. Zero if unknown.
+	pub width: u32,
+	/// The height of the picture, in pixels. Zero if unknown.
+	pub height: u32,
+	/// The color depth of the picture, in bits per pixel. Zero if unknown.
+	pub color_depth: u32,
+	/// The number of colors used by indexed-color pictures, such as GIF images. Zero for
+	/// non-indexed pictures.
+	pub indexed_colors: u32,
+	/// The binary data of the picture, in the format indicated by the MIME type.
+	pub data: Vec<u8>
+}
+
+impl VorbisPicture {
+	/// Creates a new picture of the specified type, MIME type and data, without a
+	/// description or dimensions.
+	pub fn new(
+		picture_type: VorbisPictureType,
+		mime_type: impl Into<String>,
+		data: Vec<u8>
+	) -> Self {
+		Self {
+			picture_type,
+			mime_type: mime_type.into(),
+			description: String::new(),
+			width: 0,
+			height: 0,
+			color_depth: 0,
+			indexed_colors: 0,
+			data
+		}
+	}
+
+	/// Parses a picture from the base64-encoded value of a `METADATA_BLOCK_PICTURE` comment.
+	pub fn from_base64(encoded_picture: &str) -> Result<Self, VorbisError> {
+		let picture_block = BASE64
+			.decode(encoded_picture.trim())
+			.map_err(|_| VorbisError::MalformedPicture)?;
+
+		Self::from_block(&picture_block).ok_or(VorbisError::MalformedPicture)
+	}
+
+	/// Encodes this picture to the base64 value of a `METADATA_BLOCK_PICTURE` comment.
+	///
+	/// An error may be returned if some picture field is too long to be represented.
+	pub fn to_base64(&self) -> Result<String, VorbisError> {
+		fn push_u32(block: &mut Vec<u8>, value: u32) {
+			block.extend_from_slice(&value.to_be_bytes());
+		}
+
+		fn push_bytes(block: &mut Vec<u8>, bytes: &[u8]) -> Result<(), VorbisError> {
+			push_u32(block, bytes.len().try_into()?);
+			block.extend_from_slice(bytes);
+			Ok(())
+		}
+
+		let mut picture_block = Vec::with_capacity(32 + self.mime_type.len() + self.data.len());
+		push_u32(&mut picture_block, self.picture_type.into());
+		push_bytes(&mut picture_block, self.mime_type.as_bytes())?;
+		push_bytes(&mut picture_block, self.description.as_bytes())?;
+		push_u32(&mut picture_block, self.width);
+		push_u32(&mut picture_block, self.height);
+		push_u32(&mut picture_block, self.color_depth);
+		push_u32(&mut picture_block, self.indexed_colors);
+		push_bytes(&mut picture_block, &self.data)?;
+
+		Ok(BASE64.encode(picture_block))
+	}
+
+	/// Parses a picture from a binary FLAC `METADATA_BLOCK_PICTURE` structure, returning
+	/// `None` if it is truncated or otherwise malformed.
+	fn from_block(mut picture_block: &[u8]) -> Option<Self> {
+		fn read_u32(block: &mut &[u8]) -> Option<u32> {
+			let (value, rest) = block.split_first_chunk()?;
+			*block = rest;
+			Some(u32::from_be_bytes(*value))
+		}
+
+		fn read_bytes<'block>(block: &mut &'block [u8]) -> Option<&'block [u8]> {
+			let length = read_u32(block)?.try_into().ok()?;
+			let (bytes, rest) = block.split_at_checked(length)?;
+			*block = rest;
+			Some(bytes)
+		}
+
+		let picture_type = read_u32(&mut picture_block)?.into();
+		let mime_type = String::from_utf8(read_bytes(&mut picture_block)?.into()).ok()?;
+		let description = String::from_utf8(read_bytes(&mut picture_block)?.into()).ok()?;
+		let width = read_u32(&mut picture_block)?;
+		let height = read_u32(&mut picture_block)?;
+		let color_depth = read_u32(&mut picture_block)?;
+		let indexed_colors = read_u32(&mut picture_block)?;
+		let data = read_bytes(&mut picture_block)?.into();
+
+		Some(Self {
+			picture_type,
+			mime_type,
+			description,
+			width,
+			height,
+			color_depth,
+			indexed_colors,
+			data
+		})
+	}
+}
+
+/// The type of a [`VorbisPicture`], as defined by the FLAC `METADATA_BLOCK_PICTURE`
+/// structure, which in turn borrows it from the ID3v2 `APIC` frame.
+#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
+pub enum VorbisPictureType {
+	/// Other.
+	Other,
+	/// 32x32 pixels file icon, in PNG format.
+	FileIcon,
+	/// Other file icon.
+	OtherFileIcon,
+	/// Front cover.
+	FrontCover,
+	/// Back cover.
+	BackCover,
+	/// Leaflet page.
+	LeafletPage,
+	/// Media, such as the label side of a CD.
+	Media,
+	/// Lead artist, lead performer or soloist.
+	LeadArtist,
+	/// Artist or performer.
+	Artist,
+	/// Conductor.
+	Conductor,
+	/// Band or orchestra.
+	Band,
+	/// Composer.
+	Composer,
+	/// Lyricist or text writer.
+	Lyricist,
+	/// Recording location.
+	RecordingLocation,
+	/// Picture taken during recording.
+	DuringRecording,
+	/// Picture taken during performance.
+	DuringPerformance,
+	/// Movie or video screen capture.
+	ScreenCapture,
+	/// A bright colored fish.
+	BrightColoredFish,
+	/// Illustration.
+	Illustration,
+	/// Band or artist logotype.
+	BandLogotype,
+	/// Publisher or studio logotype.
+	PublisherLogotype,
+	/// A picture type not defined by the specification at the time of writing.
+	Unknown(u32)
+}
+
+impl From<u32> for VorbisPictureType {
+	fn from(picture_type: u32) -> Self {
+		match picture_type {
+			0 => Self::Other,
+			1 => Self::FileIcon,
+			2 => Self::OtherFileIcon,
+			3 => Self::FrontCover,
+			4 => Self::BackCover,
+			5 => Self::LeafletPage,
+			6 => Self::Media,
+			7 => Self::LeadArtist,
+			8 => Self::Artist,
+			9 => Self::Conductor,
+			10 => Self::Band,
+			11 => Self::Composer,
+			12 => Self::Lyricist,
+			13 => Self::RecordingLocation,
+			14 => Self::DuringRecording,
+			15 => Self::DuringPerformance,
+			16 => Self::ScreenCapture,
+			17 => Self::BrightColoredFish,
+			18 => Self::Illustration,
+			19 => Self::BandLogotype,
+			20 => Self::PublisherLogotype,
+			picture_type => Self::Unknown(picture_type)
+		}
+	}
+}
+
+impl From<VorbisPictureType> for u32 {
+	fn from(picture_type: VorbisPictureType) -> Self {
+		match picture_type {
+			VorbisPictureType::Other => 0,
+			VorbisPictureType::FileIcon => 1,
+			VorbisPictureType::OtherFileIcon => 2,
+			VorbisPictureType::FrontCover => 3,
+			VorbisPictureType::BackCover => 4,
+			VorbisPictureType::LeafletPage => 5,
+			VorbisPictureType::Media => 6,
+			VorbisPictureType::LeadArtist => 7,
+			VorbisPictureType::Artist => 8,
+			VorbisPictureType::Conductor => 9,
+			VorbisPictureType::Band => 10,
+			VorbisPictureType::Composer => 11,
+			VorbisPictureType::Lyricist => 12,
+			VorbisPictureType::RecordingLocation => 13,
+			VorbisPictureType::DuringRecording => 14,
+			VorbisPictureType::DuringPerformance => 15,
+			VorbisPictureType::ScreenCapture => 16,
+			VorbisPictureType::BrightColoredFish => 17,
+			VorbisPictureType::Illustration => 18,
+			VorbisPictureType::BandLogotype => 19,
+			VorbisPictureType::PublisherLogotype => 20,
+			VorbisPictureType::Unknown(picture_type) => picture_type
+		}
+	}
+}
+
+impl Display for VorbisPictureType {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Unknown(picture_type) => f.write_fmt(format_args!("Unknown ({picture_type})")),
+			picture_type => fmt::Debug::fmt(picture_type, f)
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{VorbisComments, VorbisPicture, VorbisPictureType};
+
+	#[test]
+	fn comments_are_edited_case_insensitively() {
+		let mut comments = VorbisComments::new();
+		comments
+			.add("ARTIST", "First")
+			.unwrap()
+			.add("title", "Song")
+			.unwrap()
+			.add("Artist", "Second")
+			.unwrap();
+
+		assert_eq!(comments.get("artist"), Some("First"));
+		assert_eq!(
+			comments.get_all("ARTIST").collect::<Vec<_>>(),
+			["First", "Second"]
+		);
+
+		comments.replace("artist", "Third").unwrap();
+		assert_eq!(
+			comments.iter().collect::<Vec<_>>(),
+			[("artist", "Third"), ("title", "Song")]
+		);
+
+		assert_eq!(comments.remove("TITLE"), 1);
+		assert!(!comments.contains("title"));
+		assert_eq!(comments.len(), 1);
+
+		assert!(comments.add("BAD=TAG", "value").is_err());
+		assert!(comments.add("TAG", "bad\0value").is_err());
+	}
+
+	#[test]
+	fn pictures_roundtrip() {
+		let mut picture = VorbisPicture::new(
+			VorbisPictureType::FrontCover,
+			"image/png",
+			vec![0x89, b'P', b'N', b'G']
+		);
+		picture.description = "Cover".into();
+		picture.width = 300;
+		picture.height = 300;
+		picture.color_depth = 24;
+
+		let mut comments = VorbisComments::new();
+		comments.add_picture(&picture).unwrap();
+
+		let pictures = comments.pictures().collect::<Result<Vec<_>, _>>().unwrap();
+		assert_eq!(pictures, [picture]);
+
+		comments
+			.add("METADATA_BLOCK_PICTURE", "bm90IGEgcGljdHVyZQ==")
+			.unwrap();
+		assert!(comments.pictures().nth(1).unwrap().is_err());
+
+		assert_eq!(comments.remove_pictures(), 2);
+	}
+}
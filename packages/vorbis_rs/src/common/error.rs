@@ -9,6 +9,8 @@ use std::{
 
 use thiserror::Error;
 
+use crate::{VorbisChannelLayout, VorbisDecoderLimit};
+
 /// An error condition that may happen during an encoding or decoding operation.
 ///
 /// New error condition types may be added over time.
@@ -42,6 +44,14 @@ pub enum VorbisError {
 	/// A requested Vorbis user comment string contains a NUL character, which is not allowed.
 	#[error("Invalid comment string: {0}")]
 	InvalidCommentString(#[from] NulError),
+	/// A requested Vorbis user comment tag is not a valid field name, as it contains characters
+	/// outside of the 0x20 through 0x7D ASCII range, or an `=` character.
+	#[error("Invalid comment tag: {0:?}")]
+	InvalidCommentTag(String),
+	/// A picture embedded in the Vorbis user comments is not a valid base64-encoded
+	/// `METADATA_BLOCK_PICTURE` structure.
+	#[error("Malformed METADATA_BLOCK_PICTURE comment")]
+	MalformedPicture,
 	/// A number had a value that exceeded platform-dependent numerical limits.
 	#[error("Integer outside of expected range: {0}")]
 	RangeExceeded(#[from] TryFromIntError),
@@ -54,10 +64,59 @@ pub enum VorbisError {
 	#[cfg(feature = "stream-serial-rng")]
 	#[error("RNG error: {0}")]
 	Rng(#[from] getrandom::Error),
+	/// An error occurred while resampling an audio signal.
+	#[cfg(feature = "resampling")]
+	#[error("Resampling error: {0}")]
+	Resampling(#[from] rubato::ResampleError),
+	/// A conversion between the specified channel layouts is not supported, as the speaker
+	/// positions of their channels are unknown.
+	#[error("Unsupported channel conversion from {from} to {to}")]
+	UnsupportedChannelConversion {
+		/// The layout of the channels to convert.
+		from: VorbisChannelLayout,
+		/// The layout of the channels to convert to.
+		to: VorbisChannelLayout
+	},
+	/// A channel matrix does not have a coefficient for every input and output channel, or
+	/// its number of input channels does not match the channels of the signal it is applied to.
+	#[error("Invalid channel matrix dimensions")]
+	InvalidChannelMatrix,
+	/// A decoded stream exceeded one of the limits set for its decoder.
+	#[error("Decoder limit exceeded: {0}")]
+	DecoderLimitExceeded(VorbisDecoderLimit),
+	/// The requested range of samples to cut from a stream is empty, or it is too short to
+	/// be represented exactly by a new stream.
+	#[error("The requested range of samples to cut is empty or not representable")]
+	UnrepresentableCutRange,
+	/// Serialized seek table data is not valid.
+	#[error("Malformed seek table")]
+	MalformedSeekTable,
+	/// A seek table was built for a different stream than the one it was used with.
+	#[error("The seek table does not belong to the decoded stream")]
+	MismatchedSeekTable,
+	/// An Icecast server rejected the request to stream to one of its mounts.
+	#[cfg(feature = "icecast")]
+	#[error("Icecast request rejected: {status_code} {reason}")]
+	IcecastRequestRejected {
+		/// The HTTP status code of the response.
+		status_code: u16,
+		/// The reason phrase of the response.
+		reason: String
+	},
+	/// A `VorbisIcecastSource` lost its connection to the Icecast server because finishing
+	/// its previous stream or starting a new one failed when changing its comments. A new
+	/// source must be connected to keep broadcasting.
+	#[cfg(feature = "icecast")]
+	#[error("The Icecast connection was lost after a failed comment change")]
+	IcecastConnectionLost,
 	/// The output sink was already consumed by a previous `VorbisEncoderBuilder::build`
 	/// operation. A new one must be set up to keep using the builder to create new encoders.
 	#[error("The output sink was already consumed by a previous build operation")]
-	ConsumedEncoderBuilderSink
+	ConsumedEncoderBuilderSink,
+	/// An encoder configuration that requires a seekable sink was used to build an encoder
+	/// for a sink that can't be sought.
+	#[error("The encoder configuration requires a seekable sink")]
+	UnseekableEncoderSink
 }
 
 #[doc(hidden)] // Implementation detail to allow for ergonomic usage of ?
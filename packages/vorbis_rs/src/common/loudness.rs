@@ -0,0 +1,410 @@
+use std::{collections::VecDeque, f64::consts::PI, num::NonZeroU32};
+
+use crate::{VorbisChannel, VorbisChannelLayout, VorbisChannelOrder, VorbisError};
+
+/// The loudness of the quietest gating blocks that count towards the integrated loudness
+/// and loudness range, in LUFS, as defined by ITU-R BS.1770 and EBU Tech 3342.
+const ABSOLUTE_GATE: f64 = -70.0;
+/// The loudness of the quietest gating blocks that count towards the integrated loudness,
+/// relative to the loudness of the blocks above the absolute gate, in LU.
+const INTEGRATED_RELATIVE_GATE: f64 = -10.0;
+/// The loudness of the quietest short-term blocks that count towards the loudness range,
+/// relative to the loudness of the blocks above the absolute gate, in LU.
+const RANGE_RELATIVE_GATE: f64 = -20.0;
+/// The number of 100 ms steps a momentary gating block spans, for a 400 ms block.
+const MOMENTARY_STEPS: usize = 4;
+/// The number of 100 ms steps a short-term block spans, for a 3 s block.
+const SHORT_TERM_STEPS: usize = 30;
+
+/// The coefficients of the polyphase FIR filter that oversamples signals by four to
+/// estimate their true peak, as defined by ITU-R BS.1770-4 Annex 2.
+const TRUE_PEAK_FILTER: [[f32; 12]; 4] = [
+	[
+		0.001_708_984_4,
+		0.010_986_328,
+		-0.019_653_32,
+		0.033_203_125,
+		-0.059_448_242,
+		0.137_329_1,
+		0.972_167_97,
+		-0.102_294_92,
+		0.047_607_422,
+		-0.026_611_328,
+		0.014_892_578,
+		-0.008_300_781
+	],
+	[
+		-0.029_174_805,
+		0.029_296_875,
+		-0.051_757_812,
+		0.089_111_33,
+		-0.166_503_9,
+		0.465_087_9,
+		0.779_785_16,
+		-0.200_317_38,
+		0.101_562_5,
+		-0.058_227_54,
+		0.033_081_055,
+		-0.018_920_898
+	],
+	[
+		-0.018_920_898,
+		0.033_081_055,
+		-0.058_227_54,
+		0.101_562_5,
+		-0.200_317_38,
+		0.779_785_16,
+		0.465_087_9,
+		-0.166_503_9,
+		0.089_111_33,
+		-0.051_757_812,
+		0.029_296_875,
+		-0.029_174_805
+	],
+	[
+		-0.008_300_781,
+		0.014_892_578,
+		-0.026_611_328,
+		0.047_607_422,
+		-0.102_294_92,
+		0.972_167_97,
+		0.137_329_1,
+		-0.059_448_242,
+		0.033_203_125,
+		-0.019_653_32,
+		0.010_986_328,
+		0.001_708_984_4
+	]
+];
+
+/// A biquad IIR filter in transposed direct form II, with double-precision state.
+#[derive(Clone)]
+struct Biquad {
+	b: [f64; 3],
+	a: [f64; 2],
+	state: [f64; 2]
+}
+
+impl Biquad {
+	/// Filters the specified sample, updating the filter state.
+	fn process(&mut self, sample: f64) -> f64 {
+		let output = self.b[0] * sample + self.state[0];
+		self.state[0] = self.b[1] * sample - self.a[0] * output + self.state[1];
+		self.state[1] = self.b[2] * sample - self.a[1] * output;
+		output
+	}
+}
+
+/// The per-channel state of a [`VorbisLoudnessMeter`].
+struct ChannelState {
+	/// The K-weighting filters: a high shelf and a high pass filter.
+	filters: [Biquad; 2],
+	weight: f64,
+	/// The last samples of the channel, most recent first, for true peak oversampling.
+	history: [f32; 12]
+}
+
+/// Measures the loudness of an audio signal as defined by ITU-R BS.1770-4 and [EBU R128]:
+/// its integrated loudness, loudness range and true peak. This is useful to check whether
+/// a signal complies with a loudness target, or to normalize it.
+///
+/// Meters can measure signals from any source, such as the audio blocks returned by a
+/// [`VorbisDecoder`](crate::VorbisDecoder) or submitted to a
+/// [`VorbisEncoder`](crate::VorbisEncoder). Signals with an application-defined channel
+/// layout are measured as if every channel was a front channel.
+///
+/// [EBU R128]: https://tech.ebu.ch/publications/r128
+pub struct VorbisLoudnessMeter {
+	channels: Vec<ChannelState>,
+	step_samples: usize,
+	step_position: usize,
+	step_energy: f64,
+	/// The energies of the last 100 ms steps, most recent first.
+	step_energies: VecDeque<f64>,
+	momentary_energies: Vec<f64>,
+	short_term_energies: Vec<f64>,
+	sample_peak: f32,
+	true_peak: f32
+}
+
+impl VorbisLoudnessMeter {
+	/// Creates a new loudness meter for a signal with the specified sampling frequency,
+	/// channel layout and channel order. The channel layout and order are used to weight
+	/// each channel as defined by ITU-R BS.1770-4, where surround channels are louder and
+	/// the LFE channel is ignored.
+	pub fn new(
+		sampling_frequency: NonZeroU32,
+		channel_layout: VorbisChannelLayout,
+		channel_order: VorbisChannelOrder
+	) -> Self {
+		let sampling_frequency = f64::from(sampling_frequency.get());
+
+		// The filter coefficients are derived for the sampling frequency with the bilinear
+		// transform of the analog prototypes, as done by libebur128
+		let k = (PI * 1_681.974_450_955_533 / sampling_frequency).tan();
+		let q = 0.707_175_236_955_419_6;
+		let vh = 10.0_f64.powf(3.999_843_853_973_347 / 20.0);
+		let vb = vh.powf(0.499_666_774_154_541_6);
+		let a0 = 1.0 + k / q + k * k;
+		let high_shelf = Biquad {
+			b: [
+				(vh + vb * k / q + k * k) / a0,
+				2.0 * (k * k - vh) / a0,
+				(vh - vb * k / q + k * k) / a0
+			],
+			a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+			state: [0.0; 2]
+		};
+
+		let k = (PI * 38.135_470_876_024_44 / sampling_frequency).tan();
+		let q = 0.500_327_037_323_877_3;
+		let a0 = 1.0 + k / q + k * k;
+		let high_pass = Biquad {
+			b: [1.0, -2.0, 1.0],
+			a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+			state: [0.0; 2]
+		};
+
+		let channel_count = channel_layout.channel_count().get() as usize;
+		let channel_weights = channel_layout.channels(channel_order).map_or_else(
+			|| vec![1.0; channel_count],
+			|channels| {
+				channels
+					.into_iter()
+					.map(|channel| match channel {
+						VorbisChannel::SideLeft
+						| VorbisChannel::SideRight
+						| VorbisChannel::RearLeft
+						| VorbisChannel::RearRight => 1.41,
+						VorbisChannel::LowFrequencyEffects => 0.0,
+						_ => 1.0
+					})
+					.collect()
+			}
+		);
+
+		Self {
+			channels: channel_weights
+				.into_iter()
+				.map(|weight| ChannelState {
+					filters: [high_shelf.clone(), high_pass.clone()],
+					weight,
+					history: [0.0; 12]
+				})
+				.collect(),
+			step_samples: ((sampling_frequency / 10.0).round() as usize).max(1),
+			step_position: 0,
+			step_energy: 0.0,
+			step_energies: VecDeque::with_capacity(SHORT_TERM_STEPS),
+			momentary_energies: vec![],
+			short_term_energies: vec![],
+			sample_peak: 0.0,
+			true_peak: 0.0
+		}
+	}
+
+	/// Measures the specified audio block, which is expected to be in planar format (i.e.,
+	/// one vector of samples per channel), with its channels in the order this meter was
+	/// created with.
+	pub fn process<B: AsRef<[S]>, S: AsRef<[f32]>>(
+		&mut self,
+		audio_block: B
+	) -> Result<(), VorbisError> {
+		let audio_block = audio_block.as_ref();
+
+		if audio_block.len() != self.channels.len() {
+			return Err(VorbisError::InvalidAudioBlockChannelCount {
+				expected: self.channels.len(),
+				actual: audio_block.len()
+			});
+		}
+
+		let sample_count = audio_block
+			.first()
+			.map_or(0, |samples| samples.as_ref().len());
+		if let Some(samples) = audio_block
+			.iter()
+			.find(|samples| samples.as_ref().len() != sample_count)
+		{
+			return Err(VorbisError::InvalidAudioBlockSampleCount {
+				expected: sample_count,
+				actual: samples.as_ref().len()
+			});
+		}
+
+		for i in 0..sample_count {
+			for (samples, channel) in audio_block.iter().zip(&mut self.channels) {
+				let sample = samples.as_ref()[i];
+				self.sample_peak = self.sample_peak.max(sample.abs());
+
+				channel.history.copy_within(..11, 1);
+				channel.history[0] = sample;
+				for phase_coefficients in &TRUE_PEAK_FILTER {
+					let interpolated_sample = phase_coefficients
+						.iter()
+						.zip(&channel.history)
+						.map(|(coefficient, sample)| coefficient * sample)
+						.sum::<f32>();
+					self.true_peak = self.true_peak.max(interpolated_sample.abs());
+				}
+
+				let [high_shelf, high_pass] = &mut channel.filters;
+				let filtered_sample = high_pass.process(high_shelf.process(sample.into()));
+				self.step_energy += channel.weight * filtered_sample * filtered_sample;
+			}
+
+			self.step_position += 1;
+			if self.step_position == self.step_samples {
+				self.end_step();
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Returns the integrated loudness of the signal measured so far, in LUFS, or `None` if
+	/// the signal is too short or too quiet to be measured.
+	pub fn integrated_loudness(&self) -> Option<f64> {
+		gated_mean(
+			&self.momentary_energies,
+			relative_gate(&self.momentary_energies, INTEGRATED_RELATIVE_GATE)?
+		)
+		.map(loudness)
+	}
+
+	/// Returns the loudness range of the signal measured so far, in LU, as defined by EBU
+	/// Tech 3342, or `None` if the signal is too short or too quiet to be measured. This
+	/// quantifies the variation of the loudness of the signal over time.
+	pub fn loudness_range(&self) -> Option<f64> {
+		let gate = relative_gate(&self.short_term_energies, RANGE_RELATIVE_GATE)?;
+
+		let mut short_term_loudnesses = self
+			.short_term_energies
+			.iter()
+			.filter(|short_term_energy| **short_term_energy > gate)
+			.map(|short_term_energy| loudness(*short_term_energy))
+			.collect::<Vec<_>>();
+		short_term_loudnesses.sort_by(f64::total_cmp);
+
+		let percentile = |percentile: f64| {
+			short_term_loudnesses
+				[((short_term_loudnesses.len() - 1) as f64 * percentile).round() as usize]
+		};
+		Some(percentile(0.95) - percentile(0.1))
+	}
+
+	/// Returns the maximum absolute value of the signal measured so far, including the
+	/// peaks between samples, estimated by oversampling it by four. Full scale is 1.
+	pub fn true_peak(&self) -> f32 {
+		self.true_peak.max(self.sample_peak)
+	}
+
+	/// Returns the maximum absolute sample value of the signal measured so far. Full scale
+	/// is 1.
+	pub fn sample_peak(&self) -> f32 {
+		self.sample_peak
+	}
+
+	/// Ends the current 100 ms step, computing the energy of the blocks that end with it.
+	fn end_step(&mut self) {
+		if self.step_energies.len() == SHORT_TERM_STEPS {
+			self.step_energies.pop_back();
+		}
+		self.step_energies.push_front(self.step_energy);
+		self.step_energy = 0.0;
+		self.step_position = 0;
+
+		let block_energy = |steps: usize| {
+			(self.step_energies.len() >= steps).then(|| {
+				self.step_energies.range(..steps).sum::<f64>() / (steps * self.step_samples) as f64
+			})
+		};
+		let momentary_energy = block_energy(MOMENTARY_STEPS);
+		let short_term_energy = block_energy(SHORT_TERM_STEPS);
+
+		self.momentary_energies.extend(momentary_energy);
+		self.short_term_energies.extend(short_term_energy);
+	}
+}
+
+/// Converts a mean square energy to a loudness, in LUFS.
+fn loudness(energy: f64) -> f64 {
+	-0.691 + 10.0 * energy.log10()
+}
+
+/// Converts a loudness, in LUFS, to a mean square energy.
+fn energy(loudness: f64) -> f64 {
+	10.0_f64.powf((loudness + 0.691) / 10.0)
+}
+
+/// Returns the energy of the quietest blocks that pass both the absolute gate and the
+/// specified relative gate, or `None` if no block passes the absolute gate.
+fn relative_gate(block_energies: &[f64], relative_gate: f64) -> Option<f64> {
+	let absolute_gate = energy(ABSOLUTE_GATE);
+	let absolute_gated_energy = gated_mean(block_energies, absolute_gate)?;
+
+	Some(energy(loudness(absolute_gated_energy) + relative_gate).max(absolute_gate))
+}
+
+/// Returns the mean of the block energies above the specified gate energy, or `None` if
+/// no block is above it.
+fn gated_mean(block_energies: &[f64], gate_energy: f64) -> Option<f64> {
+	let (sum, count) = block_energies
+		.iter()
+		.filter(|block_energy| **block_energy > gate_energy)
+		.fold((0.0, 0_usize), |(sum, count), block_energy| {
+			(sum + block_energy, count + 1)
+		});
+
+	(count > 0).then(|| sum / count as f64)
+}
+
+#[cfg(test)]
+mod test {
+	use std::{f32::consts::TAU, num::NonZeroU32};
+
+	use super::VorbisLoudnessMeter;
+	use crate::{VorbisChannelLayout, VorbisChannelOrder, common::test_util::sine_wave};
+
+	fn stereo_meter() -> VorbisLoudnessMeter {
+		VorbisLoudnessMeter::new(
+			NonZeroU32::new(48000).unwrap(),
+			VorbisChannelLayout::Stereo,
+			VorbisChannelOrder::Vorbis
+		)
+	}
+
+	#[test]
+	fn loudness_meter_measures_reference_sine() {
+		// EBU Tech 3341 states that a 1 kHz sine at -18 dBFS in both stereo channels
+		// must measure -18 LUFS, with a tolerance of 0.1 LU
+		let amplitude = 10.0_f32.powf(-18.0 / 20.0);
+		let samples = sine_wave(48000 * 20, 48000, 1000.0, amplitude, 0.0);
+
+		let mut meter = stereo_meter();
+		meter.process([&samples, &samples]).unwrap();
+
+		assert!((meter.integrated_loudness().unwrap() + 18.0).abs() < 0.1);
+		assert!((meter.sample_peak() - amplitude).abs() < 1e-3);
+	}
+
+	#[test]
+	fn loudness_meter_measures_range_and_true_peak() {
+		// EBU Tech 3342 states that 20 s of a 1 kHz sine at -20 dBFS followed by 20 s at
+		// -30 dBFS must measure a loudness range of 10 LU, with a tolerance of 1 LU
+		let mut meter = stereo_meter();
+		for level in [-20.0, -30.0] {
+			let samples = sine_wave(48000 * 20, 48000, 1000.0, 10.0_f32.powf(level / 20.0), 0.0);
+			meter.process([&samples, &samples]).unwrap();
+		}
+		assert!((meter.loudness_range().unwrap() - 10.0).abs() < 1.0);
+
+		// A sine at a quarter of the sampling frequency, shifted by 45 degrees, has samples
+		// at 0.707 times its amplitude, but its true peak is its amplitude
+		let mut meter = stereo_meter();
+		let samples = sine_wave(48000, 48000, 12000.0, 0.5, TAU / 8.0);
+		meter.process([&samples, &samples]).unwrap();
+		assert!(meter.sample_peak() < 0.36);
+		assert!((meter.true_peak() - 0.5).abs() < 0.03);
+	}
+}
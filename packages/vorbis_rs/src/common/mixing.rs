@@ -0,0 +1,345 @@
+use std::{f32::consts::FRAC_1_SQRT_2, num::NonZeroU8};
+
+use crate::{VorbisChannel, VorbisChannelLayout, VorbisChannelOrder, VorbisError};
+
+/// The gain applied to the front center channel when folding it into the front left and
+/// right channels, as recommended by ITU-R BS.775.
+const CENTER_MIX_GAIN: f32 = FRAC_1_SQRT_2;
+/// The gain applied to surround channels when folding them into the front left and right
+/// channels, as recommended by ITU-R BS.775.
+const SURROUND_MIX_GAIN: f32 = FRAC_1_SQRT_2;
+
+/// A matrix of gain coefficients that converts an audio signal with some number of channels
+/// to a signal with a possibly different number of channels, by computing each output
+/// channel as a weighted sum of the input channels. This is useful to downmix multichannel
+/// signals to stereo or mono, or to upmix signals with fewer channels.
+///
+/// Both input and output channels are indexed in the Vorbis channel order, regardless of
+/// the [channel order](VorbisChannelOrder) a decoder or encoder uses for its audio blocks.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VorbisChannelMatrix {
+	input_channels: NonZeroU8,
+	output_channels: NonZeroU8,
+	/// The coefficients of the matrix, in row-major order: each row contains the gains
+	/// of every input channel for an output channel.
+	coefficients: Vec<f32>
+}
+
+impl VorbisChannelMatrix {
+	/// Creates a matrix that converts a signal with the specified input layout to the
+	/// specified output layout, using the speaker position of each channel as defined by
+	/// the Vorbis I specification:
+	///
+	/// - Input channels with a matching speaker position in the output layout are copied
+	///   unchanged, unless the output layout is mono.
+	/// - A mono input channel is copied to both front left and right output channels when
+	///   the output layout has no front center channel, upmixing mono to stereo.
+	/// - Other input channels are folded into the front left and right output channels with
+	///   the ITU-R BS.775 downmix coefficients: -3 dB for the front center and surround
+	///   channels, and -6 dB for the rear center channel. The LFE channel is discarded.
+	/// - When downmixing to mono, every input channel is folded into stereo first, and then
+	///   both stereo channels are averaged, so stereo signals are downmixed to mono as
+	///   `(L + R) / 2`, and the front center channel gets the same -3 dB gain as when
+	///   downmixing to stereo.
+	///
+	/// The resulting signal may exceed the amplitude range of the input signal when
+	/// downmixing loud signals. Use [`normalize`](Self::normalize) to prevent clipping if
+	/// that is a concern.
+	///
+	/// An error is returned if either layout is application-defined, as the speaker
+	/// positions of its channels are unknown. Use
+	/// [`from_coefficients`](Self::from_coefficients) to convert such signals.
+	pub fn new(
+		input_layout: VorbisChannelLayout,
+		output_layout: VorbisChannelLayout
+	) -> Result<Self, VorbisError> {
+		let (Some(input_layout_channels), Some(output_layout_channels)) = (
+			input_layout.channels(VorbisChannelOrder::Vorbis),
+			output_layout.channels(VorbisChannelOrder::Vorbis)
+		) else {
+			return Err(VorbisError::UnsupportedChannelConversion {
+				from: input_layout,
+				to: output_layout
+			});
+		};
+
+		let mut matrix = Self {
+			input_channels: input_layout.channel_count(),
+			output_channels: output_layout.channel_count(),
+			coefficients: vec![0.0; input_layout_channels.len() * output_layout_channels.len()]
+		};
+		let output_index = |channel| {
+			output_layout_channels
+				.iter()
+				.position(|output_channel| *output_channel == channel)
+		};
+
+		for (input_index, input_channel) in input_layout_channels.iter().enumerate() {
+			// The front center channel of mono output is a fold-down of every input channel,
+			// so it's not matched with the front center input channel
+			if let Some(output_index) = output_index(*input_channel)
+				&& output_layout != VorbisChannelLayout::Mono
+			{
+				matrix.set_coefficient(output_index, input_index, 1.0);
+				continue;
+			}
+
+			let (left_gain, right_gain) = if input_layout == VorbisChannelLayout::Mono {
+				(1.0, 1.0)
+			} else {
+				stereo_downmix_gains(*input_channel)
+			};
+
+			// Every layout defined by the Vorbis I specification has either a front center
+			// channel, for mono, or front left and right channels
+			if let (Some(left_index), Some(right_index)) = (
+				output_index(VorbisChannel::FrontLeft),
+				output_index(VorbisChannel::FrontRight)
+			) {
+				matrix.set_coefficient(left_index, input_index, left_gain);
+				matrix.set_coefficient(right_index, input_index, right_gain);
+			} else if let Some(center_index) = output_index(VorbisChannel::FrontCenter) {
+				matrix.set_coefficient(center_index, input_index, (left_gain + right_gain) / 2.0);
+			}
+		}
+
+		Ok(matrix)
+	}
+
+	/// Creates a matrix with custom gain coefficients for a signal with the specified
+	/// number of input channels. `coefficients` must contain one row per output channel,
+	/// and each row must contain the gain of every input channel for that output channel.
+	///
+	/// An error is returned if there are no rows, more than 255 rows, or some row does not
+	/// have a coefficient for every input channel.
+	pub fn from_coefficients<R: AsRef<[f32]>>(
+		input_channels: NonZeroU8,
+		coefficients: &[R]
+	) -> Result<Self, VorbisError> {
+		let output_channels = u8::try_from(coefficients.len())
+			.ok()
+			.and_then(NonZeroU8::new)
+			.ok_or(VorbisError::InvalidChannelMatrix)?;
+
+		if coefficients
+			.iter()
+			.any(|row| row.as_ref().len() != input_channels.get() as usize)
+		{
+			return Err(VorbisError::InvalidChannelMatrix);
+		}
+
+		Ok(Self {
+			input_channels,
+			output_channels,
+			coefficients: coefficients
+				.iter()
+				.flat_map(|row| row.as_ref().iter().copied())
+				.collect()
+		})
+	}
+
+	/// Creates a matrix that copies every channel of a signal with the specified number of
+	/// channels unchanged.
+	pub(crate) fn identity(channels: NonZeroU8) -> Self {
+		let channel_count = channels.get() as usize;
+		let mut matrix = Self {
+			input_channels: channels,
+			output_channels: channels,
+			coefficients: vec![0.0; channel_count * channel_count]
+		};
+
+		for i in 0..channel_count {
+			matrix.set_coefficient(i, i, 1.0);
+		}
+
+		matrix
+	}
+
+	/// Multiplies every coefficient of this matrix by the specified linear gain.
+	pub(crate) fn scale(&mut self, gain: f32) {
+		for coefficient in &mut self.coefficients {
+			*coefficient *= gain;
+		}
+	}
+
+	/// Scales every coefficient of this matrix by the same factor, so that no output channel
+	/// can exceed the peak amplitude of the input channels, preventing clipping. Matrices
+	/// that can't increase the amplitude of any output channel are not changed.
+	pub fn normalize(&mut self) {
+		let maximum_gain = self
+			.coefficients
+			.chunks(self.input_channels.get() as usize)
+			.map(|row| row.iter().map(|coefficient| coefficient.abs()).sum::<f32>())
+			.fold(0.0, f32::max);
+
+		if maximum_gain > 1.0 {
+			for coefficient in &mut self.coefficients {
+				*coefficient /= maximum_gain;
+			}
+		}
+	}
+
+	/// Returns the number of channels of the signals this matrix converts.
+	pub fn input_channels(&self) -> NonZeroU8 {
+		self.input_channels
+	}
+
+	/// Returns the number of channels of the signals this matrix converts to.
+	pub fn output_channels(&self) -> NonZeroU8 {
+		self.output_channels
+	}
+
+	/// Returns the layout of the channels of the signals this matrix converts.
+	pub fn input_layout(&self) -> VorbisChannelLayout {
+		VorbisChannelLayout::from_channel_count(self.input_channels)
+	}
+
+	/// Returns the layout of the channels of the signals this matrix converts to.
+	pub fn output_layout(&self) -> VorbisChannelLayout {
+		VorbisChannelLayout::from_channel_count(self.output_channels)
+	}
+
+	/// Returns the gain of the input channel at the specified index for the output channel
+	/// at the specified index, or `None` if either index is out of bounds.
+	pub fn coefficient(&self, output_index: usize, input_index: usize) -> Option<f32> {
+		if output_index >= self.output_channels.get() as usize
+			|| input_index >= self.input_channels.get() as usize
+		{
+			return None;
+		}
+
+		Some(self.coefficients[output_index * self.input_channels.get() as usize + input_index])
+	}
+
+	/// Sets the gain of the input channel at the specified index for the output channel at
+	/// the specified index. Both indices must be in bounds.
+	fn set_coefficient(&mut self, output_index: usize, input_index: usize, coefficient: f32) {
+		self.coefficients[output_index * self.input_channels.get() as usize + input_index] =
+			coefficient;
+	}
+}
+
+/// Returns the gains of the specified input channel for the left and right channels of
+/// a stereo downmix, according to ITU-R BS.775.
+fn stereo_downmix_gains(channel: VorbisChannel) -> (f32, f32) {
+	use VorbisChannel::*;
+
+	match channel {
+		FrontLeft => (1.0, 0.0),
+		FrontRight => (0.0, 1.0),
+		FrontCenter => (CENTER_MIX_GAIN, CENTER_MIX_GAIN),
+		SideLeft | RearLeft => (SURROUND_MIX_GAIN, 0.0),
+		SideRight | RearRight => (0.0, SURROUND_MIX_GAIN),
+		RearCenter => (
+			SURROUND_MIX_GAIN * FRAC_1_SQRT_2,
+			SURROUND_MIX_GAIN * FRAC_1_SQRT_2
+		),
+		LowFrequencyEffects => (0.0, 0.0)
+	}
+}
+
+/// Applies a [`VorbisChannelMatrix`] to planar audio blocks, whose channels are in the
+/// Vorbis channel order, holding the converted samples in a reusable buffer.
+pub(crate) struct ChannelMixer {
+	matrix: VorbisChannelMatrix,
+	output_buffer: Vec<Vec<f32>>
+}
+
+impl ChannelMixer {
+	/// Creates a new mixer that applies the specified matrix.
+	pub fn new(matrix: VorbisChannelMatrix) -> Self {
+		Self {
+			output_buffer: vec![vec![]; matrix.output_channels.get() as usize],
+			matrix
+		}
+	}
+
+	/// Returns the matrix this mixer applies.
+	pub fn matrix(&self) -> &VorbisChannelMatrix {
+		&self.matrix
+	}
+
+	/// Converts the specified planar block of samples, which must have as many channels as
+	/// the matrix inputs, returning the converted samples.
+	pub fn process<S: AsRef<[f32]>>(&mut self, block: &[S]) -> &[Vec<f32>] {
+		let sample_count = block.first().map_or(0, |channel| channel.as_ref().len());
+		let input_channels = self.matrix.input_channels.get() as usize;
+
+		for (output_channel, gains) in self
+			.output_buffer
+			.iter_mut()
+			.zip(self.matrix.coefficients.chunks(input_channels))
+		{
+			output_channel.clear();
+			output_channel.resize(sample_count, 0.0);
+
+			for (input_channel, gain) in block.iter().zip(gains) {
+				if *gain == 0.0 {
+					continue;
+				}
+
+				for (output_sample, input_sample) in
+					output_channel.iter_mut().zip(input_channel.as_ref())
+				{
+					*output_sample += gain * input_sample;
+				}
+			}
+		}
+
+		&self.output_buffer
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::{f32::consts::FRAC_1_SQRT_2, num::NonZeroU8};
+
+	use super::{ChannelMixer, VorbisChannelMatrix};
+	use crate::VorbisChannelLayout;
+
+	#[test]
+	fn channel_matrices_downmix_and_upmix() {
+		let mut mixer = ChannelMixer::new(
+			VorbisChannelMatrix::new(
+				VorbisChannelLayout::FivePointOne,
+				VorbisChannelLayout::Stereo
+			)
+			.unwrap()
+		);
+		let five_point_one_block = [[1.0], [0.5], [0.25], [0.5], [0.25], [1.0]];
+
+		let stereo_block = mixer.process(&five_point_one_block);
+		assert_eq!(stereo_block.len(), 2);
+		assert!((stereo_block[0][0] - (1.0 + FRAC_1_SQRT_2)).abs() < 1e-6);
+		assert!((stereo_block[1][0] - (0.25 + 0.75 * FRAC_1_SQRT_2)).abs() < 1e-6);
+
+		let mut mixer = ChannelMixer::new(
+			VorbisChannelMatrix::new(VorbisChannelLayout::Stereo, VorbisChannelLayout::Mono)
+				.unwrap()
+		);
+		assert_eq!(mixer.process(&[[1.0], [0.5]]), [[0.75]]);
+
+		let mut mixer = ChannelMixer::new(
+			VorbisChannelMatrix::new(VorbisChannelLayout::FivePointOne, VorbisChannelLayout::Mono)
+				.unwrap()
+		);
+		let mono_block = mixer.process(&five_point_one_block);
+		assert_eq!(mono_block.len(), 1);
+		assert!((mono_block[0][0] - (1.25 + 1.75 * FRAC_1_SQRT_2) / 2.0).abs() < 1e-6);
+
+		let mut mixer = ChannelMixer::new(
+			VorbisChannelMatrix::new(VorbisChannelLayout::Mono, VorbisChannelLayout::Stereo)
+				.unwrap()
+		);
+		assert_eq!(mixer.process(&[[0.5]]), [[0.5], [0.5]]);
+
+		let mut matrix =
+			VorbisChannelMatrix::from_coefficients(NonZeroU8::new(2).unwrap(), &[[1.0, 1.0]])
+				.unwrap();
+		matrix.normalize();
+		assert_eq!(matrix.coefficient(0, 1), Some(0.5));
+		assert!(
+			VorbisChannelMatrix::from_coefficients(NonZeroU8::new(2).unwrap(), &[[1.0]]).is_err()
+		);
+	}
+}
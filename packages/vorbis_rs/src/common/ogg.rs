@@ -1,15 +1,25 @@
-use std::{io::Write, mem::MaybeUninit, slice};
+use std::{
+	io::{ErrorKind, Read, Write},
+	marker::PhantomData,
+	mem::MaybeUninit,
+	slice
+};
 
 use ogg_next_sys::{
-	ogg_packet, ogg_page, ogg_stream_clear, ogg_stream_flush, ogg_stream_init, ogg_stream_packetin,
-	ogg_stream_pageout, ogg_stream_pageout_fill, ogg_stream_state
+	ogg_packet, ogg_page, ogg_page_bos, ogg_page_granulepos, ogg_page_serialno, ogg_stream_clear,
+	ogg_stream_flush, ogg_stream_init, ogg_stream_packetin, ogg_stream_packetout,
+	ogg_stream_pagein, ogg_stream_pageout, ogg_stream_pageout_fill, ogg_stream_reset,
+	ogg_stream_state, ogg_sync_buffer, ogg_sync_clear, ogg_sync_init, ogg_sync_pageout,
+	ogg_sync_pageseek, ogg_sync_reset, ogg_sync_state, ogg_sync_wrote
 };
 
-use crate::VorbisError;
+use crate::{VorbisError, VorbisLibrary, VorbisLibraryError, VorbisLibraryErrorKind};
 
 /// A high-level abstraction for an Ogg stream.
 pub(crate) struct OggStream {
-	ogg_stream: ogg_stream_state
+	ogg_stream: ogg_stream_state,
+	written_granule_position: i64,
+	written_bytes: u64
 }
 
 impl OggStream {
@@ -27,7 +37,9 @@ impl OggStream {
 			))?;
 
 			Ok(Self {
-				ogg_stream: ogg_stream.assume_init()
+				ogg_stream: ogg_stream.assume_init(),
+				written_granule_position: 0,
+				written_bytes: 0
 			})
 		}
 	}
@@ -64,10 +76,7 @@ impl OggStream {
 					break;
 				}
 
-				OggPage {
-					ogg_page: ogg_page.assume_init()
-				}
-				.write(&mut sink)?;
+				self.write_page(&OggPage::new(ogg_page.assume_init()), &mut sink)?;
 			}
 		}
 
@@ -84,17 +93,102 @@ impl OggStream {
 		// SAFETY: we assume ogg_stream_flush follows its documented contract
 		unsafe {
 			while ogg_stream_flush(&mut self.ogg_stream, ogg_page.as_mut_ptr()) != 0 {
-				OggPage {
-					ogg_page: ogg_page.assume_init()
-				}
-				.write(&mut sink)?;
+				self.write_page(&OggPage::new(ogg_page.assume_init()), &mut sink)?;
 			}
 		}
 
 		Ok(())
 	}
+
+	/// Returns the granule position of the last page written by this stream that had one,
+	/// or zero if no such page was written yet.
+	pub fn written_granule_position(&self) -> i64 {
+		self.written_granule_position
+	}
+
+	/// Returns the number of bytes of the pages written by this stream.
+	pub fn written_bytes(&self) -> u64 {
+		self.written_bytes
+	}
+
+	/// Writes the specified page of this stream to the specified sink, keeping track of
+	/// its granule position and size.
+	fn write_page<W: Write>(&mut self, ogg_page: &OggPage<'_>, sink: W) -> Result<(), VorbisError> {
+		ogg_page.write(sink)?;
+		self.written_bytes += (ogg_page.header().len() + ogg_page.body().len()) as u64;
+
+		// Pages on which no packet ends have a granule position of -1
+		let granule_position = ogg_page.granule_position();
+		if granule_position != -1 {
+			self.written_granule_position = granule_position;
+		}
+
+		Ok(())
+	}
+
+	/// Submits the specified Ogg page, which was read from some physical stream, to this
+	/// Ogg stream, so that its packets can be retrieved with [`packet_out`](Self::packet_out).
+	/// Pages that do not belong to this stream are rejected with an error.
+	///
+	/// This is a facade for the `ogg_stream_pagein` function.
+	pub fn page_in(&mut self, ogg_page: &OggPage<'_>) -> Result<(), VorbisError> {
+		let mut ogg_page = ogg_page.ogg_page;
+
+		// SAFETY: we assume ogg_stream_pagein follows its documented contract. It
+		// copies the page data, and does not write to the page
+		unsafe {
+			libogg_return_value_to_result!(ogg_stream_pagein(&mut self.ogg_stream, &mut ogg_page))?;
+		}
+
+		Ok(())
+	}
+
+	/// Retrieves the next complete packet from the pages submitted to this stream with
+	/// [`page_in`](Self::page_in). `Ok(None)` is returned when more pages are needed to
+	/// assemble a packet. A hole in the data, caused by lost or corrupt pages, is reported
+	/// as an error with the [`VorbisLibraryErrorKind::Hole`] kind, after which more packets
+	/// may be retrieved.
+	///
+	/// This is a facade for the `ogg_stream_packetout` function.
+	pub fn packet_out(&mut self) -> Result<Option<OggPacket<'_>>, VorbisError> {
+		let mut ogg_packet = MaybeUninit::uninit();
+
+		// SAFETY: we assume ogg_stream_packetout follows its documented contract. The
+		// packet data is owned by the stream, and stays valid until the next call to
+		// this method or page_in, which the returned packet lifetime enforces
+		unsafe {
+			match ogg_stream_packetout(&mut self.ogg_stream, ogg_packet.as_mut_ptr()) {
+				1 => Ok(Some(OggPacket::new(ogg_packet.assume_init()))),
+				0 => Ok(None),
+				_ => Err(VorbisLibraryError {
+					library: VorbisLibrary::Ogg,
+					function: "ogg_stream_packetout",
+					kind: VorbisLibraryErrorKind::Hole
+				}
+				.into())
+			}
+		}
+	}
+
+	/// Discards any pages and packets submitted to this stream, keeping its serial, so
+	/// that unrelated pages can be submitted next.
+	///
+	/// This is a facade for the `ogg_stream_reset` function.
+	pub fn reset(&mut self) {
+		// SAFETY: we assume ogg_stream_reset follows its documented contract. It always
+		// succeeds for initialized streams
+		unsafe { ogg_stream_reset(&mut self.ogg_stream) };
+		self.written_granule_position = 0;
+		self.written_bytes = 0;
+	}
 }
 
+// SAFETY: the buffers the Ogg stream state points to are owned by it, and libogg keeps
+// no thread-local or global mutable state. All methods that access the state take a
+// mutable reference
+unsafe impl Send for OggStream {}
+unsafe impl Sync for OggStream {}
+
 impl Drop for OggStream {
 	fn drop(&mut self) {
 		// SAFETY: when this struct is dropped we have a valid Ogg stream to clear,
@@ -103,15 +197,244 @@ impl Drop for OggStream {
 	}
 }
 
-/// A high-level abstraction for an Ogg packet.
-pub(crate) struct OggPacket {
-	ogg_packet: ogg_packet
+/// A high-level abstraction for an Ogg synchronization state, which splits a physical
+/// Ogg bitstream read from some source into pages.
+pub(crate) struct OggSync {
+	ogg_sync: ogg_sync_state
+}
+
+impl OggSync {
+	/// The size of the chunks of data read from sources at once.
+	const READ_CHUNK_SIZE: usize = 8192;
+
+	/// Creates a new Ogg synchronization state, ready to read data.
+	pub fn new() -> Self {
+		let mut ogg_sync = MaybeUninit::uninit();
+
+		// SAFETY: we assume ogg_sync_init follows its documented contract. It
+		// always succeeds
+		unsafe {
+			ogg_sync_init(ogg_sync.as_mut_ptr());
+
+			Self {
+				ogg_sync: ogg_sync.assume_init()
+			}
+		}
+	}
+
+	/// Reads the next Ogg page from the specified source, which is expected to yield
+	/// the same physical bitstream on every call. Any garbage data between pages is
+	/// skipped. `Ok(None)` is returned when the source reaches its end; any incomplete
+	/// page data at that point is discarded.
+	///
+	/// This is a facade for the `ogg_sync_pageout` function.
+	pub fn read_page<R: Read>(
+		&mut self,
+		mut source: R
+	) -> Result<Option<OggPage<'_>>, VorbisError> {
+		let mut ogg_page = MaybeUninit::uninit();
+
+		loop {
+			// SAFETY: we assume ogg_sync_pageout follows its documented contract
+			match unsafe { ogg_sync_pageout(&mut self.ogg_sync, ogg_page.as_mut_ptr()) } {
+				1 => break,
+				// More data is needed to complete a page, which may not be available
+				0 if self.read_chunk(&mut source)? == 0 => return Ok(None),
+				// Some garbage data was skipped. Try again
+				_ => {}
+			}
+		}
+
+		// SAFETY: the page was initialized by ogg_sync_pageout, and its data is owned
+		// by this sync state until the next read_page call, which the lifetime of
+		// the returned page enforces
+		Ok(Some(OggPage::new(unsafe { ogg_page.assume_init() })))
+	}
+
+	/// Submits the specified data to this sync state, to be split into pages by
+	/// [`seek_page`](Self::seek_page).
+	pub fn write(&mut self, mut data: &[u8]) -> Result<(), VorbisError> {
+		while self.read_chunk(&mut data)? > 0 {}
+
+		Ok(())
+	}
+
+	/// Looks for the next page in the data submitted to this sync state with
+	/// [`write`](Self::write), reporting any data that was skipped instead, such as
+	/// pages that fail their CRC check or garbage data between pages.
+	///
+	/// This is a facade for the `ogg_sync_pageseek` function.
+	pub fn seek_page(&mut self) -> OggPageSeek<'_> {
+		// SAFETY: the sync state buffer is initialized up to its fill position, and the
+		// data before its returned position was already consumed
+		let capture_pattern = !self.ogg_sync.data.is_null()
+			&& unsafe {
+				slice::from_raw_parts(
+					self.ogg_sync.data.add(self.ogg_sync.returned as usize),
+					(self.ogg_sync.fill - self.ogg_sync.returned) as usize
+				)
+			}
+			.starts_with(b"OggS");
+
+		let mut ogg_page = MaybeUninit::uninit();
+
+		// SAFETY: we assume ogg_sync_pageseek follows its documented contract. The page
+		// is only initialized when a page is found, and its data is owned by this sync
+		// state until the next call, which the lifetime of the returned page enforces
+		match unsafe { ogg_sync_pageseek(&mut self.ogg_sync, ogg_page.as_mut_ptr()) } {
+			0 => OggPageSeek::NeedData,
+			1.. => OggPageSeek::Page(OggPage::new(unsafe { ogg_page.assume_init() })),
+			skipped_bytes => OggPageSeek::Skipped {
+				bytes: skipped_bytes.unsigned_abs() as usize,
+				capture_pattern
+			}
+		}
+	}
+
+	/// Discards any data submitted to this sync state, so that unrelated data can be
+	/// submitted next.
+	///
+	/// This is a facade for the `ogg_sync_reset` function.
+	pub fn reset(&mut self) {
+		// SAFETY: we assume ogg_sync_reset follows its documented contract. It always
+		// succeeds for initialized sync states
+		unsafe { ogg_sync_reset(&mut self.ogg_sync) };
+	}
+
+	/// Returns the number of bytes submitted to this sync state that were neither returned
+	/// as part of a page nor skipped yet, such as the data of an incomplete page.
+	pub fn buffered_len(&self) -> usize {
+		(self.ogg_sync.fill - self.ogg_sync.returned) as usize
+	}
+
+	/// Reads a chunk of data from the specified source into the internal buffer of
+	/// this sync state, to be split into pages by [`seek_page`](Self::seek_page),
+	/// returning the number of bytes read. Zero bytes are read if and only if the
+	/// source reached its end.
+	///
+	/// This is a facade for the `ogg_sync_buffer` and `ogg_sync_wrote` functions.
+	pub fn read_chunk<R: Read>(&mut self, mut source: R) -> Result<usize, VorbisError> {
+		// SAFETY: we assume ogg_sync_buffer follows its documented contract, returning
+		// either a null pointer or a pointer to a buffer with at least the requested size
+		let buf = unsafe {
+			let buf = ogg_sync_buffer(&mut self.ogg_sync, Self::READ_CHUNK_SIZE as _);
+			if buf.is_null() {
+				return Err(VorbisLibraryError {
+					library: VorbisLibrary::Ogg,
+					function: "ogg_sync_buffer",
+					kind: VorbisLibraryErrorKind::Other { result_code: -1 }
+				}
+				.into());
+			}
+
+			// The buffer may contain uninitialized memory, so zero it before handing it
+			// to safe Rust code
+			buf.write_bytes(0, Self::READ_CHUNK_SIZE);
+			slice::from_raw_parts_mut(buf.cast::<u8>(), Self::READ_CHUNK_SIZE)
+		};
+
+		let bytes_read = loop {
+			match source.read(buf) {
+				Ok(bytes_read) => break bytes_read,
+				Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+				Err(err) => return Err(err.into())
+			}
+		};
+
+		// SAFETY: we assume ogg_sync_wrote follows its documented contract. The number
+		// of bytes read is bounded by the buffer size, so it can't overflow
+		unsafe {
+			libogg_return_value_to_result!(ogg_sync_wrote(&mut self.ogg_sync, bytes_read as _))?;
+		}
+
+		Ok(bytes_read)
+	}
+}
+
+// SAFETY: the buffer the Ogg sync state points to is owned by it, and libogg keeps no
+// thread-local or global mutable state. All methods that access the state take a
+// mutable reference
+unsafe impl Send for OggSync {}
+unsafe impl Sync for OggSync {}
+
+impl Drop for OggSync {
+	fn drop(&mut self) {
+		// SAFETY: when this struct is dropped we have a valid Ogg sync state to clear,
+		// and there are no references to it
+		unsafe { ogg_sync_clear(&mut self.ogg_sync) };
+	}
+}
+
+/// The outcome of looking for an Ogg page with [`OggSync::seek_page`].
+pub(crate) enum OggPageSeek<'data> {
+	/// A page was found.
+	Page(OggPage<'data>),
+	/// Some bytes were skipped, as they are not the start of a valid page. The capture
+	/// pattern flag tells whether they begin like a page, as pages with CRC failures do.
+	Skipped { bytes: usize, capture_pattern: bool },
+	/// More data is needed to find a page.
+	NeedData
+}
+
+/// A high-level abstraction for an Ogg packet, whose data is borrowed for the
+/// `'data` lifetime.
+pub(crate) struct OggPacket<'data> {
+	ogg_packet: ogg_packet,
+	data: PhantomData<&'data [u8]>
 }
 
-impl OggPacket {
-	/// Creates a new Ogg packet.
+impl<'data> OggPacket<'data> {
+	/// Creates a new Ogg packet. The caller is responsible for choosing a lifetime
+	/// that does not outlive the packet data.
 	pub fn new(ogg_packet: ogg_packet) -> Self {
-		OggPacket { ogg_packet }
+		OggPacket {
+			ogg_packet,
+			data: PhantomData
+		}
+	}
+
+	/// Creates a new Ogg packet that borrows the specified data, with the specified
+	/// granule position and beginning and end of stream flags.
+	pub fn from_data(data: &'data [u8], granule_position: i64, bos: bool, eos: bool) -> Self {
+		Self::new(ogg_packet {
+			// libogg does not write to the packet data, but its API does not
+			// reflect this
+			packet: data.as_ptr().cast_mut(),
+			bytes: data.len() as _,
+			b_o_s: bos.into(),
+			e_o_s: eos.into(),
+			granulepos: granule_position,
+			// libogg does not use this field when submitting packets
+			packetno: 0
+		})
+	}
+
+	/// Returns the data contained in this packet.
+	pub fn data(&self) -> &'data [u8] {
+		if self.ogg_packet.bytes <= 0 {
+			return &[];
+		}
+
+		// SAFETY: the packet data pointer is valid for as many bytes as stated
+		// for the packet lifetime
+		unsafe { slice::from_raw_parts(self.ogg_packet.packet, self.ogg_packet.bytes as usize) }
+	}
+
+	/// Returns the granule position of this packet. This is only known for the last
+	/// packet that ends on a page, being -1 for any other packet.
+	pub fn granule_position(&self) -> i64 {
+		self.ogg_packet.granulepos
+	}
+
+	/// Checks whether this packet is the last of its logical stream.
+	pub fn is_eos(&self) -> bool {
+		self.ogg_packet.e_o_s != 0
+	}
+
+	/// Returns a copy of the underlying low-level packet struct, for passing it to
+	/// C functions.
+	pub fn as_ogg_packet(&self) -> ogg_packet {
+		self.ogg_packet
 	}
 
 	/// Submits this Ogg packet to be written to the specified Ogg stream. The
@@ -133,31 +456,123 @@ impl OggPacket {
 	}
 }
 
-/// A high-level abstraction for an Ogg page.
-struct OggPage {
-	ogg_page: ogg_page
+/// A high-level abstraction for an Ogg page, whose data is borrowed for the
+/// `'data` lifetime.
+pub(crate) struct OggPage<'data> {
+	ogg_page: ogg_page,
+	data: PhantomData<&'data [u8]>
 }
 
-impl OggPage {
-	/// Writes this Ogg page to the specified sink.
-	fn write<W: Write>(&self, mut sink: W) -> Result<(), VorbisError> {
-		// SAFETY: we reinterpret an initialized, aligned C pointer that outlives
-		// this stack frame with a buffer length to a Rust slice for read-only
-		// operations, which is safe. header_len is guaranteed by the Ogg spec
-		// to be at most 252, which can be safely casted to a usize. body_len is
-		// also guaranteed by spec to fit in an unsigned 16 bits integer, and
-		// usize can be assumed to always be at least 16 bits wide
-		unsafe {
-			sink.write_all(slice::from_raw_parts(
-				self.ogg_page.header,
-				self.ogg_page.header_len as usize
-			))?;
-			sink.write_all(slice::from_raw_parts(
-				self.ogg_page.body,
-				self.ogg_page.body_len as usize
-			))?;
+impl OggPage<'_> {
+	/// Creates a new Ogg page. The caller is responsible for choosing a lifetime
+	/// that does not outlive the page data.
+	fn new(ogg_page: ogg_page) -> Self {
+		Self {
+			ogg_page,
+			data: PhantomData
 		}
+	}
+
+	/// Returns the serial of the logical stream this page belongs to.
+	pub fn stream_serial(&self) -> i32 {
+		// SAFETY: we assume ogg_page_serialno follows its documented contract
+		unsafe { ogg_page_serialno(&self.ogg_page) }
+	}
+
+	/// Returns the granule position of this page, which is the granule position of the
+	/// last packet that ends on it, or -1 if no packet ends on it.
+	pub fn granule_position(&self) -> i64 {
+		// SAFETY: we assume ogg_page_granulepos follows its documented contract
+		unsafe { ogg_page_granulepos(&self.ogg_page) }
+	}
+
+	/// Checks whether this page is the first page of its logical stream.
+	pub fn is_bos(&self) -> bool {
+		// SAFETY: we assume ogg_page_bos follows its documented contract
+		unsafe { ogg_page_bos(&self.ogg_page) != 0 }
+	}
+
+	/// Returns the header bytes of this page.
+	pub fn header(&self) -> &[u8] {
+		// SAFETY: the header pointer is valid for as many bytes as stated for the page
+		// lifetime. header_len is guaranteed by the Ogg spec to be at most 282, which
+		// can be safely casted to a usize
+		unsafe { slice::from_raw_parts(self.ogg_page.header, self.ogg_page.header_len as usize) }
+	}
+
+	/// Returns the body bytes of this page.
+	pub fn body(&self) -> &[u8] {
+		// SAFETY: the body pointer is valid for as many bytes as stated for the page
+		// lifetime. body_len is guaranteed by spec to fit in an unsigned 16 bits
+		// integer, and usize can be assumed to always be at least 16 bits wide
+		unsafe { slice::from_raw_parts(self.ogg_page.body, self.ogg_page.body_len as usize) }
+	}
+
+	/// Writes this Ogg page to the specified sink.
+	pub fn write<W: Write>(&self, mut sink: W) -> Result<(), VorbisError> {
+		sink.write_all(self.header())?;
+		sink.write_all(self.body())?;
 
 		Ok(())
 	}
 }
+
+/// Appends the variable-length encoding used by Ogg Skeleton indexes of the specified
+/// number to the specified buffer: groups of 7 bits, from least to most significant, with
+/// the high bit of the last byte set.
+pub(crate) fn write_variable_length(buf: &mut Vec<u8>, mut number: u64) {
+	loop {
+		let byte = (number & 0x7F) as u8;
+		number >>= 7;
+
+		if number == 0 {
+			buf.push(byte | 0x80);
+			break;
+		}
+		buf.push(byte);
+	}
+}
+
+/// Reads a number encoded by [`write_variable_length`] from the beginning of the specified
+/// buffer, advancing it past the number. `None` is returned if the buffer ends before the
+/// number does, or the number does not fit in 64 bits.
+pub(crate) fn read_variable_length(buf: &mut &[u8]) -> Option<u64> {
+	let mut number = 0_u64;
+	for shift in (0..64).step_by(7) {
+		let (&byte, rest) = buf.split_first()?;
+		*buf = rest;
+
+		let bits = u64::from(byte & 0x7F);
+		if bits << shift >> shift != bits {
+			return None;
+		}
+		number |= bits << shift;
+
+		if byte & 0x80 != 0 {
+			return Some(number);
+		}
+	}
+
+	None
+}
+
+#[cfg(test)]
+mod test {
+	use super::{read_variable_length, write_variable_length};
+
+	#[test]
+	fn variable_length_encoding_works() {
+		let mut buf = vec![];
+		for number in [0, 127, 128, 300, u64::MAX] {
+			write_variable_length(&mut buf, number);
+		}
+		assert_eq!(buf[..6], [0x80, 0xFF, 0x00, 0x81, 0x2C, 0x82]);
+
+		let mut encoded = &buf[..];
+		for number in [0, 127, 128, 300, u64::MAX] {
+			assert_eq!(read_variable_length(&mut encoded), Some(number));
+		}
+		assert_eq!(read_variable_length(&mut encoded), None);
+		assert_eq!(read_variable_length(&mut &[0x7F, 0x7F][..]), None);
+	}
+}
@@ -0,0 +1,137 @@
+use crate::{VorbisComments, VorbisError};
+
+/// The loudness ReplayGain 2.0 normalizes signals to, in LUFS.
+const REFERENCE_LOUDNESS: f64 = -18.0;
+
+/// The name of the Vorbis comment field that stores the track gain.
+const TRACK_GAIN_TAG: &str = "REPLAYGAIN_TRACK_GAIN";
+/// The name of the Vorbis comment field that stores the track peak.
+const TRACK_PEAK_TAG: &str = "REPLAYGAIN_TRACK_PEAK";
+/// The name of the Vorbis comment field that stores the album gain.
+const ALBUM_GAIN_TAG: &str = "REPLAYGAIN_ALBUM_GAIN";
+/// The name of the Vorbis comment field that stores the album peak.
+const ALBUM_PEAK_TAG: &str = "REPLAYGAIN_ALBUM_PEAK";
+
+/// The [ReplayGain] information of a track or album, which tells players how much to
+/// amplify or attenuate a stream to play it at a consistent loudness, as stored in the
+/// `REPLAYGAIN_*` Vorbis comments.
+///
+/// [ReplayGain]: https://wiki.hydrogenaud.io/index.php?title=ReplayGain_2.0_specification
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct VorbisReplayGain {
+	/// The gain to apply, in decibels (dB).
+	pub gain: f32,
+	/// The peak absolute sample value of the signal, where 1 is full scale, if known.
+	pub peak: Option<f32>
+}
+
+impl VorbisReplayGain {
+	/// Parses the ReplayGain information of the specified kind from the specified Vorbis
+	/// comments. When album gain is requested but missing, the track gain is returned
+	/// instead. `None` is returned if there is no valid gain of any suitable kind.
+	pub fn from_comments(comments: &VorbisComments, mode: VorbisReplayGainMode) -> Option<Self> {
+		let parse = |gain_tag, peak_tag| {
+			let gain = comments.get(gain_tag)?.trim();
+			let gain = gain
+				.get(gain.len().saturating_sub(2)..)
+				.filter(|unit| unit.eq_ignore_ascii_case("dB"))
+				.map_or(gain, |_| &gain[..gain.len() - 2]);
+
+			Some(Self {
+				gain: gain
+					.trim()
+					.parse()
+					.ok()
+					.filter(|gain: &f32| gain.is_finite())?,
+				peak: comments
+					.get(peak_tag)
+					.and_then(|peak| peak.trim().parse().ok())
+					.filter(|peak: &f32| peak.is_finite() && *peak > 0.0)
+			})
+		};
+
+		match mode {
+			VorbisReplayGainMode::Album => parse(ALBUM_GAIN_TAG, ALBUM_PEAK_TAG),
+			VorbisReplayGainMode::Track => None
+		}
+		.or_else(|| parse(TRACK_GAIN_TAG, TRACK_PEAK_TAG))
+	}
+
+	/// Returns the ReplayGain 2.0 information for a signal with the specified integrated
+	/// loudness, in LUFS, and peak absolute sample value.
+	pub(crate) fn from_loudness(loudness: f64, peak: f32) -> Self {
+		Self {
+			gain: (REFERENCE_LOUDNESS - loudness) as f32,
+			peak: Some(peak)
+		}
+	}
+
+	/// Returns the linear factor to multiply samples by to apply this gain. If the peak is
+	/// known, the factor is reduced as needed to keep the peak from clipping.
+	pub fn linear_gain(&self) -> f32 {
+		let linear_gain = 10.0_f32.powf(self.gain / 20.0);
+
+		match self.peak {
+			Some(peak) => linear_gain.min(1.0 / peak),
+			None => linear_gain
+		}
+	}
+
+	/// Replaces the track ReplayGain tags of the specified comments with this information.
+	/// Values are formatted with a fixed width, so that tags can be rewritten in place.
+	pub(crate) fn set_track_tags(&self, comments: &mut VorbisComments) -> Result<(), VorbisError> {
+		comments
+			.replace(
+				TRACK_GAIN_TAG,
+				format!("{:+06.2} dB", self.gain.clamp(-99.99, 99.99))
+			)?
+			.replace(
+				TRACK_PEAK_TAG,
+				format!("{:.6}", self.peak.unwrap_or(0.0).clamp(0.0, 9.999_999))
+			)?;
+
+		Ok(())
+	}
+}
+
+/// The kind of [`VorbisReplayGain`] information to apply to a decoded signal.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum VorbisReplayGainMode {
+	/// Play every track at the same loudness.
+	Track,
+	/// Play every album at the same loudness, preserving the loudness differences between
+	/// its tracks. Streams without album gain fall back to their track gain.
+	Album
+}
+
+#[cfg(test)]
+mod test {
+	use super::{VorbisReplayGain, VorbisReplayGainMode};
+	use crate::VorbisComments;
+
+	#[test]
+	fn replay_gain_is_parsed_from_comments() {
+		let mut comments = VorbisComments::new();
+		comments
+			.add("REPLAYGAIN_TRACK_GAIN", "-7.03 dB")
+			.unwrap()
+			.add("REPLAYGAIN_TRACK_PEAK", "0.5")
+			.unwrap()
+			.add("replaygain_album_gain", "+3.5dB")
+			.unwrap();
+
+		let track_gain =
+			VorbisReplayGain::from_comments(&comments, VorbisReplayGainMode::Track).unwrap();
+		assert_eq!(track_gain.gain, -7.03);
+		assert_eq!(track_gain.peak, Some(0.5));
+
+		track_gain.set_track_tags(&mut comments).unwrap();
+		assert_eq!(comments.get("REPLAYGAIN_TRACK_GAIN"), Some("-07.03 dB"));
+		assert_eq!(comments.get("REPLAYGAIN_TRACK_PEAK"), Some("0.500000"));
+
+		let album_gain =
+			VorbisReplayGain::from_comments(&comments, VorbisReplayGainMode::Album).unwrap();
+		assert_eq!(album_gain.gain, 3.5);
+		assert_eq!(album_gain.peak, None);
+	}
+}
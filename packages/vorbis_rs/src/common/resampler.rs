@@ -0,0 +1,184 @@
+use std::num::NonZeroU32;
+
+use rubato::{
+	Resampler, SincFixedIn, SincInterpolationParameters, SincInterpolationType, WindowFunction
+};
+
+use crate::VorbisError;
+
+/// The number of input samples per channel the resampler processes at once.
+const CHUNK_SIZE: usize = 1024;
+
+/// A high-quality resampler for planar audio signals, which converts them from one sampling
+/// frequency to another.
+///
+/// The resampler delay is compensated, and the resampled signal is generated with exactly
+/// as many samples as the input signal duration implies, so that sample positions and counts
+/// stay consistent between both sampling frequencies.
+pub(crate) struct AudioResampler {
+	resampler: SincFixedIn<f32>,
+	ratio: f64,
+	input_buffer: Vec<Vec<f32>>,
+	output_buffer: Vec<Vec<f32>>,
+	input_samples: u64,
+	output_samples: u64,
+	pending_delay: usize
+}
+
+// SAFETY: shared references to this resampler only allow reading its ratio, which is
+// not affected by the interpolator: the rest of the state, which is `Send`, is only
+// accessed through mutable references
+unsafe impl Sync for AudioResampler {}
+
+impl AudioResampler {
+	/// Creates a new resampler for a signal with the specified number of channels, from the
+	/// input sampling frequency to the output one.
+	pub fn new(
+		input_sampling_frequency: NonZeroU32,
+		output_sampling_frequency: NonZeroU32,
+		channels: usize
+	) -> Self {
+		let ratio =
+			f64::from(output_sampling_frequency.get()) / f64::from(input_sampling_frequency.get());
+
+		// These parameters are recommended by the rubato documentation for high quality
+		let resampler = SincFixedIn::new(
+			ratio,
+			1.0,
+			SincInterpolationParameters {
+				sinc_len: 256,
+				f_cutoff: 0.95,
+				oversampling_factor: 256,
+				interpolation: SincInterpolationType::Cubic,
+				window: WindowFunction::BlackmanHarris2
+			},
+			CHUNK_SIZE,
+			channels
+		)
+		// The ratio is always positive and finite, and the relative ratio is fixed
+		.expect("Unexpected resampler construction error");
+
+		Self {
+			pending_delay: resampler.output_delay(),
+			resampler,
+			ratio,
+			input_buffer: vec![Vec::with_capacity(CHUNK_SIZE); channels],
+			output_buffer: vec![vec![]; channels],
+			input_samples: 0,
+			output_samples: 0
+		}
+	}
+
+	/// Returns the ratio between the output and input sampling frequencies.
+	pub fn ratio(&self) -> f64 {
+		self.ratio
+	}
+
+	/// Submits the specified planar block of samples for resampling, returning the resampled
+	/// samples that became available as a result, which may be none.
+	pub fn process<S: AsRef<[f32]>>(&mut self, block: &[S]) -> Result<&[Vec<f32>], VorbisError> {
+		for output_channel in &mut self.output_buffer {
+			output_channel.clear();
+		}
+
+		for (input_channel, block_channel) in self.input_buffer.iter_mut().zip(block) {
+			input_channel.extend_from_slice(block_channel.as_ref());
+		}
+		self.input_samples += block.first().map_or(0, |channel| channel.as_ref().len()) as u64;
+
+		while self.input_buffer[0].len() >= CHUNK_SIZE {
+			self.process_chunk(u64::MAX)?;
+		}
+
+		Ok(&self.output_buffer)
+	}
+
+	/// Signals the end of the input signal, returning the remaining resampled samples.
+	/// Calling this method again returns no samples, until more are submitted.
+	pub fn finish(&mut self) -> Result<&[Vec<f32>], VorbisError> {
+		for output_channel in &mut self.output_buffer {
+			output_channel.clear();
+		}
+
+		// Pad the input signal with silence until every sample it implies is generated
+		let total_output_samples = (self.input_samples as f64 * self.ratio).round() as u64;
+		while self.output_samples < total_output_samples {
+			for input_channel in &mut self.input_buffer {
+				input_channel.resize(input_channel.len().max(CHUNK_SIZE), 0.0);
+			}
+
+			self.process_chunk(total_output_samples)?;
+		}
+
+		Ok(&self.output_buffer)
+	}
+
+	/// Discards any buffered samples and resets the resampler state, as if it was just
+	/// created.
+	pub fn reset(&mut self) {
+		self.resampler.reset();
+		for input_channel in &mut self.input_buffer {
+			input_channel.clear();
+		}
+		self.input_samples = 0;
+		self.output_samples = 0;
+		self.pending_delay = self.resampler.output_delay();
+	}
+
+	/// Resamples a chunk of buffered input samples, appending the resulting samples to the
+	/// output buffer, up to the specified total count of output samples.
+	fn process_chunk(&mut self, total_output_samples: u64) -> Result<(), VorbisError> {
+		let input_chunk = self
+			.input_buffer
+			.iter()
+			.map(|input_channel| &input_channel[..CHUNK_SIZE])
+			.collect::<Vec<_>>();
+		let output_chunk = self.resampler.process(&input_chunk, None)?;
+
+		for input_channel in &mut self.input_buffer {
+			input_channel.drain(..CHUNK_SIZE);
+		}
+
+		// The first samples generated by the resampler are due to its delay, so skip them
+		let output_chunk_len = output_chunk.first().map_or(0, Vec::len);
+		let skipped = self.pending_delay.min(output_chunk_len);
+		self.pending_delay -= skipped;
+
+		let kept = ((output_chunk_len - skipped) as u64)
+			.min(total_output_samples - self.output_samples) as usize;
+		for (output_channel, output_chunk_channel) in
+			self.output_buffer.iter_mut().zip(output_chunk)
+		{
+			output_channel.extend_from_slice(&output_chunk_channel[skipped..skipped + kept]);
+		}
+		self.output_samples += kept as u64;
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::num::NonZeroU32;
+
+	use super::AudioResampler;
+
+	#[test]
+	fn resampler_generates_exact_sample_count() {
+		let mut resampler = AudioResampler::new(
+			NonZeroU32::new(44100).unwrap(),
+			NonZeroU32::new(48000).unwrap(),
+			2
+		);
+		let block = vec![0.5; 3000];
+
+		let mut resampled_samples = 0;
+		for _ in 0..3 {
+			resampled_samples += resampler.process(&[&block, &block]).unwrap()[0].len();
+		}
+		resampled_samples += resampler.finish().unwrap()[0].len();
+
+		assert_eq!(resampled_samples, 9796);
+		assert!(resampler.finish().unwrap()[0].is_empty());
+	}
+}
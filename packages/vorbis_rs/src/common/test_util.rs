@@ -0,0 +1,86 @@
+use std::{
+	f32::consts::TAU,
+	io::Write,
+	num::{NonZeroU8, NonZeroU32}
+};
+
+use crate::VorbisEncoderBuilder;
+
+/// The sampling frequency of the test signals of fixed frequency, in Hertz (Hz).
+pub(crate) const SAMPLING_FREQUENCY: u32 = 8000;
+
+/// Generates a sine wave with the specified frequency, amplitude and phase.
+pub(crate) fn sine_wave(
+	sample_count: usize,
+	sampling_frequency: u32,
+	frequency: f32,
+	amplitude: f32,
+	phase: f32
+) -> Vec<f32> {
+	(0..sample_count)
+		.map(|i| (i as f32 * frequency * TAU / sampling_frequency as f32 + phase).sin() * amplitude)
+		.collect()
+}
+
+/// Generates a 440 Hz sine wave with the specified amplitude, sampled at
+/// [`SAMPLING_FREQUENCY`].
+pub(crate) fn tone(sample_count: usize, amplitude: f32) -> Vec<f32> {
+	sine_wave(sample_count, SAMPLING_FREQUENCY, 440.0, amplitude, 0.0)
+}
+
+/// Generates white noise with a fixed seed, which makes for streams with many pages.
+pub(crate) fn noise(sample_count: usize) -> Vec<f32> {
+	let mut seed = 1_u32;
+	(0..sample_count)
+		.map(|_| {
+			seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+			(seed >> 8) as f32 / (1 << 24) as f32 * 0.2 - 0.1
+		})
+		.collect()
+}
+
+/// Returns a builder for encoders of mono signals sampled at [`SAMPLING_FREQUENCY`],
+/// which write a stream with the specified serial to `sink`.
+pub(crate) fn mono_encoder_builder<W: Write>(sink: W, serial: i32) -> VorbisEncoderBuilder<W> {
+	VorbisEncoderBuilder::new_with_serial(
+		NonZeroU32::new(SAMPLING_FREQUENCY).unwrap(),
+		NonZeroU8::new(1).unwrap(),
+		sink,
+		serial
+	)
+}
+
+/// Encodes the specified mono signal, sampled at [`SAMPLING_FREQUENCY`], with the
+/// default options.
+pub(crate) fn encode_mono(samples: &[f32], serial: i32) -> Vec<u8> {
+	let mut encoded_ogg = vec![];
+	let mut encoder = mono_encoder_builder(&mut encoded_ogg, serial)
+		.build()
+		.unwrap();
+	encoder.encode_audio_block([samples]).unwrap();
+	encoder.finish().unwrap();
+	encoded_ogg
+}
+
+/// Encodes a [`tone`] with the specified number of samples and half amplitude.
+pub(crate) fn encode_tone(sample_count: usize, serial: i32) -> Vec<u8> {
+	encode_mono(&tone(sample_count, 0.5), serial)
+}
+
+/// Returns the byte offsets of the pages of the specified Ogg stream.
+pub(crate) fn page_offsets(encoded_ogg: &[u8]) -> Vec<usize> {
+	let mut page_offsets = vec![];
+	let mut page_offset = 0;
+	while page_offset < encoded_ogg.len() {
+		page_offsets.push(page_offset);
+		let page = &encoded_ogg[page_offset..];
+
+		let segments = usize::from(page[26]);
+		let body_length = page[27..][..segments]
+			.iter()
+			.map(|&lacing_value| usize::from(lacing_value))
+			.sum::<usize>();
+		page_offset += 27 + segments + body_length;
+	}
+	page_offsets
+}
@@ -1,20 +1,21 @@
 use std::{
-	borrow::Cow,
-	ffi::CString,
+	ffi::{CStr, CString},
 	mem::MaybeUninit,
 	num::{NonZeroU8, NonZeroU32},
 	os::raw::c_int,
-	ptr
+	ptr, slice
 };
 
 use aotuv_lancer_vorbis_sys::{
-	OV_ECTL_RATEMANAGE2_SET, vorbis_comment, vorbis_comment_add_tag, vorbis_comment_clear,
-	vorbis_comment_init, vorbis_encode_ctl, vorbis_encode_init, vorbis_encode_init_vbr,
-	vorbis_encode_setup_init, vorbis_encode_setup_managed, vorbis_info, vorbis_info_clear,
-	vorbis_info_init
+	OV_ECTL_COUPLING_SET, OV_ECTL_LOWPASS_SET, OV_ECTL_RATEMANAGE2_SET, vorbis_comment,
+	vorbis_comment_add_tag, vorbis_comment_clear, vorbis_comment_init, vorbis_commentheader_out,
+	vorbis_encode_ctl, vorbis_encode_setup_init, vorbis_encode_setup_managed,
+	vorbis_encode_setup_vbr, vorbis_info, vorbis_info_blocksize, vorbis_info_clear,
+	vorbis_info_init, vorbis_packet_blocksize, vorbis_synthesis_headerin
 };
+use ogg_next_sys::ogg_packet_clear;
 
-use crate::common::VorbisError;
+use crate::common::{OggPacket, VorbisComments, VorbisError};
 
 /// A high-level abstraction for a Vorbis stream information struct.
 pub(crate) struct VorbisInfo {
@@ -25,7 +26,7 @@ impl VorbisInfo {
 	/// Performs basic initialization of a new `VorbisInfo` struct. Most client code
 	/// interested in encoding audio will want to complete this initialization by
 	/// calling more methods on the returned struct, such as
-	/// [`encode_init_vbr`](Self::encode_init_vbr).
+	/// [`encode_setup_vbr`](Self::encode_setup_vbr).
 	pub fn new() -> Self {
 		let vorbis_info = Box::into_raw(Box::<vorbis_info>::new_uninit()).cast();
 
@@ -39,14 +40,15 @@ impl VorbisInfo {
 		Self { vorbis_info }
 	}
 
-	/// Prepares this Vorbis codec information struct to encode an audio signal
+	/// Sets up this Vorbis codec information struct to encode an audio signal
 	/// in pure VBR quality mode selected by a target bitrate (in bit/s). The
-	/// bitrate management engine is not enabled.
+	/// bitrate management engine is not enabled. The setup must be completed with
+	/// [`encode_setup_init`](Self::encode_setup_init).
 	///
 	/// An error may be returned if the encoder doesn't know any modes to use to
 	/// encode the specified signal at the requested bitrate, and is thus unable to
 	/// comply with the request.
-	pub fn encode_init_vbr(
+	pub fn encode_setup_vbr(
 		&mut self,
 		sampling_frequency: NonZeroU32,
 		channels: NonZeroU8,
@@ -70,14 +72,12 @@ impl VorbisInfo {
 				OV_ECTL_RATEMANAGE2_SET as c_int,
 				ptr::null_mut()
 			))?;
-
-			libvorbisenc_return_value_to_result!(vorbis_encode_setup_init(self.vorbis_info))?;
 		}
 
 		Ok(())
 	}
 
-	/// Like [`encode_init_vbr`](Self::encode_init_vbr), but selects the quality mode
+	/// Like [`encode_setup_vbr`](Self::encode_setup_vbr), but selects the quality mode
 	/// directly from the specified quality factor, without internally converting a
 	/// target bitrate to a quality factor. The valid range for this factor is
 	/// [-0.2, 1].
@@ -90,15 +90,15 @@ impl VorbisInfo {
 	/// Another upside is that there always is some mode to encode audio at a given
 	/// quality level. The downside is that the output bitrate is harder to predict
 	/// across different types of audio signals.
-	pub fn encode_init_quality_vbr(
+	pub fn encode_setup_quality_vbr(
 		&mut self,
 		sampling_frequency: NonZeroU32,
 		channels: NonZeroU8,
 		quality_factor: f32
 	) -> Result<(), VorbisError> {
 		unsafe {
-			// SAFETY: we assume vorbis_encode_init_vbr follows its documented contract
-			libvorbisenc_return_value_to_result!(vorbis_encode_init_vbr(
+			// SAFETY: we assume vorbis_encode_setup_vbr follows its documented contract
+			libvorbisenc_return_value_to_result!(vorbis_encode_setup_vbr(
 				self.vorbis_info,
 				channels.get().into(),
 				sampling_frequency.get().try_into()?,
@@ -109,23 +109,24 @@ impl VorbisInfo {
 		Ok(())
 	}
 
-	/// Prepares this Vorbis codec information struct to encode an audio signal
+	/// Sets up this Vorbis codec information struct to encode an audio signal
 	/// in ABR mode selected by an average bitrate (in bit/s). The bitrate management
 	/// engine is enabled to ensure that the instantaneous bitrate does not divert
 	/// significantly from the specified average, but no hard bitrate limits are imposed.
+	/// The setup must be completed with [`encode_setup_init`](Self::encode_setup_init).
 	///
 	/// An error may be returned if the encoder doesn't know any modes to use to
 	/// encode the specified signal at the requested bitrate, and is thus unable to
 	/// comply with the request.
-	pub fn encode_init_abr(
+	pub fn encode_setup_abr(
 		&mut self,
 		sampling_frequency: NonZeroU32,
 		channels: NonZeroU8,
 		average_bitrate: NonZeroU32
 	) -> Result<(), VorbisError> {
-		// SAFETY: we assume vorbis_encode_init follows its documented contract
+		// SAFETY: we assume vorbis_encode_setup_managed follows its documented contract
 		unsafe {
-			libvorbisenc_return_value_to_result!(vorbis_encode_init(
+			libvorbisenc_return_value_to_result!(vorbis_encode_setup_managed(
 				self.vorbis_info,
 				channels.get().into(),
 				sampling_frequency.get().try_into()?,
@@ -138,25 +139,26 @@ impl VorbisInfo {
 		Ok(())
 	}
 
-	/// Prepares this Vorbis codec information struct to encode an audio signal
+	/// Sets up this Vorbis codec information struct to encode an audio signal
 	/// in constrained ABR mode selected by a hard maximum bitrate (in bit/s).
 	/// The bitrate management engine is enabled to ensure that the instantaneous
 	/// bitrate never exceeds the specified maximum bitrate, which is a hard
 	/// limit. Internally, the encoder will target an average bitrate that's
-	/// slightly lower than the specified maximum bitrate.
+	/// slightly lower than the specified maximum bitrate. The setup must be completed
+	/// with [`encode_setup_init`](Self::encode_setup_init).
 	///
 	/// An error may be returned if the encoder doesn't know any modes to use to
 	/// encode the specified signal at the requested bitrate, and is thus unable to
 	/// comply with the request.
-	pub fn encode_init_constrained_abr(
+	pub fn encode_setup_constrained_abr(
 		&mut self,
 		sampling_frequency: NonZeroU32,
 		channels: NonZeroU8,
 		maximum_bitrate: NonZeroU32
 	) -> Result<(), VorbisError> {
-		// SAFETY: we assume vorbis_encode_init follows its documented contract
+		// SAFETY: we assume vorbis_encode_setup_managed follows its documented contract
 		unsafe {
-			libvorbisenc_return_value_to_result!(vorbis_encode_init(
+			libvorbisenc_return_value_to_result!(vorbis_encode_setup_managed(
 				self.vorbis_info,
 				channels.get().into(),
 				sampling_frequency.get().try_into()?,
@@ -169,6 +171,128 @@ impl VorbisInfo {
 		Ok(())
 	}
 
+	/// Overrides the lowpass frequency of the encoding mode set up for this Vorbis codec
+	/// information struct, in Hertz (Hz). `libvorbisenc` clamps it to the [2, 99] kHz
+	/// range. This must be called after setting up an encoding mode, and before
+	/// [`encode_setup_init`](Self::encode_setup_init).
+	pub fn encode_set_lowpass_frequency(
+		&mut self,
+		lowpass_frequency: NonZeroU32
+	) -> Result<(), VorbisError> {
+		let mut lowpass_frequency_khz = f64::from(lowpass_frequency.get()) / 1000.0;
+
+		// SAFETY: we assume vorbis_encode_ctl follows its documented contract, which
+		// requires a pointer to a double for this request
+		unsafe {
+			libvorbisenc_return_value_to_result!(vorbis_encode_ctl(
+				self.vorbis_info,
+				OV_ECTL_LOWPASS_SET as c_int,
+				(&raw mut lowpass_frequency_khz).cast()
+			))?;
+		}
+
+		Ok(())
+	}
+
+	/// Enables or disables the channel coupling of the encoding mode set up for this Vorbis
+	/// codec information struct, which is enabled by default. This must be called after
+	/// setting up an encoding mode, and before [`encode_setup_init`](Self::encode_setup_init).
+	pub fn encode_set_channel_coupling(
+		&mut self,
+		channel_coupling: bool
+	) -> Result<(), VorbisError> {
+		let mut channel_coupling = c_int::from(channel_coupling);
+
+		// SAFETY: we assume vorbis_encode_ctl follows its documented contract, which
+		// requires a pointer to an int for this request
+		unsafe {
+			libvorbisenc_return_value_to_result!(vorbis_encode_ctl(
+				self.vorbis_info,
+				OV_ECTL_COUPLING_SET as c_int,
+				(&raw mut channel_coupling).cast()
+			))?;
+		}
+
+		Ok(())
+	}
+
+	/// Completes the encoding setup of this Vorbis codec information struct, after which
+	/// it is fully initialized for encoding.
+	pub fn encode_setup_init(&mut self) -> Result<(), VorbisError> {
+		// SAFETY: we assume vorbis_encode_setup_init follows its documented contract
+		unsafe {
+			libvorbisenc_return_value_to_result!(vorbis_encode_setup_init(self.vorbis_info))?;
+		}
+
+		Ok(())
+	}
+
+	/// Decodes the specified Vorbis header packet into this Vorbis stream information
+	/// struct and the specified list of comments, which must be fresh. The three Vorbis
+	/// header packets must be decoded in order before this struct is fully initialized
+	/// for decoding.
+	pub fn synthesis_headerin(
+		&mut self,
+		raw_comments: &mut RawVorbisComments,
+		header_packet: &OggPacket<'_>
+	) -> Result<(), VorbisError> {
+		let mut ogg_packet = header_packet.as_ogg_packet();
+
+		// SAFETY: we assume vorbis_synthesis_headerin follows its documented contract,
+		// and that it does not write to the packet data nor keep references to it
+		unsafe {
+			libvorbis_return_value_to_result!(vorbis_synthesis_headerin(
+				self.vorbis_info,
+				&mut raw_comments.vorbis_comment,
+				&mut ogg_packet
+			))?;
+		}
+
+		Ok(())
+	}
+
+	/// Returns the block size of the specified audio packet, in samples, from which
+	/// the number of samples it decodes to can be derived. This struct must have been
+	/// initialized for decoding with [`synthesis_headerin`](Self::synthesis_headerin).
+	pub fn packet_blocksize(&self, audio_packet: &OggPacket<'_>) -> Result<u32, VorbisError> {
+		let mut ogg_packet = audio_packet.as_ogg_packet();
+
+		// SAFETY: we assume vorbis_packet_blocksize follows its documented contract,
+		// and that it does not write to the packet data nor keep references to it.
+		// Vorbis block sizes are at most 8192 samples, so the cast is lossless
+		unsafe {
+			Ok(libvorbis_return_value_to_result!(vorbis_packet_blocksize(
+				self.vorbis_info,
+				&mut ogg_packet
+			))? as u32)
+		}
+	}
+
+	/// Returns the maximum, nominal and minimum bitrates of the stream this Vorbis stream
+	/// information struct was initialized for, in bit/s and in that order. Bitrates that
+	/// are not set, such as the bounds of VBR streams, are `None`.
+	pub fn bitrates(&self) -> [Option<NonZeroU32>; 3] {
+		// SAFETY: the pointed-to struct is always initialized
+		let vorbis_info = unsafe { &*self.vorbis_info };
+
+		[
+			vorbis_info.bitrate_upper,
+			vorbis_info.bitrate_nominal,
+			vorbis_info.bitrate_lower
+		]
+		.map(|bitrate| u32::try_from(bitrate).ok().and_then(NonZeroU32::new))
+	}
+
+	/// Returns the short and long block sizes of the stream this Vorbis stream information
+	/// struct was initialized for, in samples. This struct must be fully initialized for
+	/// either encoding or decoding.
+	pub fn block_sizes(&self) -> [u16; 2] {
+		// SAFETY: we assume vorbis_info_blocksize follows its documented contract. Vorbis
+		// block sizes are at most 8192 samples, so the cast is lossless
+		[0, 1]
+			.map(|long_block| unsafe { vorbis_info_blocksize(self.vorbis_info, long_block) } as u16)
+	}
+
 	/// Returns the number of channels of the audio signal this Vorbis stream
 	/// information struct was initialized for.
 	pub fn channels(&self) -> NonZeroU8 {
@@ -179,6 +303,15 @@ impl VorbisInfo {
 	}
 }
 
+// SAFETY: the pointed-to struct is owned by this value, and libvorbis does not tie
+// it to the thread that created it: its codec state is only ever accessed through the
+// structs that own it, with no thread-local or global mutable state involved. Shared
+// references only allow reading it, except for VorbisEncodingState::new, which lazily
+// builds the encoding codebooks through them. That function is unsafe and requires its
+// callers to not share the struct with other threads while it runs
+unsafe impl Send for VorbisInfo {}
+unsafe impl Sync for VorbisInfo {}
+
 impl Drop for VorbisInfo {
 	fn drop(&mut self) {
 		// SAFETY: when this struct is dropped we have a valid Vorbis info struct to clear,
@@ -190,13 +323,14 @@ impl Drop for VorbisInfo {
 	}
 }
 
-/// A high-level abstraction for a list of Vorbis user comments.
-pub(crate) struct VorbisComments {
+/// A high-level abstraction for a `libvorbis` list of Vorbis user comments, which
+/// is used to hand over a [`VorbisComments`] list to C code.
+pub(crate) struct RawVorbisComments {
 	pub(crate) vorbis_comment: vorbis_comment
 }
 
-impl VorbisComments {
-	/// Creates a new list of Vorbis user comments.
+impl RawVorbisComments {
+	/// Creates a new, empty list of `libvorbis` Vorbis user comments.
 	pub fn new() -> Self {
 		let mut vorbis_comment = MaybeUninit::uninit();
 
@@ -210,35 +344,86 @@ impl VorbisComments {
 		}
 	}
 
-	/// Adds the specified tag-value pair to the list of user comments held by this struct.
-	pub fn add_tag<'tag, 'value>(
-		&mut self,
-		tag: impl Into<Cow<'tag, str>>,
-		value: impl Into<Cow<'value, str>>
-	) -> Result<(), VorbisError> {
-		fn cow_string_to_byte_vec(string: Cow<'_, str>) -> Vec<u8> {
-			match string {
-				Cow::Owned(string) => string.into(),
-				Cow::Borrowed(string) => string.into()
-			}
+	/// Creates a new list of `libvorbis` Vorbis user comments with the same comments as
+	/// the specified list. The vendor string is not copied, as `libvorbis` always writes
+	/// its own.
+	pub fn from_comments(comments: &VorbisComments) -> Result<Self, VorbisError> {
+		let mut raw_comments = Self::new();
+
+		for (tag, value) in comments {
+			let tag_c_string = CString::new(tag)?;
+			let value_c_string = CString::new(value)?;
+			// SAFETY: we assume vorbis_comment_add_tag follows its documented contract
+			unsafe {
+				vorbis_comment_add_tag(
+					&mut raw_comments.vorbis_comment,
+					tag_c_string.as_ptr(),
+					value_c_string.as_ptr()
+				);
+			};
 		}
 
-		let tag_c_string = CString::new(cow_string_to_byte_vec(tag.into()))?;
-		let value_c_string = CString::new(cow_string_to_byte_vec(value.into()))?;
-		// SAFETY: we assume vorbis_comment_add_tag follows its documented contract
+		Ok(raw_comments)
+	}
+
+	/// Returns the data of the Vorbis comment header packet for these comments, as
+	/// generated by `libvorbis` when encoding.
+	///
+	/// This is a facade for the `vorbis_commentheader_out` function.
+	pub fn header_packet_data(&mut self) -> Result<Vec<u8>, VorbisError> {
+		let mut comment_header = MaybeUninit::uninit();
+
+		// SAFETY: we assume vorbis_commentheader_out follows its documented contract. The
+		// packet data is allocated by libvorbis on success, so it is copied to Rust-owned
+		// memory and then freed
 		unsafe {
-			vorbis_comment_add_tag(
+			libvorbis_return_value_to_result!(vorbis_commentheader_out(
 				&mut self.vorbis_comment,
-				tag_c_string.as_ptr(),
-				value_c_string.as_ptr()
-			);
-		};
+				comment_header.as_mut_ptr()
+			))?;
 
-		Ok(())
+			let mut comment_header = comment_header.assume_init();
+			let data = OggPacket::new(comment_header).data().to_vec();
+			ogg_packet_clear(&mut comment_header);
+
+			Ok(data)
+		}
+	}
+
+	/// Converts the specified `libvorbis` list of Vorbis user comments to an owned
+	/// [`VorbisComments`] list. Comments are expected to be UTF-8 strings, as mandated
+	/// by the Vorbis I specification, but invalid UTF-8 sequences are replaced instead
+	/// of causing errors. Comments without a `=` separator are kept with an empty value.
+	///
+	/// # Safety
+	/// The specified struct must have been initialized by `libvorbis`.
+	pub unsafe fn to_comments(vorbis_comment: &vorbis_comment) -> VorbisComments {
+		let mut comments = VorbisComments::new();
+
+		// SAFETY: the vendor string is NUL-terminated, and the comment arrays contain
+		// as many elements as comments there are, with the length of each comment
+		// not including a NUL terminator
+		unsafe {
+			if !vorbis_comment.vendor.is_null() {
+				comments.set_vendor(CStr::from_ptr(vorbis_comment.vendor).to_string_lossy());
+			}
+
+			for i in 0..usize::try_from(vorbis_comment.comments).unwrap_or(0) {
+				let comment = String::from_utf8_lossy(slice::from_raw_parts(
+					(*vorbis_comment.user_comments.add(i)).cast::<u8>(),
+					*vorbis_comment.comment_lengths.add(i) as usize
+				));
+
+				let (tag, value) = comment.split_once('=').unwrap_or((&comment, ""));
+				comments.push_unchecked(tag.into(), value.into());
+			}
+		}
+
+		comments
 	}
 }
 
-impl Drop for VorbisComments {
+impl Drop for RawVorbisComments {
 	fn drop(&mut self) {
 		// SAFETY: when this struct is dropped we have a valid Vorbis comment struct to clear
 		unsafe { vorbis_comment_clear(&mut self.vorbis_comment) };
@@ -1,5 +1,17 @@
 mod decoder_util;
 pub use decoder_util::VorbisAudioSamples;
 
+mod decoder_limits;
+pub use decoder_limits::{VorbisDecoderLimit, VorbisDecoderLimits};
+
+mod decoder_events;
+pub use decoder_events::VorbisDecoderEvent;
+
+mod decoder_seek_table;
+pub use decoder_seek_table::{VorbisSeekPoint, VorbisSeekTable};
+
+mod decoder_source;
+pub use decoder_source::{VorbisCachedSource, VorbisRandomAccessSource};
+
 mod decoder_impl;
 pub use decoder_impl::VorbisDecoder;
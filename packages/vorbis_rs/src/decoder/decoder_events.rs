@@ -0,0 +1,217 @@
+use std::collections::HashSet;
+
+use aotuv_lancer_vorbis_sys::{vorbis_info, vorbis_packet_blocksize};
+
+#[allow(unused_imports)]
+use crate::VorbisDecoder;
+use crate::{
+	VorbisError,
+	common::{OggPage, OggPageSeek, OggStream, OggSync}
+};
+
+/// A recoverable problem found in a damaged stream by a
+/// [resilient](VorbisDecoder::set_resilient) decoder, which skips the affected data and
+/// carries on decoding.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum VorbisDecoderEvent {
+	/// Some audio data is missing due to lost, corrupt or out of sequence pages, so the
+	/// decoded signal has a discontinuity.
+	Hole {
+		/// The position of the discontinuity in the decoded signal, as counted by
+		/// [`VorbisDecoder::decoded_samples`].
+		sample_position: u64
+	},
+	/// Some data that begins like an Ogg page was skipped, as it failed its CRC check or
+	/// has a malformed header.
+	CorruptPage {
+		/// The offset of the skipped data in the source, in bytes.
+		byte_offset: u64,
+		/// The length of the skipped data, in bytes.
+		length: u64
+	},
+	/// Some data that does not belong to any Ogg page was skipped.
+	Garbage {
+		/// The offset of the skipped data in the source, in bytes.
+		byte_offset: u64,
+		/// The length of the skipped data, in bytes.
+		length: u64
+	},
+	/// An audio packet can't be decoded, so it was skipped.
+	BadPacket {
+		/// The offset in the source of the page the packet ends on, in bytes.
+		byte_offset: u64
+	}
+}
+
+/// Scans the data read by a decoder for corrupt pages, garbage data and bad audio
+/// packets, which `vorbisfile` skips without reporting them.
+pub(in crate::decoder) struct CorruptionScanner {
+	ogg_sync: OggSync,
+	scan_state: ScanState
+}
+
+impl CorruptionScanner {
+	/// Creates a new scanner for the Vorbis stream with the specified serial, whose next
+	/// data to scan is at the specified byte offset of the source. The scanner doesn't
+	/// need to start at a page boundary.
+	///
+	/// # Safety
+	/// The specified Vorbis stream information must be valid, and stay so while this
+	/// scanner is alive.
+	pub unsafe fn new(
+		stream_serial: i32,
+		vorbis_info: *mut vorbis_info,
+		byte_offset: u64
+	) -> Result<Self, VorbisError> {
+		Ok(Self {
+			ogg_sync: OggSync::new(),
+			scan_state: ScanState {
+				ogg_stream: OggStream::new(stream_serial)?,
+				stream_serial,
+				vorbis_info,
+				byte_offset,
+				synced: false,
+				skipped_data: None,
+				reported_events: HashSet::new(),
+				events: Vec::new()
+			}
+		})
+	}
+
+	/// Scans the specified data, which follows the data previously scanned.
+	pub fn scan(&mut self, data: &[u8]) {
+		// Scanning is best-effort, so data that can't be buffered is not scanned
+		if self.ogg_sync.write(data).is_err() {
+			return;
+		}
+
+		loop {
+			match self.ogg_sync.seek_page() {
+				OggPageSeek::Page(ogg_page) => self.scan_state.scan_page(&ogg_page),
+				OggPageSeek::Skipped {
+					bytes,
+					capture_pattern
+				} => self.scan_state.skip(bytes as u64, capture_pattern),
+				OggPageSeek::NeedData => break
+			}
+		}
+	}
+
+	/// Reports any data skipped at the end of the source, which was completely scanned.
+	pub fn finish(&mut self) {
+		self.scan_state.report_skipped_data();
+	}
+
+	/// Discards the data to scan, as the next data to scan is at the specified byte
+	/// offset of the source, which may not be at a page boundary.
+	pub fn seek(&mut self, byte_offset: u64) {
+		self.scan_state.report_skipped_data();
+		self.ogg_sync.reset();
+		self.scan_state.ogg_stream.reset();
+		self.scan_state.byte_offset = byte_offset;
+		self.scan_state.synced = false;
+	}
+
+	/// Takes the events reported so far.
+	pub fn take_events(&mut self) -> impl Iterator<Item = VorbisDecoderEvent> + '_ {
+		self.scan_state.events.drain(..)
+	}
+}
+
+/// The state of a [`CorruptionScanner`] other than its Ogg synchronization state.
+struct ScanState {
+	ogg_stream: OggStream,
+	stream_serial: i32,
+	vorbis_info: *mut vorbis_info,
+	byte_offset: u64,
+	synced: bool,
+	skipped_data: Option<(u64, u64, bool)>,
+	reported_events: HashSet<VorbisDecoderEvent>,
+	events: Vec<VorbisDecoderEvent>
+}
+
+impl ScanState {
+	/// Accounts for skipped data, which is merged with any data skipped right before.
+	fn skip(&mut self, bytes: u64, capture_pattern: bool) {
+		match &mut self.skipped_data {
+			Some((_, length, _)) => *length += bytes,
+			None => self.skipped_data = Some((self.byte_offset, bytes, capture_pattern))
+		}
+
+		self.byte_offset += bytes;
+	}
+
+	/// Reports the data skipped since the last page, if any. Data skipped before the first
+	/// page found since scanning started at an arbitrary offset is not reported, as it is
+	/// usually the end of a valid page.
+	fn report_skipped_data(&mut self) {
+		let Some((byte_offset, length, capture_pattern)) = self.skipped_data.take() else {
+			return;
+		};
+
+		if self.synced {
+			self.report(if capture_pattern {
+				VorbisDecoderEvent::CorruptPage {
+					byte_offset,
+					length
+				}
+			} else {
+				VorbisDecoderEvent::Garbage {
+					byte_offset,
+					length
+				}
+			});
+		}
+	}
+
+	/// Checks the audio packets that end on the specified page, if it belongs to the
+	/// scanned Vorbis stream.
+	fn scan_page(&mut self, ogg_page: &OggPage<'_>) {
+		let page_offset = self.byte_offset;
+		self.byte_offset += (ogg_page.header().len() + ogg_page.body().len()) as u64;
+
+		self.report_skipped_data();
+		self.synced = true;
+
+		if ogg_page.stream_serial() != self.stream_serial
+			|| self.ogg_stream.page_in(ogg_page).is_err()
+		{
+			return;
+		}
+
+		loop {
+			// Holes are reported by vorbisfile, so they are ignored here
+			let bad_packet = match self.ogg_stream.packet_out() {
+				Ok(Some(packet)) => {
+					// Empty packets and header packets are ignored by decoders. Else, the
+					// same checks as in vorbis_synthesis are done to tell whether the packet
+					// can be decoded
+					packet.data().first().is_some_and(|byte| byte & 1 == 0)
+						&& unsafe {
+							// SAFETY: the Vorbis stream information is valid, as the caller
+							// of the constructor guarantees. libvorbis does not write to
+							// the packet
+							vorbis_packet_blocksize(self.vorbis_info, &mut packet.as_ogg_packet())
+						} < 0
+				}
+				Ok(None) => break,
+				Err(_) => false
+			};
+
+			if bad_packet {
+				self.report(VorbisDecoderEvent::BadPacket {
+					byte_offset: page_offset
+				});
+			}
+		}
+	}
+
+	/// Reports the specified event, unless it was already reported, which may happen
+	/// when some data is read again after seeking.
+	fn report(&mut self, event: VorbisDecoderEvent) {
+		if self.reported_events.insert(event) {
+			self.events.push(event);
+		}
+	}
+}
@@ -1,26 +1,61 @@
 use core::slice;
 use std::{
+	collections::VecDeque,
 	ffi::c_void,
-	io::Read,
+	io::{Cursor, Read, Seek, SeekFrom},
 	marker::PhantomData,
 	mem::MaybeUninit,
 	num::{NonZeroU8, NonZeroU32},
-	os::raw::c_int,
+	os::raw::{c_int, c_long},
 	ptr
 };
 
 use aotuv_lancer_vorbis_sys::{
-	OggVorbis_File, ov_callbacks, ov_clear, ov_open_callbacks, ov_read_float
+	OggVorbis_File, ov_callbacks, ov_clear, ov_comment, ov_crosslap, ov_halfrate,
+	ov_open_callbacks, ov_pcm_seek, ov_pcm_seek_lap, ov_pcm_tell, ov_pcm_total, ov_raw_seek,
+	ov_read_float, ov_serialnumber, ov_time_seek_lap, vorbis_info_blocksize
 };
 use errno::{Errno, set_errno};
+use ogg_next_sys::ogg_int64_t;
 
-use crate::{common::VorbisError, decoder::VorbisAudioSamples};
+#[cfg(feature = "resampling")]
+use crate::common::AudioResampler;
+use crate::{
+	common::{
+		ChannelMixer, RawVorbisComments, VorbisChannelLayout, VorbisChannelMatrix,
+		VorbisChannelOrder, VorbisComments, VorbisError, VorbisLibrary, VorbisLibraryError,
+		VorbisLibraryErrorKind, VorbisReplayGain, VorbisReplayGainMode
+	},
+	decoder::{
+		VorbisAudioSamples, VorbisCachedSource, VorbisDecoderEvent, VorbisDecoderLimits,
+		VorbisRandomAccessSource, VorbisSeekTable, decoder_events::CorruptionScanner,
+		decoder_limits::HeaderValidator
+	}
+};
 
 /// A decoder that turns a perceptually-encoded, non-chained Ogg Vorbis stream into
 /// blocks of planar, single-precision float audio samples.
+///
+/// Decoders are [`Send`] and [`Sync`] as long as their source is, so they can be moved
+/// to other threads, such as the workers of a thread pool.
 pub struct VorbisDecoder<R: Read> {
 	ogg_vorbis_file: Box<OggVorbis_File>,
 	source: PhantomData<R>,
+	comments: VorbisComments,
+	decoded_samples: u64,
+	half_rate: bool,
+	channel_order: VorbisChannelOrder,
+	channel_matrix: Option<VorbisChannelMatrix>,
+	replay_gain_mode: Option<VorbisReplayGainMode>,
+	channel_mixer: Option<ChannelMixer>,
+	limits: VorbisDecoderLimits,
+	resilient: bool,
+	events: VecDeque<VorbisDecoderEvent>,
+	seek_table: Option<VorbisSeekTable>,
+	#[cfg(feature = "resampling")]
+	output_sampling_frequency: Option<NonZeroU32>,
+	#[cfg(feature = "resampling")]
+	resampler: Option<AudioResampler>,
 	last_audio_block: Option<VorbisAudioSamples>
 }
 
@@ -33,11 +68,38 @@ impl<R: Read> VorbisDecoder<R> {
 	/// I/O errors that might happen during that operation will be returned to the
 	/// caller.
 	pub fn new<S: Into<Box<R>>>(source: S) -> Result<Self, VorbisError> {
+		Self::open(*source.into(), None, None, None)
+	}
+
+	/// Like [`new`](Self::new), but rejects streams that exceed the specified limits,
+	/// which makes it suitable for decoding untrusted streams. Header data is checked as
+	/// it is read, before `libvorbis` parses it.
+	pub fn new_with_limits<S: Into<Box<R>>>(
+		source: S,
+		limits: &VorbisDecoderLimits
+	) -> Result<Self, VorbisError> {
+		Self::open(*source.into(), Some(*limits), None, None)
+	}
+
+	/// Common initialization code for [`VorbisDecoder`] constructors, which opens the
+	/// source with the specified optional limits, and seek and tell callbacks.
+	fn open(
+		source: R,
+		limits: Option<VorbisDecoderLimits>,
+		seek_func: Option<unsafe extern "C" fn(*mut c_void, ogg_int64_t, c_int) -> c_int>,
+		tell_func: Option<unsafe extern "C" fn(*mut c_void) -> c_long>
+	) -> Result<Self, VorbisError> {
 		// The source read needs to be allocated in the heap (i.e., boxed) to have a
 		// constant memory address. Then leak it to a raw pointer to hand its ownership
 		// over to C code. Related, interesting read about trait objects and FFI:
 		// https://adventures.michaelfbryan.com/posts/ffi-safe-polymorphism-in-rust/
-		let source = Box::into_raw(source.into());
+		let source = Box::into_raw(Box::new(DecoderSource {
+			source,
+			position: 0,
+			header_validator: limits.map(HeaderValidator::new),
+			header_error: None,
+			corruption_scanner: None
+		}));
 
 		// The underlying `OggVorbis_File` struct also needs to have a constant memory address because
 		// it stores the vorbis_dsp_state and vorbis_block states by value, and the latter stores a
@@ -52,60 +114,54 @@ impl<R: Read> VorbisDecoder<R> {
 				ptr::null(),
 				0,
 				ov_callbacks {
-					read_func: {
-						// This read callback should match the stdio fread behavior.
-						// See: https://man7.org/linux/man-pages/man3/fread.3p.html
-						unsafe extern "C" fn read_func<R: Read>(
-							ptr: *mut c_void,
-							size: usize,
-							count: usize,
-							datasource: *mut c_void
-						) -> usize {
-							let source = unsafe { &mut *(datasource.cast::<R>()) };
-							let buf =
-								unsafe { slice::from_raw_parts_mut(ptr.cast(), size * count) };
-							match source.read(buf) {
-								Ok(n) => n / size,
-								Err(err) => {
-									// vorbisfile checks errno to tell EOF apart from read errors:
-									// https://xiph.org/vorbis/doc/vorbisfile/callbacks.html
-									// Rust Read trait implementations are not required to set
-									// errno, so make sure we set errno with the most informative
-									// value possible, falling back to a non-zero errno, which is
-									// implied by the C standard to signal some condition
-									set_errno(Errno(err.raw_os_error().unwrap_or(i32::MAX)));
-
-									0
-								}
-							}
-						}
-						Some(read_func::<R>)
-					},
-					seek_func: None,
-					close_func: {
-						unsafe extern "C" fn close_func<R: Read>(datasource: *mut c_void) -> c_int {
-							// Drop the Read when it's no longer needed by vorbisfile.
-							// This is called by ov_clear
-							drop(unsafe { Box::from_raw(datasource.cast::<R>()) });
-
-							0
-						}
-						Some(close_func::<R>)
-					},
-					tell_func: None
+					read_func: Some(read_func::<R>),
+					seek_func,
+					close_func: Some(close_func::<R>),
+					tell_func
 				}
 			)) {
-				Ok(_) => Ok(Self {
-					ogg_vorbis_file: ogg_vorbis_file.assume_init(),
-					source: PhantomData,
-					last_audio_block: None
-				}),
+				Ok(_) => {
+					let mut ogg_vorbis_file = ogg_vorbis_file.assume_init();
+
+					// vorbisfile may ignore read errors after reading the headers, so make
+					// sure that no header was rejected
+					if let Some(err) = (*source).header_error.take() {
+						ov_clear(&mut *ogg_vorbis_file);
+						return Err(err);
+					}
+
+					// Copy the comments to Rust-owned memory once, so that they can be
+					// borrowed and edited without worrying about vorbisfile state
+					let comments =
+						RawVorbisComments::to_comments(&*ov_comment(&mut *ogg_vorbis_file, -1));
+
+					Ok(Self {
+						ogg_vorbis_file,
+						source: PhantomData,
+						comments,
+						decoded_samples: 0,
+						half_rate: false,
+						channel_order: VorbisChannelOrder::default(),
+						channel_matrix: None,
+						replay_gain_mode: None,
+						channel_mixer: None,
+						limits: limits.unwrap_or_default(),
+						resilient: false,
+						events: VecDeque::new(),
+						seek_table: None,
+						#[cfg(feature = "resampling")]
+						output_sampling_frequency: None,
+						#[cfg(feature = "resampling")]
+						resampler: None,
+						last_audio_block: None
+					})
+				}
 				Err(err) => {
 					// According to the documented contract for ov_open_callbacks, the
 					// application is responsible for cleaning up the data source on
 					// failure. This is reiterated in the docs for OggVorbis_File
-					drop(Box::from_raw(source));
-					Err(err)
+					let source = Box::from_raw(source);
+					Err(source.header_error.unwrap_or(err))
 				}
 			}
 		}
@@ -113,21 +169,92 @@ impl<R: Read> VorbisDecoder<R> {
 
 	/// Decodes the next block of samples from this Vorbis stream, in planar format (i.e., one
 	/// vector of samples per channel). The order of channels is defined by the Vorbis I
-	/// specification. `Ok(None)` will be returned when the stream ends, and thus there are no
-	/// more samples to decode.
+	/// specification, unless another [channel order](Self::set_channel_order) was set. When
+	/// a [channel matrix](Self::set_channel_matrix) is set, the returned channels are the
+	/// converted ones. `Ok(None)` will be returned when the stream ends, and thus there are
+	/// no more samples to decode.
+	///
+	/// Samples that the stream signals to discard through the granule positions of its first
+	/// and last pages, such as encoder priming samples and end padding, are not returned.
 	pub fn decode_audio_block(&mut self) -> Result<Option<&VorbisAudioSamples>, VorbisError> {
+		let Some(mut audio_block) = self.decode_vorbis_audio_block()? else {
+			return Ok(None);
+		};
+
+		if let Some(channel_mixer) = &mut self.channel_mixer {
+			// SAFETY: the mixer buffers are only modified when the next audio block is
+			// decoded, and the mixer is only replaced after discarding the returned block
+			audio_block =
+				VorbisAudioSamples::from_buffers(channel_mixer.process(audio_block.samples()));
+		}
+
+		audio_block.remap_channels(self.channel_layout(), self.channel_order);
+		let decoded_samples = self.decoded_samples + audio_block.samples()[0].len() as u64;
+		self.limits.check_samples(decoded_samples)?;
+		self.decoded_samples = decoded_samples;
+		self.last_audio_block = Some(audio_block);
+
+		Ok(self.last_audio_block.as_ref())
+	}
+
+	/// Decodes the next block of samples from this Vorbis stream, in the Vorbis channel
+	/// order, resampling them if necessary.
+	fn decode_vorbis_audio_block(&mut self) -> Result<Option<VorbisAudioSamples>, VorbisError> {
+		#[cfg(feature = "resampling")]
+		if self.resampler.is_some() {
+			// The resampler may need several blocks before generating any samples
+			loop {
+				let audio_block = self.read_audio_block()?;
+				let resampler = self.resampler.as_mut().unwrap();
+
+				let resampled_audio_block = match &audio_block {
+					Some(audio_block) => resampler.process(audio_block.samples())?,
+					None => resampler.finish()?
+				};
+
+				if !resampled_audio_block[0].is_empty() {
+					return Ok(Some(VorbisAudioSamples::from_buffers(
+						resampled_audio_block
+					)));
+				}
+				if audio_block.is_none() {
+					return Ok(None);
+				}
+			}
+		}
+
+		self.read_audio_block()
+	}
+
+	/// Reads the next block of samples decoded by `vorbisfile`, in the Vorbis channel order.
+	/// In resilient mode, holes are reported as events instead of errors.
+	fn read_audio_block(&mut self) -> Result<Option<VorbisAudioSamples>, VorbisError> {
 		let mut current_bitstream = MaybeUninit::uninit();
 		let mut sample_buf = MaybeUninit::uninit();
 
 		// SAFETY: we assume ov_read_float follows its documented contract. See the
 		// VorbisAudioSamples implementation for more safety information
 		unsafe {
-			let samples_read = vorbisfile_return_value_to_result!(ov_read_float(
-				&mut *self.ogg_vorbis_file,
-				sample_buf.as_mut_ptr(),
-				2048, // Most stereo Ogg Vorbis files in the wild use a maximum block size of 2048 samples
-				current_bitstream.as_mut_ptr()
-			))?;
+			let samples_read = loop {
+				let result = vorbisfile_return_value_to_result!(ov_read_float(
+					&mut *self.ogg_vorbis_file,
+					sample_buf.as_mut_ptr(),
+					2048, // Most stereo Ogg Vorbis files in the wild use a maximum block size of 2048 samples
+					current_bitstream.as_mut_ptr()
+				));
+				self.take_scanner_events();
+
+				match result {
+					Err(VorbisError::LibraryError(err))
+						if self.resilient && err.kind() == VorbisLibraryErrorKind::Hole =>
+					{
+						self.events.push_back(VorbisDecoderEvent::Hole {
+							sample_position: self.decoded_samples
+						});
+					}
+					result => break result?
+				}
+			};
 
 			if samples_read > 0 {
 				// This is not documented, but we can only assume the current bitstream number was
@@ -137,46 +264,818 @@ impl<R: Read> VorbisDecoder<R> {
 					return Err(VorbisError::UnsupportedStreamChaining);
 				}
 
-				self.last_audio_block = Some(VorbisAudioSamples::new(
+				Ok(Some(VorbisAudioSamples::new(
 					sample_buf.assume_init(),
 					(*self.ogg_vorbis_file.vi).channels as usize,
 					samples_read as usize
-				));
-
-				Ok(self.last_audio_block.as_ref())
+				)))
 			} else {
 				Ok(None)
 			}
 		}
 	}
 
-	/// Returns the number of channels of the audio signal decoded by this decoder.
+	/// Enables or disables resilient decoding, which is useful to recover as much audio as
+	/// possible from damaged streams. In resilient mode, holes in the data caused by lost or
+	/// corrupt pages don't cause errors, and decoding carries on past them. These holes,
+	/// pages that fail their CRC check, garbage data between pages and audio packets that
+	/// can't be decoded are skipped, and reported as events that can be retrieved with
+	/// [`drain_events`](Self::drain_events).
+	///
+	/// Corrupt data is found by scanning the data read from the source after enabling this
+	/// mode, which adds some overhead. Data that `vorbisfile` already buffered is not
+	/// scanned. By default, this is disabled.
+	pub fn set_resilient(&mut self, resilient: bool) -> Result<(), VorbisError> {
+		if resilient == self.resilient {
+			return Ok(());
+		}
+
+		self.decoder_source().corruption_scanner = if resilient {
+			// SAFETY: we assume ov_serialnumber follows its documented contract. The Vorbis
+			// stream information of non-chained streams lives as long as this decoder, which
+			// outlives its source
+			unsafe {
+				let stream_serial = ov_serialnumber(&mut *self.ogg_vorbis_file, -1) as i32;
+				Some(CorruptionScanner::new(
+					stream_serial,
+					self.ogg_vorbis_file.vi,
+					self.decoder_source().position
+				)?)
+			}
+		} else {
+			None
+		};
+		self.resilient = resilient;
+
+		Ok(())
+	}
+
+	/// Checks whether resilient decoding is enabled. See [`set_resilient`](Self::set_resilient)
+	/// for more details.
+	pub fn is_resilient(&self) -> bool {
+		self.resilient
+	}
+
+	/// Returns an iterator that takes the events reported by resilient decoding since the
+	/// last call, in the order they happened. See [`set_resilient`](Self::set_resilient) for
+	/// more details.
+	pub fn drain_events(&mut self) -> impl Iterator<Item = VorbisDecoderEvent> + '_ {
+		self.take_scanner_events();
+		self.events.drain(..)
+	}
+
+	/// Moves the events reported by the corruption scanner, if any, to the event queue.
+	fn take_scanner_events(&mut self) {
+		// SAFETY: the data source is valid until ov_clear is called on drop, and vorbisfile
+		// does not keep references to it between calls
+		let decoder_source =
+			unsafe { &mut *self.ogg_vorbis_file.datasource.cast::<DecoderSource<R>>() };
+
+		if let Some(corruption_scanner) = &mut decoder_source.corruption_scanner {
+			self.events.extend(corruption_scanner.take_events());
+		}
+	}
+
+	/// Returns the data source owned by `vorbisfile`.
+	fn decoder_source(&mut self) -> &mut DecoderSource<R> {
+		// SAFETY: the data source is valid until ov_clear is called on drop, and vorbisfile
+		// does not keep references to it between calls
+		unsafe { &mut *self.ogg_vorbis_file.datasource.cast::<DecoderSource<R>>() }
+	}
+
+	/// Returns the number of channels of the audio signal decoded by this decoder, which is
+	/// the number of output channels of the [channel matrix](Self::set_channel_matrix), if
+	/// any.
 	pub fn channels(&self) -> NonZeroU8 {
+		self.channel_layout().channel_count()
+	}
+
+	/// Returns the layout of the channels of the audio signal decoded by this decoder, which
+	/// is the output layout of the [channel matrix](Self::set_channel_matrix), if any.
+	pub fn channel_layout(&self) -> VorbisChannelLayout {
+		match &self.channel_matrix {
+			Some(channel_matrix) => channel_matrix.output_layout(),
+			None => VorbisChannelLayout::from_channel_count(self.source_channels())
+		}
+	}
+
+	/// Returns the number of channels of the audio signal decoded by `vorbisfile`, before
+	/// any channel conversion.
+	fn source_channels(&self) -> NonZeroU8 {
 		// SAFETY: as per the Vorbis I specification, the channel count must be greater than
 		// zero and at most 255
 		unsafe { NonZeroU8::new_unchecked((*self.ogg_vorbis_file.vi).channels as u8) }
 	}
 
-	/// Returns the sampling frequency of the audio signal decoded by this decoder.
+	/// Sets the matrix to convert the channels of the decoded audio signal with, which is
+	/// useful to downmix multichannel streams to stereo or mono, or to upmix mono streams to
+	/// stereo. The input layout of the matrix must have as many channels as the stream, and
+	/// the [channel order](Self::set_channel_order) applies to its output channels.
+	///
+	/// By default, this is `None`, which disables channel conversion.
+	pub fn set_channel_matrix(
+		&mut self,
+		channel_matrix: Option<VorbisChannelMatrix>
+	) -> Result<(), VorbisError> {
+		if let Some(channel_matrix) = &channel_matrix {
+			if channel_matrix.input_channels() != self.source_channels() {
+				return Err(VorbisError::InvalidChannelMatrix);
+			}
+		}
+
+		self.channel_matrix = channel_matrix;
+		self.update_channel_mixer();
+
+		Ok(())
+	}
+
+	/// Returns the matrix the channels of the decoded audio signal are converted with, if
+	/// any. See [`set_channel_matrix`](Self::set_channel_matrix) for more details.
+	pub fn channel_matrix(&self) -> Option<&VorbisChannelMatrix> {
+		self.channel_matrix.as_ref()
+	}
+
+	/// Sets the kind of [ReplayGain](VorbisReplayGain) information to apply to the decoded
+	/// audio signal, which is read from the `REPLAYGAIN_*` comments of the stream. When the
+	/// peak of the signal is known, the gain is reduced as needed to prevent clipping.
+	/// Streams without suitable ReplayGain comments are decoded unchanged.
+	///
+	/// By default, this is `None`, which disables applying any gain.
+	pub fn set_replay_gain_mode(&mut self, replay_gain_mode: Option<VorbisReplayGainMode>) {
+		self.replay_gain_mode = replay_gain_mode;
+		self.update_channel_mixer();
+	}
+
+	/// Returns the kind of ReplayGain information applied to the decoded audio signal, if
+	/// any. See [`set_replay_gain_mode`](Self::set_replay_gain_mode) for more details.
+	pub fn replay_gain_mode(&self) -> Option<VorbisReplayGainMode> {
+		self.replay_gain_mode
+	}
+
+	/// Replaces the channel mixer with a new one for the current channel matrix and
+	/// ReplayGain mode, whose gain is applied by scaling the matrix, or removes it if the
+	/// decoded samples don't need any change.
+	fn update_channel_mixer(&mut self) {
+		let linear_gain = self
+			.replay_gain_mode
+			.and_then(|replay_gain_mode| {
+				VorbisReplayGain::from_comments(&self.comments, replay_gain_mode)
+			})
+			.map(|replay_gain| replay_gain.linear_gain());
+
+		let channel_matrix = match (&self.channel_matrix, linear_gain) {
+			(Some(channel_matrix), _) => Some(channel_matrix.clone()),
+			(None, Some(_)) => Some(VorbisChannelMatrix::identity(self.source_channels())),
+			(None, None) => None
+		};
+
+		// The last audio block may borrow the buffers of the replaced mixer
+		self.last_audio_block = None;
+		self.channel_mixer = channel_matrix.map(|mut channel_matrix| {
+			if let Some(linear_gain) = linear_gain {
+				channel_matrix.scale(linear_gain);
+			}
+
+			ChannelMixer::new(channel_matrix)
+		});
+	}
+
+	/// Sets the order of the channels in the audio blocks returned by
+	/// [`decode_audio_block`](Self::decode_audio_block), which are remapped from the
+	/// Vorbis channel order as needed. This is useful to output audio to sinks that use
+	/// other channel orders, such as WAV files.
+	///
+	/// By default, audio blocks are returned in the Vorbis channel order.
+	pub fn set_channel_order(&mut self, channel_order: VorbisChannelOrder) {
+		self.channel_order = channel_order;
+	}
+
+	/// Returns the order of the channels in the audio blocks returned by
+	/// [`decode_audio_block`](Self::decode_audio_block).
+	pub fn channel_order(&self) -> VorbisChannelOrder {
+		self.channel_order
+	}
+
+	/// Returns the Vorbis user comments of the decoded stream, which contain its metadata.
+	///
+	/// These comments can be passed to
+	/// [`VorbisEncoderBuilder::comments`](crate::VorbisEncoderBuilder::comments) to carry
+	/// them over when transcoding.
+	pub fn comments(&self) -> &VorbisComments {
+		&self.comments
+	}
+
+	/// Returns the number of samples per channel decoded so far by
+	/// [`decode_audio_block`](Self::decode_audio_block). After it returns `Ok(None)`, this is
+	/// the exact length of the stream, with any discarded samples excluded. Seeking sets this
+	/// count to the seeked position, as if every sample before it was decoded.
+	pub fn decoded_samples(&self) -> u64 {
+		self.decoded_samples
+	}
+
+	/// Crosslaps the end of the audio decoded by `previous` into the beginning of the audio
+	/// that this decoder will decode next, blending them with the Vorbis MDCT windows to
+	/// avoid clicks when switching from one stream to another. This is useful to switch
+	/// tracks or loop audio seamlessly.
+	///
+	/// Both decoders must have been decoding their streams, and the next samples decoded by
+	/// `previous`, if any, are not affected. The decoders may differ in sampling frequency
+	/// and channel count, in which case the lapping is done on a best-effort basis.
+	///
+	/// This is a facade for the `ov_crosslap` function.
+	pub fn crosslap_from<P: Read>(
+		&mut self,
+		previous: &mut VorbisDecoder<P>
+	) -> Result<(), VorbisError> {
+		// SAFETY: we assume ov_crosslap follows its documented contract. Both files are
+		// open, as they were opened on decoder creation, and closed only on drop
+		unsafe {
+			vorbisfile_return_value_to_result!(ov_crosslap(
+				&mut *previous.ogg_vorbis_file,
+				&mut *self.ogg_vorbis_file
+			))?;
+		}
+
+		Ok(())
+	}
+
+	/// Enables or disables half-rate decoding, which decodes the stream at half its sampling
+	/// frequency for much less CPU usage, at the cost of losing the upper half of its audio
+	/// spectrum. When enabled, every sample count and position, including the ones reported by
+	/// [`decoded_samples`](Self::decoded_samples) and the
+	/// [`sampling_frequency`](Self::sampling_frequency), refer to the half-rate signal.
+	///
+	/// This setting is best changed before decoding any audio. Otherwise, the decoder state is
+	/// reset, which for decoders not created with [`new_seekable`](VorbisDecoder::new_seekable)
+	/// causes a brief discontinuity in the decoded audio. Streams with too small blocks or
+	/// sampled at 1 Hz can't be decoded at half rate, causing an error to be returned.
+	///
+	/// This is a facade for the `ov_halfrate` function.
+	pub fn set_half_rate(&mut self, half_rate: bool) -> Result<(), VorbisError> {
+		// ov_halfrate resets the decoder state even if the setting does not change, so
+		// don't cause a needless discontinuity
+		if half_rate == self.half_rate {
+			return Ok(());
+		}
+
+		// Halving a sampling frequency of 1 Hz would yield an invalid sampling frequency of
+		// 0 Hz. libvorbis does not check for this, so reject such streams here
+		// SAFETY: vi is valid as long as the decoder is
+		if half_rate && unsafe { (*self.ogg_vorbis_file.vi).rate } < 2 {
+			return Err(VorbisLibraryError {
+				library: VorbisLibrary::VorbisFile,
+				function: "ov_halfrate",
+				kind: VorbisLibraryErrorKind::NotImplemented
+			}
+			.into());
+		}
+
+		// SAFETY: we assume ov_halfrate follows its documented contract
+		unsafe {
+			vorbisfile_return_value_to_result!(ov_halfrate(
+				&mut *self.ogg_vorbis_file,
+				half_rate.into()
+			))?;
+		}
+
+		let source_position = self.source_position(self.decoded_samples);
+
+		self.half_rate = half_rate;
+		#[cfg(feature = "resampling")]
+		self.update_resampler();
+
+		self.decoded_samples = self.output_position(if half_rate {
+			source_position / 2
+		} else {
+			source_position * 2
+		});
+
+		Ok(())
+	}
+
+	/// Checks whether half-rate decoding is enabled. See [`set_half_rate`](Self::set_half_rate)
+	/// for more details.
+	pub fn is_half_rate(&self) -> bool {
+		self.half_rate
+	}
+
+	/// Sets the sampling frequency to resample the decoded audio signal to, in Hertz (Hz).
+	/// Every sample count and position, including the ones reported by
+	/// [`decoded_samples`](Self::decoded_samples) and the
+	/// [`sampling_frequency`](Self::sampling_frequency), refer to the resampled signal.
+	///
+	/// This setting is best changed before decoding any audio, as changing it discards any
+	/// samples buffered by the resampler. By default, this is `None`, which disables
+	/// resampling.
+	#[cfg(feature = "resampling")]
+	pub fn set_output_sampling_frequency(&mut self, output_sampling_frequency: Option<NonZeroU32>) {
+		let source_position = self.source_position(self.decoded_samples);
+
+		self.output_sampling_frequency = output_sampling_frequency;
+		self.update_resampler();
+
+		self.decoded_samples = self.output_position(source_position);
+	}
+
+	/// Returns the sampling frequency of the audio signal decoded by this decoder, which is
+	/// halved when [half-rate decoding](Self::set_half_rate) is enabled, or the
+	/// output sampling frequency when resampling.
 	pub fn sampling_frequency(&self) -> NonZeroU32 {
-		// SAFETY: as per the Vorbis I specification, the sampling frequency must be greater
-		// than zero and fit in an unsigned 32-bit integer
-		unsafe { NonZeroU32::new_unchecked((*self.ogg_vorbis_file.vi).rate as u32) }
+		#[cfg(feature = "resampling")]
+		if let Some(output_sampling_frequency) = self.output_sampling_frequency {
+			return output_sampling_frequency;
+		}
+
+		self.source_sampling_frequency()
+	}
+
+	/// Returns the sampling frequency of the audio signal decoded by `vorbisfile`, before
+	/// any resampling.
+	fn source_sampling_frequency(&self) -> NonZeroU32 {
+		// SAFETY: vi is valid as long as the decoder is. As per the Vorbis I specification,
+		// the sampling frequency must be greater than zero and fit in an unsigned 32-bit
+		// integer, and set_half_rate rejects streams sampled at 1 Hz, so this should never
+		// be zero. Fall back to 1 Hz anyway, as it's not worth trusting the stream on this
+		let sampling_frequency = unsafe { (*self.ogg_vorbis_file.vi).rate } as u32;
+		NonZeroU32::new(sampling_frequency >> u32::from(self.half_rate)).unwrap_or(NonZeroU32::MIN)
+	}
+
+	/// Replaces the resampler with a new one for the current source and output sampling
+	/// frequencies, or removes it if no resampling is needed.
+	#[cfg(feature = "resampling")]
+	fn update_resampler(&mut self) {
+		let source_sampling_frequency = self.source_sampling_frequency();
+
+		self.resampler = self
+			.output_sampling_frequency
+			.filter(|output_sampling_frequency| {
+				*output_sampling_frequency != source_sampling_frequency
+			})
+			.map(|output_sampling_frequency| {
+				AudioResampler::new(
+					source_sampling_frequency,
+					output_sampling_frequency,
+					self.source_channels().get().into()
+				)
+			});
+	}
+
+	/// Converts a sample position of the signal decoded by `vorbisfile` to a position of the
+	/// output signal, which differ when resampling.
+	#[cfg_attr(not(feature = "resampling"), allow(clippy::unused_self))]
+	fn output_position(&self, source_position: u64) -> u64 {
+		#[cfg(feature = "resampling")]
+		if let Some(resampler) = &self.resampler {
+			return (source_position as f64 * resampler.ratio()).round() as u64;
+		}
+
+		source_position
+	}
+
+	/// Converts a sample position of the output signal to a position of the signal decoded
+	/// by `vorbisfile`, which differ when resampling.
+	#[cfg_attr(not(feature = "resampling"), allow(clippy::unused_self))]
+	fn source_position(&self, output_position: u64) -> u64 {
+		#[cfg(feature = "resampling")]
+		if let Some(resampler) = &self.resampler {
+			return (output_position as f64 / resampler.ratio()).round() as u64;
+		}
+
+		output_position
 	}
 }
 
+impl<R: Read + Seek> VorbisDecoder<R> {
+	/// Creates a new Vorbis decoder that will read an Ogg Vorbis stream from the specified
+	/// seekable source, which enables seeking within the stream.
+	///
+	/// In addition to the header data read by [`new`](Self::new), `vorbisfile` scans the
+	/// whole source to find the boundaries of the stream, which may take some time for large
+	/// sources with slow I/O. I/O errors that might happen during these operations will be
+	/// returned to the caller.
+	pub fn new_seekable<S: Into<Box<R>>>(source: S) -> Result<Self, VorbisError> {
+		Self::open(
+			*source.into(),
+			None,
+			Some(seek_func::<R>),
+			Some(tell_func::<R>)
+		)
+	}
+
+	/// Like [`new_seekable`](Self::new_seekable), but rejects streams that exceed the
+	/// specified limits, as explained for [`new_with_limits`](Self::new_with_limits).
+	pub fn new_seekable_with_limits<S: Into<Box<R>>>(
+		source: S,
+		limits: &VorbisDecoderLimits
+	) -> Result<Self, VorbisError> {
+		Self::open(
+			*source.into(),
+			Some(*limits),
+			Some(seek_func::<R>),
+			Some(tell_func::<R>)
+		)
+	}
+
+	/// Returns the total number of samples per channel of the stream, with any samples
+	/// discarded at its beginning and end excluded, which is the
+	/// [`decoded_samples`](Self::decoded_samples) count after decoding it whole. This
+	/// decoder must have been created with [`new_seekable`](Self::new_seekable), as
+	/// `vorbisfile` finds this length when it scans the source.
+	///
+	/// This is a facade for the `ov_pcm_total` function.
+	pub fn total_samples(&mut self) -> Result<u64, VorbisError> {
+		// SAFETY: we assume ov_pcm_total follows its documented contract
+		let total_samples = unsafe {
+			vorbisfile_return_value_to_result!(ov_pcm_total(&mut *self.ogg_vorbis_file, -1))?
+		};
+
+		Ok(self.output_position(u64::try_from(total_samples)? >> u32::from(self.half_rate)))
+	}
+
+	/// Seeks to the specified sample position, counted from the first sample of the stream,
+	/// so that the next decoded block starts at that sample. This decoder must have been
+	/// created with [`new_seekable`](Self::new_seekable).
+	///
+	/// If a [seek table](Self::set_seek_table) is set, this decoder seeks to the last seek
+	/// point before the position and discards the samples decoded until it, which takes a
+	/// single seek of the source. Otherwise, this is a facade for the `ov_pcm_seek` function,
+	/// which bisects the source to find the position.
+	pub fn seek(&mut self, sample_position: u64) -> Result<(), VorbisError> {
+		let full_rate_position = self.full_rate_position(sample_position)?;
+
+		if !self.seek_with_table(full_rate_position)? {
+			// SAFETY: we assume ov_pcm_seek follows its documented contract
+			unsafe {
+				vorbisfile_return_value_to_result!(ov_pcm_seek(
+					&mut *self.ogg_vorbis_file,
+					full_rate_position
+				))?;
+			}
+		}
+
+		self.update_decoded_samples();
+		Ok(())
+	}
+
+	/// Seeks to the specified full-rate sample position with the seek table, if any,
+	/// returning whether it did. Positions the seek table can't help to reach, such as
+	/// positions past the end of the stream, are left for `ov_pcm_seek` to handle.
+	fn seek_with_table(&mut self, full_rate_position: i64) -> Result<bool, VorbisError> {
+		let Some(seek_table) = &self.seek_table else {
+			return Ok(false);
+		};
+
+		if self.ogg_vorbis_file.seekable == 0 {
+			return Ok(false);
+		}
+
+		// SAFETY: seekable streams have their PCM offsets and lengths computed when
+		// opened, and the Vorbis stream information of non-chained streams lives as long
+		// as this decoder. We assume vorbis_info_blocksize follows its documented contract
+		let (start_granule_position, long_blocksize) = unsafe {
+			(
+				*self.ogg_vorbis_file.pcmlengths,
+				vorbis_info_blocksize(self.ogg_vorbis_file.vi, 1)
+			)
+		};
+
+		// The first packet after a seek point only primes the decoder, so up to half a
+		// long block of samples after its granule position can't be decoded
+		let byte_offset = u64::try_from(
+			full_rate_position
+				.saturating_add(start_granule_position)
+				.saturating_sub(i64::from(long_blocksize / 2))
+		)
+		.ok()
+		.and_then(|granule_position| seek_table.seek_point_before(granule_position))
+		.map_or(0, |seek_point| seek_point.byte_offset);
+
+		// SAFETY: we assume ov_raw_seek and ov_pcm_tell follow their documented contract
+		unsafe {
+			vorbisfile_return_value_to_result!(ov_raw_seek(
+				&mut *self.ogg_vorbis_file,
+				i64::try_from(byte_offset)?
+			))?;
+		}
+		let mut position = unsafe { ov_pcm_tell(&mut *self.ogg_vorbis_file) };
+		if position > full_rate_position {
+			return Ok(false);
+		}
+
+		let mut sample_buf = MaybeUninit::uninit();
+		while position < full_rate_position {
+			let samples_to_discard = ((full_rate_position - position) >> u32::from(self.half_rate))
+				.clamp(1, 4096) as c_int;
+
+			// SAFETY: we assume ov_read_float follows its documented contract. The
+			// returned samples are discarded without reading them
+			match unsafe {
+				vorbisfile_return_value_to_result!(ov_read_float(
+					&mut *self.ogg_vorbis_file,
+					sample_buf.as_mut_ptr(),
+					samples_to_discard,
+					ptr::null_mut()
+				))
+			} {
+				Ok(0) => return Ok(false),
+				// ov_pcm_seek ignores holes while decoding up to the position too
+				Ok(_) => {}
+				Err(VorbisError::LibraryError(err))
+					if err.kind() == VorbisLibraryErrorKind::Hole => {}
+				Err(err) => return Err(err)
+			}
+			self.take_scanner_events();
+
+			// SAFETY: we assume ov_pcm_tell follows its documented contract
+			position = unsafe { ov_pcm_tell(&mut *self.ogg_vorbis_file) };
+		}
+
+		Ok(true)
+	}
+
+	/// Sets the seek table [`seek`](Self::seek) uses to seek with a single seek of the
+	/// source, instead of bisecting it with many reads, which is much faster for slow or
+	/// remote sources. The seek table must have been built for the decoded stream, or a
+	/// [`VorbisError::MismatchedSeekTable`] error is returned. By default, no seek table is
+	/// set.
+	///
+	/// Seeking with lapping is not affected by the seek table.
+	pub fn set_seek_table(
+		&mut self,
+		seek_table: Option<VorbisSeekTable>
+	) -> Result<(), VorbisError> {
+		if let Some(seek_table) = &seek_table {
+			// SAFETY: we assume ov_serialnumber follows its documented contract
+			let stream_serial = unsafe { ov_serialnumber(&mut *self.ogg_vorbis_file, -1) } as i32;
+
+			if seek_table.stream_serial() != stream_serial {
+				return Err(VorbisError::MismatchedSeekTable);
+			}
+		}
+
+		self.seek_table = seek_table;
+		Ok(())
+	}
+
+	/// Returns the seek table set for this decoder, if any. See
+	/// [`set_seek_table`](Self::set_seek_table) for more details.
+	pub fn seek_table(&self) -> Option<&VorbisSeekTable> {
+		self.seek_table.as_ref()
+	}
+
+	/// Like [`seek`](Self::seek), but crosslaps the audio decoded before seeking into the
+	/// audio decoded after it, avoiding the clicks an abrupt discontinuity would cause.
+	///
+	/// This is a facade for the `ov_pcm_seek_lap` function.
+	pub fn seek_lap(&mut self, sample_position: u64) -> Result<(), VorbisError> {
+		// SAFETY: we assume ov_pcm_seek_lap follows its documented contract
+		unsafe {
+			vorbisfile_return_value_to_result!(ov_pcm_seek_lap(
+				&mut *self.ogg_vorbis_file,
+				self.full_rate_position(sample_position)?
+			))?;
+		}
+
+		self.update_decoded_samples();
+		Ok(())
+	}
+
+	/// Like [`seek_lap`](Self::seek_lap), but takes a position in seconds instead of
+	/// samples.
+	///
+	/// This is a facade for the `ov_time_seek_lap` function.
+	pub fn time_seek_lap(&mut self, seconds: f64) -> Result<(), VorbisError> {
+		// SAFETY: we assume ov_time_seek_lap follows its documented contract
+		unsafe {
+			vorbisfile_return_value_to_result!(ov_time_seek_lap(
+				&mut *self.ogg_vorbis_file,
+				seconds
+			))?;
+		}
+
+		self.update_decoded_samples();
+		Ok(())
+	}
+
+	/// Sets the decoded samples count to the current position of `vorbisfile` after seeking.
+	fn update_decoded_samples(&mut self) {
+		// SAFETY: we assume ov_pcm_tell follows its documented contract
+		let position = unsafe { ov_pcm_tell(&mut *self.ogg_vorbis_file) };
+		self.decoded_samples =
+			self.output_position(u64::try_from(position).unwrap_or(0) >> u32::from(self.half_rate));
+
+		// Samples buffered by the resampler belong to the signal before seeking
+		#[cfg(feature = "resampling")]
+		if let Some(resampler) = &mut self.resampler {
+			resampler.reset();
+		}
+	}
+
+	/// Converts a sample position of the decoded signal to a position of the full-rate
+	/// signal, which is what `vorbisfile` seeking functions expect.
+	fn full_rate_position(&self, sample_position: u64) -> Result<i64, VorbisError> {
+		// Positions that saturate are past the end of any stream, so they still fail to seek
+		Ok(i64::try_from(self.source_position(sample_position))?
+			.saturating_mul(if self.half_rate { 2 } else { 1 }))
+	}
+}
+
+impl<B: AsRef<[u8]>> VorbisDecoder<Cursor<B>> {
+	/// Creates a new Vorbis decoder that will read an Ogg Vorbis stream from the specified
+	/// bytes, which enables seeking within the stream. The bytes can be borrowed or owned
+	/// by any type that can be viewed as a byte slice, such as `&[u8]`, `Vec<u8>`,
+	/// `Arc<[u8]>`, or a memory map of a file.
+	///
+	/// This is a shorthand for [`new_seekable`](Self::new_seekable) with a [`Cursor`] over
+	/// the bytes, so `vorbisfile` copies the bytes it reads into its internal buffer, as it
+	/// does for any source. Memory maps are only read from as `vorbisfile` requests data, so
+	/// only the pages of the file that are decoded or sought over are loaded in memory.
+	pub fn new_from_bytes(bytes: B) -> Result<Self, VorbisError> {
+		Self::open(
+			Cursor::new(bytes),
+			None,
+			Some(seek_func::<Cursor<B>>),
+			Some(tell_func::<Cursor<B>>)
+		)
+	}
+
+	/// Like [`new_from_bytes`](Self::new_from_bytes), but rejects streams that exceed the
+	/// specified limits, as explained for [`new_with_limits`](Self::new_with_limits).
+	pub fn new_from_bytes_with_limits(
+		bytes: B,
+		limits: &VorbisDecoderLimits
+	) -> Result<Self, VorbisError> {
+		Self::open(
+			Cursor::new(bytes),
+			Some(*limits),
+			Some(seek_func::<Cursor<B>>),
+			Some(tell_func::<Cursor<B>>)
+		)
+	}
+}
+
+impl<S: VorbisRandomAccessSource> VorbisDecoder<VorbisCachedSource<S>> {
+	/// Creates a new Vorbis decoder that will read an Ogg Vorbis stream from the specified
+	/// random-access source, which enables seeking within the stream. The source is read
+	/// through a [`VorbisCachedSource`] with the default settings, so that the source
+	/// regions `vorbisfile` reads several times are only read once.
+	///
+	/// This is a shorthand for [`new_seekable`](Self::new_seekable) with a
+	/// [`VorbisCachedSource`].
+	pub fn new_random_access(source: S) -> Result<Self, VorbisError> {
+		Self::new_seekable(VorbisCachedSource::new(source))
+	}
+}
+
+// SAFETY: vorbisfile keeps no thread-local or global mutable state, so the decoding
+// state can be used from any thread. That state owns the source, which is read and
+// sought through it as if it was a `Box<R>`, so thread safety is bounded on it just like
+// for that box. The rest of the data source state only points to memory owned by the
+// decoder. Methods that take a shared reference to the decoder only read fields of the
+// decoding state
+unsafe impl<R: Read + Send> Send for VorbisDecoder<R> {}
+unsafe impl<R: Read + Sync> Sync for VorbisDecoder<R> {}
+
 impl<R: Read> Drop for VorbisDecoder<R> {
 	fn drop(&mut self) {
 		unsafe { ov_clear(&mut *self.ogg_vorbis_file) };
 	}
 }
 
+/// The data source handed over to `vorbisfile`, which wraps a decoder source to check
+/// the header data read from it against the decoder limits, if any.
+struct DecoderSource<R> {
+	source: R,
+	position: u64,
+	header_validator: Option<HeaderValidator>,
+	header_error: Option<VorbisError>,
+	corruption_scanner: Option<CorruptionScanner>
+}
+
+/// Reads data from a source for `vorbisfile`. This callback should match the stdio `fread`
+/// behavior. See: <https://man7.org/linux/man-pages/man3/fread.3p.html>
+unsafe extern "C" fn read_func<R: Read>(
+	ptr: *mut c_void,
+	size: usize,
+	count: usize,
+	datasource: *mut c_void
+) -> usize {
+	let source = unsafe { &mut *(datasource.cast::<DecoderSource<R>>()) };
+	let buf = unsafe { slice::from_raw_parts_mut(ptr.cast(), size * count) };
+
+	if source.header_error.is_some() {
+		set_errno(Errno(i32::MAX));
+		return 0;
+	}
+
+	match source.source.read(buf) {
+		Ok(n) => {
+			// Data is checked before vorbisfile gets it, so it never gets a complete page
+			// of a header that exceeds the limits
+			if let Some(header_validator) = &mut source.header_validator {
+				match header_validator.validate(&buf[..n]) {
+					Ok(false) => {}
+					Ok(true) => source.header_validator = None,
+					Err(err) => {
+						source.header_error = Some(err);
+						set_errno(Errno(i32::MAX));
+						return 0;
+					}
+				}
+			}
+
+			source.position += n as u64;
+			if let Some(corruption_scanner) = &mut source.corruption_scanner {
+				if n == 0 {
+					corruption_scanner.finish();
+				} else {
+					corruption_scanner.scan(&buf[..n]);
+				}
+			}
+
+			n / size
+		}
+		Err(err) => {
+			// vorbisfile checks errno to tell EOF apart from read errors:
+			// https://xiph.org/vorbis/doc/vorbisfile/callbacks.html
+			// Rust Read trait implementations are not required to set
+			// errno, so make sure we set errno with the most informative
+			// value possible, falling back to a non-zero errno, which is
+			// implied by the C standard to signal some condition
+			set_errno(Errno(err.raw_os_error().unwrap_or(i32::MAX)));
+
+			0
+		}
+	}
+}
+
+/// Seeks a source for `vorbisfile`. This callback should match the stdio `fseek` behavior.
+/// See: <https://man7.org/linux/man-pages/man3/fseek.3p.html>
+unsafe extern "C" fn seek_func<R: Seek>(
+	datasource: *mut c_void,
+	offset: ogg_int64_t,
+	whence: c_int
+) -> c_int {
+	let source = unsafe { &mut *(datasource.cast::<DecoderSource<R>>()) };
+	let seek_from = match whence {
+		0 => u64::try_from(offset).map(SeekFrom::Start).ok(),
+		1 => Some(SeekFrom::Current(offset)),
+		2 => Some(SeekFrom::End(offset)),
+		_ => None
+	};
+
+	match seek_from.map(|seek_from| source.source.seek(seek_from)) {
+		Some(Ok(position)) => {
+			source.position = position;
+			if let Some(corruption_scanner) = &mut source.corruption_scanner {
+				corruption_scanner.seek(position);
+			}
+
+			0
+		}
+		_ => -1
+	}
+}
+
+/// Returns the current position of a source for `vorbisfile`. This callback should match the
+/// stdio `ftell` behavior. See: <https://man7.org/linux/man-pages/man3/ftell.3p.html>
+unsafe extern "C" fn tell_func<R: Seek>(datasource: *mut c_void) -> c_long {
+	let source = unsafe { &mut (*datasource.cast::<DecoderSource<R>>()).source };
+	source
+		.stream_position()
+		.ok()
+		.and_then(|position| position.try_into().ok())
+		.unwrap_or(-1)
+}
+
+/// Drops a source when it's no longer needed by `vorbisfile`. This is called by `ov_clear`.
+unsafe extern "C" fn close_func<R: Read>(datasource: *mut c_void) -> c_int {
+	drop(unsafe { Box::from_raw(datasource.cast::<DecoderSource<R>>()) });
+
+	0
+}
+
 #[cfg(test)]
 mod test {
-	use std::io::{self, ErrorKind, Read};
+	use std::{
+		io::{self, Cursor, ErrorKind, Read},
+		num::{NonZeroU8, NonZeroU32, NonZeroU64},
+		sync::Arc,
+		thread
+	};
 
 	use super::VorbisDecoder;
-	use crate::{VorbisError, VorbisLibraryError, VorbisLibraryErrorKind};
+	use crate::{
+		VorbisChannelLayout, VorbisChannelMatrix, VorbisChannelOrder, VorbisComments,
+		VorbisDecoderEvent, VorbisDecoderLimit, VorbisDecoderLimits, VorbisEncoder,
+		VorbisEncoderBuilder, VorbisError, VorbisLibraryError, VorbisLibraryErrorKind,
+		VorbisLoudnessMeter, VorbisPicture, VorbisPictureType, VorbisReplayGain,
+		VorbisReplayGainMode, VorbisSeekTable,
+		common::test_util::{
+			encode_mono, encode_tone, mono_encoder_builder, noise, page_offsets, tone
+		},
+		decoder::decoder_source::MockRangeServer
+	};
 
 	#[test]
 	fn decoder_handles_io_failures() {
@@ -206,4 +1105,482 @@ mod test {
 			result.err()
 		);
 	}
+
+	#[test]
+	fn decoder_reads_encoded_comments() {
+		let mut comments = VorbisComments::new();
+		comments
+			.add("TITLE", "Sine wave")
+			.unwrap()
+			.add("ARTIST", "vorbis-rs")
+			.unwrap()
+			.add_picture(&VorbisPicture::new(
+				VorbisPictureType::FrontCover,
+				"image/png",
+				vec![1, 2, 3]
+			))
+			.unwrap();
+
+		let mut encoded_ogg = vec![];
+		mono_encoder_builder(&mut encoded_ogg, 0)
+			.comments(comments.clone())
+			.build()
+			.unwrap()
+			.finish()
+			.unwrap();
+
+		let decoder = VorbisDecoder::<&[u8]>::new(&*encoded_ogg).unwrap();
+		let decoded_comments = decoder.comments();
+
+		assert!(!decoded_comments.vendor().is_empty());
+		assert!(decoded_comments.iter().eq(comments.iter()));
+		assert_eq!(decoded_comments.pictures().count(), 1);
+	}
+
+	#[test]
+	fn decoder_honors_priming_and_total_samples() {
+		let samples = tone(16000, 0.5);
+
+		let mut encoded_ogg = vec![];
+		let mut encoder = mono_encoder_builder(&mut encoded_ogg, 0)
+			.priming_samples(1234)
+			.total_samples(Some(9876))
+			.build()
+			.unwrap();
+		encoder.encode_audio_block([&samples]).unwrap();
+		encoder.finish().unwrap();
+
+		let mut decoder = VorbisDecoder::<&[u8]>::new(&*encoded_ogg).unwrap();
+		while decoder.decode_audio_block().unwrap().is_some() {}
+
+		assert_eq!(decoder.decoded_samples(), 9876);
+
+		let mut decoder = VorbisDecoder::new_seekable(Cursor::new(&*encoded_ogg)).unwrap();
+		assert_eq!(decoder.total_samples().unwrap(), 9876);
+	}
+
+	#[test]
+	fn decoder_seeks_and_crosslaps() {
+		let encoded_ogg = encode_tone(16000, 0);
+
+		let mut decoder = VorbisDecoder::new_seekable(Cursor::new(&*encoded_ogg)).unwrap();
+		decoder.decode_audio_block().unwrap();
+		decoder.seek_lap(12000).unwrap();
+		assert_eq!(decoder.decoded_samples(), 12000);
+
+		let mut next_decoder = VorbisDecoder::new_seekable(Cursor::new(&*encoded_ogg)).unwrap();
+		next_decoder.crosslap_from(&mut decoder).unwrap();
+		while next_decoder.decode_audio_block().unwrap().is_some() {}
+
+		assert_eq!(next_decoder.decoded_samples(), 16000);
+	}
+
+	#[test]
+	fn decoder_decodes_from_bytes() {
+		let encoded_ogg = encode_tone(16000, 0);
+
+		let mut decoder = VorbisDecoder::new_from_bytes(&*encoded_ogg).unwrap();
+		decoder.seek(12000).unwrap();
+		while decoder.decode_audio_block().unwrap().is_some() {}
+		assert_eq!(decoder.decoded_samples(), 16000);
+
+		// Owned bytes, such as memory maps, work too
+		let mut owned_decoder = VorbisDecoder::new_from_bytes_with_limits(
+			Arc::<[u8]>::from(&*encoded_ogg),
+			VorbisDecoderLimits::new().maximum_channels(NonZeroU8::new(1))
+		)
+		.unwrap();
+		owned_decoder.seek(12000).unwrap();
+		while owned_decoder.decode_audio_block().unwrap().is_some() {}
+		assert_eq!(owned_decoder.decoded_samples(), 16000);
+	}
+
+	#[test]
+	fn decoder_seeks_with_seek_tables() {
+		let encoded_ogg = encode_tone(40_000, 0);
+
+		let seek_table =
+			VorbisSeekTable::scan(&*encoded_ogg, NonZeroU64::new(4000).unwrap()).unwrap();
+		assert!(seek_table.seek_points().len() > 1);
+		let seek_table = VorbisSeekTable::from_bytes(&seek_table.to_bytes()).unwrap();
+
+		let mut decoder = VorbisDecoder::new_seekable(Cursor::new(&*encoded_ogg)).unwrap();
+		let mut table_decoder = VorbisDecoder::new_seekable(Cursor::new(&*encoded_ogg)).unwrap();
+		table_decoder.set_seek_table(Some(seek_table)).unwrap();
+
+		for sample_position in [30_123, 0, 15_000, 4001] {
+			decoder.seek(sample_position).unwrap();
+			table_decoder.seek(sample_position).unwrap();
+			assert_eq!(table_decoder.decoded_samples(), sample_position);
+
+			let audio_block = decoder.decode_audio_block().unwrap().unwrap().samples()[0].to_vec();
+			let table_audio_block = table_decoder
+				.decode_audio_block()
+				.unwrap()
+				.unwrap()
+				.samples()[0];
+			let compared_samples = audio_block.len().min(table_audio_block.len());
+			assert_eq!(
+				audio_block[..compared_samples],
+				table_audio_block[..compared_samples]
+			);
+		}
+
+		assert!(table_decoder.seek(40_001).is_err());
+
+		let mut other_encoded_ogg = vec![];
+		mono_encoder_builder(&mut other_encoded_ogg, 1)
+			.build()
+			.unwrap()
+			.finish()
+			.unwrap();
+		let other_seek_table =
+			VorbisSeekTable::scan(&*other_encoded_ogg, NonZeroU64::new(4000).unwrap()).unwrap();
+		assert!(matches!(
+			table_decoder.set_seek_table(Some(other_seek_table)),
+			Err(VorbisError::MismatchedSeekTable)
+		));
+	}
+
+	#[test]
+	fn decoder_seeks_on_random_access_sources() {
+		let encoded_ogg = encode_tone(40_000, 0);
+
+		let mut mock_range_server = MockRangeServer {
+			data: encoded_ogg,
+			requested_ranges: vec![]
+		};
+		let mut decoder = VorbisDecoder::new_random_access(&mut mock_range_server).unwrap();
+		decoder.seek(30_000).unwrap();
+		while decoder.decode_audio_block().unwrap().is_some() {}
+		decoder.seek(1000).unwrap();
+		assert_eq!(decoder.decoded_samples(), 1000);
+		drop(decoder);
+
+		// The whole stream fits in a single cached chunk, so every byte is requested once
+		assert!(
+			mock_range_server
+				.requested_ranges
+				.windows(2)
+				.all(|ranges| ranges[0].0 < ranges[1].0)
+		);
+	}
+
+	#[test]
+	fn decoder_decodes_at_half_rate() {
+		let samples = vec![0.25; 16000];
+
+		let encoded_ogg = encode_mono(&samples, 0);
+
+		let mut decoder = VorbisDecoder::<&[u8]>::new(&*encoded_ogg).unwrap();
+		decoder.set_half_rate(true).unwrap();
+		while decoder.decode_audio_block().unwrap().is_some() {}
+
+		assert!(decoder.is_half_rate());
+		assert_eq!(decoder.sampling_frequency().get(), 4000);
+		assert!(decoder.decoded_samples().abs_diff(8000) <= 1);
+	}
+
+	#[test]
+	fn decoder_converts_channels() {
+		let samples = vec![0.25; 16000];
+
+		let mut encoded_ogg = vec![];
+		let mut encoder = mono_encoder_builder(&mut encoded_ogg, 0)
+			.channel_matrix(Some(
+				VorbisChannelMatrix::new(VorbisChannelLayout::Mono, VorbisChannelLayout::Stereo)
+					.unwrap()
+			))
+			.build()
+			.unwrap();
+		assert_eq!(encoder.channel_layout(), VorbisChannelLayout::Stereo);
+		encoder.encode_audio_block([&samples]).unwrap();
+		encoder.finish().unwrap();
+
+		let mut decoder = VorbisDecoder::<&[u8]>::new(&*encoded_ogg).unwrap();
+		assert_eq!(decoder.channels().get(), 2);
+		assert!(
+			decoder
+				.set_channel_matrix(Some(
+					VorbisChannelMatrix::new(
+						VorbisChannelLayout::FivePointOne,
+						VorbisChannelLayout::Stereo
+					)
+					.unwrap()
+				))
+				.is_err()
+		);
+
+		decoder
+			.set_channel_matrix(Some(
+				VorbisChannelMatrix::new(VorbisChannelLayout::Stereo, VorbisChannelLayout::Mono)
+					.unwrap()
+			))
+			.unwrap();
+		assert_eq!(decoder.channel_layout(), VorbisChannelLayout::Mono);
+
+		while let Some(audio_block) = decoder.decode_audio_block().unwrap() {
+			assert_eq!(audio_block.samples().len(), 1);
+		}
+		assert_eq!(decoder.decoded_samples(), 16000);
+	}
+
+	#[test]
+	fn decoder_applies_encoded_replay_gain() {
+		let samples = tone(16000, 0.5);
+
+		let mut encoder = mono_encoder_builder(Cursor::new(vec![]), 0)
+			.replay_gain_tags(true)
+			.build()
+			.unwrap();
+		encoder.encode_audio_block([&samples]).unwrap();
+		let encoded_ogg = encoder.finish().unwrap().into_inner();
+
+		let mut decoder = VorbisDecoder::<&[u8]>::new(&*encoded_ogg).unwrap();
+		let replay_gain =
+			VorbisReplayGain::from_comments(decoder.comments(), VorbisReplayGainMode::Track)
+				.unwrap();
+		assert!(replay_gain.gain < -5.0);
+		assert!((replay_gain.peak.unwrap() - 0.5).abs() < 0.01);
+
+		decoder.set_replay_gain_mode(Some(VorbisReplayGainMode::Track));
+		let mut decoded_peak = 0.0_f32;
+		while let Some(audio_block) = decoder.decode_audio_block().unwrap() {
+			for sample in audio_block.samples()[0] {
+				decoded_peak = decoded_peak.max(sample.abs());
+			}
+		}
+
+		assert!(decoded_peak < 0.5 * replay_gain.linear_gain() * 1.2);
+	}
+
+	#[test]
+	fn decoder_decodes_loudness_normalized_audio() {
+		let samples = tone(32000, 0.05);
+
+		let encoded_ogg = mono_encoder_builder(vec![], 0)
+			.encode_normalized(-16.0, || Ok([Ok([&samples])]))
+			.unwrap();
+
+		let mut decoder = VorbisDecoder::<&[u8]>::new(&*encoded_ogg).unwrap();
+		let mut loudness_meter = VorbisLoudnessMeter::new(
+			decoder.sampling_frequency(),
+			decoder.channel_layout(),
+			VorbisChannelOrder::Vorbis
+		);
+		while let Some(audio_block) = decoder.decode_audio_block().unwrap() {
+			loudness_meter.process(audio_block.samples()).unwrap();
+		}
+
+		let loudness = loudness_meter.integrated_loudness().unwrap();
+		assert!(
+			(loudness + 16.0).abs() < 1.0,
+			"Unexpected loudness: {loudness}"
+		);
+	}
+
+	#[test]
+	fn encoders_and_decoders_are_thread_safe() {
+		fn assert_send_sync<T: Send + Sync>() {}
+
+		assert_send_sync::<VorbisEncoder<Vec<u8>>>();
+		assert_send_sync::<VorbisEncoderBuilder<Vec<u8>>>();
+		assert_send_sync::<VorbisDecoder<&[u8]>>();
+		assert_send_sync::<VorbisDecoder<Cursor<Vec<u8>>>>();
+	}
+
+	#[test]
+	fn encoders_and_decoders_run_on_spawned_threads() {
+		let workers = (1..=4u8)
+			.map(|channels| {
+				let channels = NonZeroU8::new(channels).unwrap();
+				let samples = vec![tone(8000, 0.5); channels.get().into()];
+
+				// Build the encoder on this thread, and finish it on another
+				let mut encoder = VorbisEncoderBuilder::new_with_serial(
+					NonZeroU32::new(8000).unwrap(),
+					channels,
+					vec![],
+					channels.get().into()
+				)
+				.build()
+				.unwrap();
+
+				thread::spawn(move || {
+					encoder.encode_audio_block(&samples).unwrap();
+					encoder.finish().unwrap()
+				})
+			})
+			.collect::<Vec<_>>();
+
+		let workers = workers
+			.into_iter()
+			.map(|worker| {
+				// Create the decoder on this thread, and decode on another
+				let mut decoder = VorbisDecoder::new(Cursor::new(worker.join().unwrap())).unwrap();
+
+				thread::spawn(move || {
+					while decoder.decode_audio_block().unwrap().is_some() {}
+					(decoder.channels().get(), decoder.decoded_samples())
+				})
+			})
+			.collect::<Vec<_>>();
+
+		for (channels, worker) in (1..=4).zip(workers) {
+			assert_eq!(worker.join().unwrap(), (channels, 8000));
+		}
+	}
+
+	#[test]
+	fn decoder_rejects_streams_over_limits() {
+		let samples = vec![vec![0.25; 8000]; 2];
+		let mut comments = VorbisComments::new();
+		comments.add("DESCRIPTION", "a".repeat(1000)).unwrap();
+
+		let mut encoded_ogg = vec![];
+		let mut encoder = VorbisEncoderBuilder::new_with_serial(
+			NonZeroU32::new(8000).unwrap(),
+			NonZeroU8::new(2).unwrap(),
+			&mut encoded_ogg,
+			0
+		)
+		.comments(comments)
+		.build()
+		.unwrap();
+		encoder.encode_audio_block(&samples).unwrap();
+		encoder.finish().unwrap();
+
+		let open = |limits: &VorbisDecoderLimits| {
+			VorbisDecoder::<&[u8]>::new_with_limits(&*encoded_ogg, limits).map(drop)
+		};
+		let assert_limit_exceeded = |result: Result<(), VorbisError>, limit| {
+			assert!(
+				matches!(result, Err(VorbisError::DecoderLimitExceeded(actual)) if actual == limit),
+				"Expected {limit} limit error, got {result:?}"
+			);
+		};
+
+		assert_limit_exceeded(
+			open(VorbisDecoderLimits::new().maximum_channels(NonZeroU8::new(1))),
+			VorbisDecoderLimit::Channels
+		);
+		assert_limit_exceeded(
+			open(VorbisDecoderLimits::new().maximum_sampling_frequency(NonZeroU32::new(4000))),
+			VorbisDecoderLimit::SamplingFrequency
+		);
+		assert_limit_exceeded(
+			open(VorbisDecoderLimits::new().maximum_header_size(Some(256))),
+			VorbisDecoderLimit::HeaderSize
+		);
+		assert_limit_exceeded(
+			open(VorbisDecoderLimits::new().maximum_comment_length(Some(500))),
+			VorbisDecoderLimit::CommentLength
+		);
+		assert_limit_exceeded(
+			open(VorbisDecoderLimits::new().maximum_memory(Some(4096))),
+			VorbisDecoderLimit::Memory
+		);
+
+		let mut decoder = VorbisDecoder::<&[u8]>::new_with_limits(
+			&*encoded_ogg,
+			VorbisDecoderLimits::new().maximum_samples(Some(4000))
+		)
+		.unwrap();
+		let result = loop {
+			match decoder.decode_audio_block() {
+				Ok(Some(_)) => {}
+				result => break result.map(drop)
+			}
+		};
+		assert_limit_exceeded(result, VorbisDecoderLimit::Samples);
+
+		let mut decoder = VorbisDecoder::<&[u8]>::new_with_limits(
+			&*encoded_ogg,
+			VorbisDecoderLimits::new()
+				.maximum_channels(NonZeroU8::new(2))
+				.maximum_sampling_frequency(NonZeroU32::new(8000))
+				.maximum_header_size(Some(64 * 1024))
+				.maximum_comment_length(Some(2000))
+				.maximum_samples(Some(8000))
+				.maximum_memory(Some(16 * 1024 * 1024))
+		)
+		.unwrap();
+		while decoder.decode_audio_block().unwrap().is_some() {}
+		assert_eq!(decoder.decoded_samples(), 8000);
+	}
+
+	#[test]
+	fn decoder_reports_corruption_in_resilient_mode() {
+		// Noise makes for large enough streams with many pages
+		let mut encoded_ogg = encode_mono(&noise(160_000), 0);
+		let page_offsets = page_offsets(&encoded_ogg);
+
+		// Flip a byte of a page body to make it fail its CRC check, and insert some garbage
+		// between two earlier pages
+		let garbage_offset = page_offsets[page_offsets.len() / 2];
+		let corrupt_page_offset = page_offsets[page_offsets.len() * 3 / 4];
+		let corrupt_page_segments = usize::from(encoded_ogg[corrupt_page_offset + 26]);
+		encoded_ogg[corrupt_page_offset + 27 + corrupt_page_segments] ^= 0xFF;
+		encoded_ogg.splice(garbage_offset..garbage_offset, [0; 100]);
+
+		let mut decoder = VorbisDecoder::<&[u8]>::new(&*encoded_ogg).unwrap();
+		let result = loop {
+			match decoder.decode_audio_block() {
+				Ok(Some(_)) => {}
+				result => break result.map(drop)
+			}
+		};
+		assert!(
+			matches!(&result, Err(VorbisError::LibraryError(err)) if err.kind() == VorbisLibraryErrorKind::Hole),
+			"Unexpected result: {result:?}"
+		);
+
+		let mut decoder = VorbisDecoder::<&[u8]>::new(&*encoded_ogg).unwrap();
+		decoder.set_resilient(true).unwrap();
+		while decoder.decode_audio_block().unwrap().is_some() {}
+
+		let events = decoder.drain_events().collect::<Vec<_>>();
+		assert_eq!(
+			events[0],
+			VorbisDecoderEvent::Garbage {
+				byte_offset: garbage_offset as u64,
+				length: 100
+			}
+		);
+		assert!(
+			matches!(events[1], VorbisDecoderEvent::CorruptPage { byte_offset, .. } if byte_offset == corrupt_page_offset as u64 + 100),
+			"Unexpected events: {events:?}"
+		);
+		assert!(
+			events[2..]
+				.iter()
+				.any(|event| matches!(event, VorbisDecoderEvent::Hole { .. })),
+			"Unexpected events: {events:?}"
+		);
+		assert!(decoder.decoded_samples() < 160_000);
+		assert_eq!(decoder.drain_events().count(), 0);
+	}
+
+	#[cfg(feature = "resampling")]
+	#[test]
+	fn decoder_resamples_encoded_audio() {
+		let samples = vec![0.25; 16000];
+
+		let mut encoded_ogg = vec![];
+		let mut encoder = mono_encoder_builder(&mut encoded_ogg, 0)
+			.encoding_sampling_frequency(NonZeroU32::new(11025))
+			.build()
+			.unwrap();
+		encoder.encode_audio_block([&samples]).unwrap();
+		encoder.finish().unwrap();
+
+		let mut decoder = VorbisDecoder::<&[u8]>::new(&*encoded_ogg).unwrap();
+		assert_eq!(decoder.sampling_frequency().get(), 11025);
+
+		decoder.set_output_sampling_frequency(NonZeroU32::new(8000));
+		while decoder.decode_audio_block().unwrap().is_some() {}
+
+		assert_eq!(decoder.sampling_frequency().get(), 8000);
+		assert_eq!(decoder.decoded_samples(), 16000);
+	}
 }
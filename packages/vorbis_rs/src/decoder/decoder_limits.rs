@@ -0,0 +1,546 @@
+use std::{
+	fmt::{self, Display, Formatter},
+	mem,
+	num::{NonZeroU8, NonZeroU32}
+};
+
+#[allow(unused_imports)]
+use crate::VorbisDecoder;
+use crate::{
+	VorbisError,
+	common::{OggPage, OggStream, OggSync}
+};
+
+/// Limits on the resources a [`VorbisDecoder`] may use to decode a stream, which protect
+/// applications that decode untrusted streams from streams crafted to exhaust them.
+///
+/// Streams that exceed any limit are rejected with a
+/// [`VorbisError::DecoderLimitExceeded`] error. Limits on header data are checked while
+/// reading it, before the headers are handed over to `libvorbis`, so oversized headers
+/// never cause large allocations. No limits are set by default.
+///
+/// # Examples
+///
+/// ```
+/// # use std::num::{NonZeroU8, NonZeroU32};
+/// # use vorbis_rs::VorbisDecoderLimits;
+/// let mut limits = VorbisDecoderLimits::new();
+/// limits
+///     .maximum_channels(NonZeroU8::new(8))
+///     .maximum_sampling_frequency(NonZeroU32::new(192_000))
+///     .maximum_header_size(Some(1024 * 1024))
+///     .maximum_comment_length(Some(256 * 1024))
+///     .maximum_samples(Some(48_000 * 60 * 60))
+///     .maximum_memory(Some(16 * 1024 * 1024));
+/// ```
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct VorbisDecoderLimits {
+	maximum_channels: Option<NonZeroU8>,
+	maximum_sampling_frequency: Option<NonZeroU32>,
+	maximum_header_size: Option<usize>,
+	maximum_comment_length: Option<usize>,
+	maximum_samples: Option<u64>,
+	maximum_memory: Option<usize>
+}
+
+impl VorbisDecoderLimits {
+	/// Creates a new set of decoder limits, which does not limit anything.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Sets the maximum number of channels of decoded streams.
+	pub fn maximum_channels(&mut self, maximum_channels: Option<NonZeroU8>) -> &mut Self {
+		self.maximum_channels = maximum_channels;
+		self
+	}
+
+	/// Sets the maximum sampling frequency of decoded streams, in Hz.
+	pub fn maximum_sampling_frequency(
+		&mut self,
+		maximum_sampling_frequency: Option<NonZeroU32>
+	) -> &mut Self {
+		self.maximum_sampling_frequency = maximum_sampling_frequency;
+		self
+	}
+
+	/// Sets the maximum combined size of the three Vorbis header packets of decoded
+	/// streams, in bytes. This bounds the size of the comment and setup headers, which
+	/// may be arbitrarily large.
+	pub fn maximum_header_size(&mut self, maximum_header_size: Option<usize>) -> &mut Self {
+		self.maximum_header_size = maximum_header_size;
+		self
+	}
+
+	/// Sets the maximum length of the vendor string and of each user comment of decoded
+	/// streams, in bytes.
+	pub fn maximum_comment_length(&mut self, maximum_comment_length: Option<usize>) -> &mut Self {
+		self.maximum_comment_length = maximum_comment_length;
+		self
+	}
+
+	/// Sets the maximum number of samples per channel decoded from a stream, as counted by
+	/// [`VorbisDecoder::decoded_samples`]. Decoding past this limit returns an error.
+	pub fn maximum_samples(&mut self, maximum_samples: Option<u64>) -> &mut Self {
+		self.maximum_samples = maximum_samples;
+		self
+	}
+
+	/// Sets the maximum memory the decoding state of a stream may use, in bytes. This
+	/// memory is estimated from the stream headers before decoding starts, accounting for
+	/// the codebooks, comments and sample buffers that `libvorbis` allocates, so it is an
+	/// approximation of the actual memory usage.
+	pub fn maximum_memory(&mut self, maximum_memory: Option<usize>) -> &mut Self {
+		self.maximum_memory = maximum_memory;
+		self
+	}
+
+	/// Checks that the specified number of decoded samples is within limits.
+	pub(in crate::decoder) fn check_samples(&self, samples: u64) -> Result<(), VorbisError> {
+		check_limit(samples, self.maximum_samples, VorbisDecoderLimit::Samples)
+	}
+}
+
+/// A limit set by [`VorbisDecoderLimits`] that a stream exceeded.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum VorbisDecoderLimit {
+	/// The maximum number of channels.
+	Channels,
+	/// The maximum sampling frequency.
+	SamplingFrequency,
+	/// The maximum combined size of the Vorbis header packets.
+	HeaderSize,
+	/// The maximum length of the vendor string or a user comment.
+	CommentLength,
+	/// The maximum number of decoded samples per channel.
+	Samples,
+	/// The maximum memory usage of the decoding state.
+	Memory
+}
+
+impl Display for VorbisDecoderLimit {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Channels => f.write_str("channel count"),
+			Self::SamplingFrequency => f.write_str("sampling frequency"),
+			Self::HeaderSize => f.write_str("header size"),
+			Self::CommentLength => f.write_str("comment length"),
+			Self::Samples => f.write_str("decoded samples"),
+			Self::Memory => f.write_str("memory usage")
+		}
+	}
+}
+
+/// Returns a [`VorbisError::DecoderLimitExceeded`] error if the specified value exceeds
+/// the specified maximum, if any.
+fn check_limit<T: PartialOrd>(
+	value: T,
+	maximum: Option<T>,
+	limit: VorbisDecoderLimit
+) -> Result<(), VorbisError> {
+	match maximum {
+		Some(maximum) if value > maximum => Err(VorbisError::DecoderLimitExceeded(limit)),
+		_ => Ok(())
+	}
+}
+
+/// Checks the header data of an Ogg Vorbis stream against some [`VorbisDecoderLimits`]
+/// as it is read, splitting it into pages and packets on its own.
+pub(in crate::decoder) struct HeaderValidator {
+	ogg_sync: OggSync,
+	header_state: HeaderState
+}
+
+impl HeaderValidator {
+	/// Creates a new header validator for the specified limits.
+	pub fn new(limits: VorbisDecoderLimits) -> Self {
+		Self {
+			ogg_sync: OggSync::new(),
+			header_state: HeaderState {
+				limits,
+				ogg_stream: None,
+				stream_serial: 0,
+				header_size: 0,
+				header_packets: 0,
+				memory: 0
+			}
+		}
+	}
+
+	/// Checks the specified data, which follows the data previously checked. Returns
+	/// whether every header was checked, so that no more data needs to be checked.
+	pub fn validate(&mut self, mut data: &[u8]) -> Result<bool, VorbisError> {
+		while let Some(ogg_page) = self.ogg_sync.read_page(&mut data)? {
+			if self.header_state.check_page(&ogg_page)? {
+				return Ok(true);
+			}
+		}
+
+		Ok(false)
+	}
+}
+
+/// The state of a [`HeaderValidator`] other than its Ogg synchronization state.
+struct HeaderState {
+	limits: VorbisDecoderLimits,
+	ogg_stream: Option<OggStream>,
+	stream_serial: i32,
+	header_size: usize,
+	header_packets: u8,
+	memory: u64
+}
+
+impl HeaderState {
+	/// Checks the header packets completed by the specified page, returning whether every
+	/// header was checked.
+	fn check_page(&mut self, ogg_page: &OggPage<'_>) -> Result<bool, VorbisError> {
+		let ogg_stream = match &mut self.ogg_stream {
+			Some(ogg_stream) if ogg_page.stream_serial() == self.stream_serial => ogg_stream,
+			Some(_) => return Ok(false),
+			None => {
+				// Like vorbisfile, look for the first logical stream that begins with a Vorbis
+				// identification header, which is alone on its first page
+				if !ogg_page.is_bos() || !ogg_page.body().starts_with(b"\x01vorbis") {
+					return Ok(false);
+				}
+
+				self.stream_serial = ogg_page.stream_serial();
+				self.ogg_stream.insert(OggStream::new(self.stream_serial)?)
+			}
+		};
+
+		// Header packets can't be complete before their pages are, so this bounds them
+		// before any is assembled
+		self.header_size = self.header_size.saturating_add(ogg_page.body().len());
+		check_limit(
+			self.header_size,
+			self.limits.maximum_header_size,
+			VorbisDecoderLimit::HeaderSize
+		)?;
+
+		ogg_stream.page_in(ogg_page)?;
+		while let Some(header_packet) = ogg_stream.packet_out()? {
+			let header_packet = header_packet.data();
+			let header_memory = match self.header_packets {
+				0 => check_identification_header(&self.limits, header_packet)?,
+				1 => check_comment_header(&self.limits, header_packet)?,
+				_ => setup_header_memory(header_packet, |memory| {
+					check_limit(
+						self.memory.saturating_add(memory),
+						self.limits.maximum_memory.map(|memory| memory as u64),
+						VorbisDecoderLimit::Memory
+					)
+				})?
+			};
+
+			self.memory = self.memory.saturating_add(header_memory);
+			check_limit(
+				self.memory,
+				self.limits.maximum_memory.map(|memory| memory as u64),
+				VorbisDecoderLimit::Memory
+			)?;
+
+			self.header_packets += 1;
+			if self.header_packets == 3 {
+				return Ok(true);
+			}
+		}
+
+		Ok(false)
+	}
+}
+
+/// Checks the channel count and sampling frequency of the specified identification
+/// header, returning an estimate of the memory used by sample buffers. Malformed headers
+/// are left for `libvorbis` to reject.
+fn check_identification_header(
+	limits: &VorbisDecoderLimits,
+	header_packet: &[u8]
+) -> Result<u64, VorbisError> {
+	let (Some(&channels), Some(sampling_frequency), Some(&blocksizes)) = (
+		header_packet.get(11),
+		header_packet.get(12..16),
+		header_packet.get(28)
+	) else {
+		return Ok(0);
+	};
+	let sampling_frequency = u32::from_le_bytes(sampling_frequency.try_into().unwrap());
+
+	check_limit(
+		channels,
+		limits.maximum_channels.map(NonZeroU8::get),
+		VorbisDecoderLimit::Channels
+	)?;
+	check_limit(
+		sampling_frequency,
+		limits.maximum_sampling_frequency.map(NonZeroU32::get),
+		VorbisDecoderLimit::SamplingFrequency
+	)?;
+
+	// Each channel has PCM and overlap buffers as long as the long blocks, and the MDCT
+	// and window lookups for both block sizes take about as much again
+	let long_blocksize = 1_u64 << (blocksizes >> 4);
+	Ok(long_blocksize * (u64::from(channels) * 2 + 4) * mem::size_of::<f32>() as u64)
+}
+
+/// Checks the length of the vendor string and user comments of the specified comment
+/// header, returning an estimate of the memory used to store them. Malformed headers are
+/// left for `libvorbis` to reject.
+fn check_comment_header(
+	limits: &VorbisDecoderLimits,
+	header_packet: &[u8]
+) -> Result<u64, VorbisError> {
+	let mut comment_data = header_packet.get(7..).unwrap_or_default();
+	let mut read_u32 = || {
+		let (value, remaining_data) = comment_data.split_first_chunk()?;
+		comment_data = remaining_data;
+		Some(u32::from_le_bytes(*value))
+	};
+
+	let Some(vendor_length) = read_u32() else {
+		return Ok(0);
+	};
+	check_limit(
+		vendor_length as usize,
+		limits.maximum_comment_length,
+		VorbisDecoderLimit::CommentLength
+	)?;
+
+	// libvorbis allocates a pointer and a length for every declared comment before
+	// reading them, and then copies each comment
+	let Some(comment_count) = comment_data
+		.get(vendor_length as usize..)
+		.and_then(|data| data.first_chunk())
+		.map(|comment_count| u32::from_le_bytes(*comment_count))
+	else {
+		return Ok(header_packet.len() as u64);
+	};
+	let mut comment_data = &comment_data[vendor_length as usize + 4..];
+	for _ in 0..comment_count {
+		let Some((comment_length, remaining_data)) = comment_data.split_first_chunk() else {
+			break;
+		};
+		let comment_length = u32::from_le_bytes(*comment_length) as usize;
+		check_limit(
+			comment_length,
+			limits.maximum_comment_length,
+			VorbisDecoderLimit::CommentLength
+		)?;
+
+		comment_data = remaining_data.get(comment_length..).unwrap_or_default();
+	}
+
+	Ok(header_packet.len() as u64
+		+ (u64::from(comment_count) + 1) * (mem::size_of::<usize>() + mem::size_of::<u32>()) as u64)
+}
+
+/// Estimates the memory used by the codebooks of the specified setup header, whose
+/// declared sizes may make `libvorbis` allocate much more memory than the header size.
+/// The running estimate is passed to `check_memory` after each codebook, so that parsing
+/// can stop as soon as it is exceeded. Malformed headers are left for `libvorbis` to
+/// reject.
+fn setup_header_memory(
+	header_packet: &[u8],
+	mut check_memory: impl FnMut(u64) -> Result<(), VorbisError>
+) -> Result<u64, VorbisError> {
+	let mut setup_header = BitReader::new(header_packet.get(7..).unwrap_or_default());
+	let mut memory = header_packet.len() as u64;
+
+	let Some(codebook_count) = setup_header.read(8) else {
+		return Ok(memory);
+	};
+	for _ in 0..=codebook_count {
+		let (codebook_memory, complete) = codebook_memory(&mut setup_header);
+		memory = memory.saturating_add(codebook_memory);
+		check_memory(memory)?;
+
+		if !complete {
+			break;
+		}
+	}
+
+	Ok(memory)
+}
+
+/// Reads the next codebook from the specified setup header reader, returning an estimate
+/// of the memory `libvorbis` allocates for it, and whether it was completely read. The
+/// estimate accounts for the memory allocated before reading data that may be missing.
+fn codebook_memory(setup_header: &mut BitReader<'_>) -> (u64, bool) {
+	let (Some(0x56_43_42), Some(dimensions), Some(entries), Some(ordered)) = (
+		setup_header.read(24),
+		setup_header.read(16),
+		setup_header.read(24),
+		setup_header.read(1)
+	) else {
+		return (0, false);
+	};
+
+	// The code length list is allocated before reading it
+	let mut memory = u64::from(entries);
+	let mut used_entries = 0;
+
+	if ordered == 0 {
+		let Some(sparse) = setup_header.read(1) else {
+			return (memory, false);
+		};
+		for _ in 0..entries {
+			let used = if sparse == 0 {
+				true
+			} else {
+				let Some(used) = setup_header.read(1) else {
+					return (memory, false);
+				};
+				used != 0
+			};
+
+			if used {
+				if setup_header.read(5).is_none() {
+					return (memory, false);
+				}
+				used_entries += 1;
+			}
+		}
+	} else {
+		if setup_header.read(5).is_none() {
+			return (memory, false);
+		}
+		while used_entries < entries {
+			let Some(length_entries) = setup_header.read(ilog(entries - used_entries)) else {
+				return (memory, false);
+			};
+			used_entries = used_entries.saturating_add(length_entries);
+		}
+		used_entries = used_entries.min(entries);
+	}
+
+	let Some(lookup_type) = setup_header.read(4) else {
+		return (memory, false);
+	};
+	let mut value_memory = 0;
+	if lookup_type == 1 || lookup_type == 2 {
+		let (Some(_), Some(_), Some(value_bits), Some(_)) = (
+			setup_header.read(32),
+			setup_header.read(32),
+			setup_header.read(4),
+			setup_header.read(1)
+		) else {
+			return (memory, false);
+		};
+		let lookup_values = if lookup_type == 1 {
+			lookup1_values(entries, dimensions)
+		} else {
+			u64::from(entries) * u64::from(dimensions)
+		};
+
+		// The quantized values list is allocated before reading it, and each used entry
+		// gets its own unquantized vector
+		memory = memory.saturating_add(lookup_values * mem::size_of::<i64>() as u64);
+		value_memory = u64::from(dimensions) * mem::size_of::<f32>() as u64;
+
+		if !setup_header.skip(lookup_values * (u64::from(value_bits) + 1)) {
+			return (memory, false);
+		}
+	}
+
+	// Every used entry has a codeword, a decode index and a code length, besides its
+	// unquantized vector
+	let entry_memory = value_memory + 2 * mem::size_of::<u32>() as u64 + 1;
+	(
+		memory.saturating_add(u64::from(used_entries) * entry_memory),
+		true
+	)
+}
+
+/// Returns the number of scalar values in a codebook with the specified number of entries
+/// and dimensions, and a lookup table of type 1, as defined by the Vorbis I specification.
+fn lookup1_values(entries: u32, dimensions: u32) -> u64 {
+	if dimensions == 0 {
+		return 0;
+	}
+
+	// The greatest integer whose power of the dimensions does not exceed the entries
+	let fits = |values: u64| {
+		values
+			.checked_pow(dimensions)
+			.is_some_and(|values| values <= u64::from(entries))
+	};
+	let mut values = f64::from(entries).powf(1.0 / f64::from(dimensions)).floor() as u64;
+	while fits(values + 1) {
+		values += 1;
+	}
+	while values > 0 && !fits(values) {
+		values -= 1;
+	}
+
+	values
+}
+
+/// Returns the number of bits needed to represent the specified value, as defined by the
+/// Vorbis I specification.
+fn ilog(value: u32) -> u32 {
+	u32::BITS - value.leading_zeros()
+}
+
+/// Reads values packed least significant bit first from a byte slice, as Vorbis packets
+/// do.
+struct BitReader<'data> {
+	data: &'data [u8],
+	bit_position: u64
+}
+
+impl<'data> BitReader<'data> {
+	/// Creates a new bit reader that reads from the start of the specified data.
+	fn new(data: &'data [u8]) -> Self {
+		Self {
+			data,
+			bit_position: 0
+		}
+	}
+
+	/// Reads an unsigned value with the specified number of bits, which must not exceed
+	/// 32. `None` is returned if there is not enough data left.
+	fn read(&mut self, bits: u32) -> Option<u32> {
+		let mut value = 0;
+		for bit in 0..bits {
+			let bit_position = self.bit_position + u64::from(bit);
+			let byte = self.data.get(usize::try_from(bit_position / 8).ok()?)?;
+			value |= u32::from((byte >> (bit_position % 8)) & 1) << bit;
+		}
+
+		self.bit_position += u64::from(bits);
+		Some(value)
+	}
+
+	/// Skips the specified number of bits, returning whether there was enough data left.
+	fn skip(&mut self, bits: u64) -> bool {
+		self.bit_position = self.bit_position.saturating_add(bits);
+		self.bit_position <= self.data.len() as u64 * 8
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{BitReader, lookup1_values};
+
+	#[test]
+	fn bit_reader_reads_least_significant_bits_first() {
+		let mut bit_reader = BitReader::new(&[0b1010_1101, 0b0000_0011]);
+
+		assert_eq!(bit_reader.read(1), Some(1));
+		assert_eq!(bit_reader.read(3), Some(0b110));
+		assert_eq!(bit_reader.read(6), Some(0b11_1010));
+		assert_eq!(bit_reader.read(7), None);
+		assert!(bit_reader.skip(6));
+		assert!(!bit_reader.skip(1));
+	}
+
+	#[test]
+	fn lookup1_values_match_specification() {
+		assert_eq!(lookup1_values(81, 4), 3);
+		assert_eq!(lookup1_values(80, 4), 2);
+		assert_eq!(lookup1_values(1, 2), 1);
+		assert_eq!(lookup1_values(0x00ff_ffff, 1), 0x00ff_ffff);
+		assert_eq!(lookup1_values(16, 0), 0);
+	}
+}
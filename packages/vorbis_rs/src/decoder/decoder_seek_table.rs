@@ -0,0 +1,279 @@
+use std::{io::Read, num::NonZeroU64};
+
+#[allow(unused_imports)]
+use crate::VorbisDecoder;
+use crate::common::{
+	OggPageSeek, OggSync, VorbisError, VorbisLibrary, VorbisLibraryError, VorbisLibraryErrorKind,
+	read_variable_length, write_variable_length
+};
+
+/// The magic bytes serialized seek tables begin with, followed by a format version byte.
+const SERIALIZED_MAGIC: &[u8] = b"VSTB\x01";
+
+/// The magic bytes every Vorbis identification header packet begins with.
+const IDENTIFICATION_HEADER_MAGIC: &[u8] = b"\x01vorbis";
+
+/// A seek point of a [`VorbisSeekTable`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct VorbisSeekPoint {
+	/// The granule position of the last page of the Vorbis stream before this seek point,
+	/// which is the position of the last sample that can be decoded from the pages before
+	/// it.
+	pub granule_position: u64,
+	/// The offset of the first page after this seek point, in bytes since the beginning of
+	/// the physical Ogg bitstream.
+	pub byte_offset: u64
+}
+
+/// A compact table of seek points of an Ogg Vorbis stream, which lets a [`VorbisDecoder`]
+/// seek to any sample with a single seek of its source, instead of bisecting the source
+/// with many reads. This is much faster on slow or remote sources, such as network-backed
+/// ones.
+///
+/// Seek tables are built by [scanning](Self::scan) a stream once, and can be
+/// [serialized](Self::to_bytes) to be stored next to it, so that decoders can
+/// [use](VorbisDecoder::set_seek_table) them later.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use std::{fs::File, num::NonZeroU64};
+/// # use vorbis_rs::{VorbisDecoder, VorbisSeekTable};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// // Place a seek point every 10 seconds of 44.1 kHz audio
+/// let seek_table = VorbisSeekTable::scan(
+///     File::open("audio.ogg")?,
+///     NonZeroU64::new(10 * 44_100).unwrap()
+/// )?;
+/// let serialized_seek_table = seek_table.to_bytes();
+///
+/// let mut decoder = VorbisDecoder::new_seekable(File::open("audio.ogg")?)?;
+/// decoder.set_seek_table(Some(VorbisSeekTable::from_bytes(&serialized_seek_table)?))?;
+/// decoder.seek(60 * 44_100)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct VorbisSeekTable {
+	stream_serial: i32,
+	seek_points: Vec<VorbisSeekPoint>
+}
+
+impl VorbisSeekTable {
+	/// Builds a seek table for the first logical Vorbis stream of the physical Ogg bitstream
+	/// read from the specified source, which is read to its end, with seek points at page
+	/// boundaries spaced at least the specified interval apart, in samples. Shorter intervals
+	/// make decoders discard fewer samples after seeking, at the cost of bigger tables.
+	pub fn scan<R: Read>(mut source: R, interval: NonZeroU64) -> Result<Self, VorbisError> {
+		let mut ogg_sync = OggSync::new();
+		let mut stream_serial = None;
+		let mut seek_points = Vec::<VorbisSeekPoint>::new();
+		let mut byte_offset = 0;
+
+		loop {
+			let page = match ogg_sync.seek_page() {
+				OggPageSeek::Page(page) => page,
+				OggPageSeek::Skipped { bytes, .. } => {
+					byte_offset += bytes as u64;
+					continue;
+				}
+				OggPageSeek::NeedData => {
+					if ogg_sync.read_chunk(&mut source)? == 0 {
+						break;
+					}
+					continue;
+				}
+			};
+			byte_offset += (page.header().len() + page.body().len()) as u64;
+
+			if stream_serial.is_none()
+				&& page.is_bos()
+				&& page.body().starts_with(IDENTIFICATION_HEADER_MAGIC)
+			{
+				stream_serial = Some(page.stream_serial());
+			}
+			if stream_serial != Some(page.stream_serial()) {
+				continue;
+			}
+
+			// Header pages have a granule position of zero, and pages where no packet ends
+			// don't have any, so only pages after audio pages become seek points
+			let Ok(granule_position @ 1..) = u64::try_from(page.granule_position()) else {
+				continue;
+			};
+			if seek_points.last().is_none_or(|last_seek_point| {
+				granule_position
+					>= last_seek_point
+						.granule_position
+						.saturating_add(interval.get())
+			}) {
+				seek_points.push(VorbisSeekPoint {
+					granule_position,
+					byte_offset
+				});
+			}
+		}
+
+		let Some(stream_serial) = stream_serial else {
+			return Err(VorbisLibraryError {
+				library: VorbisLibrary::Vorbis,
+				function: "vorbis_synthesis_idheader",
+				kind: VorbisLibraryErrorKind::NotVorbis
+			}
+			.into());
+		};
+
+		Ok(Self {
+			stream_serial,
+			seek_points
+		})
+	}
+
+	/// Returns the serial of the logical Vorbis stream this seek table belongs to.
+	pub fn stream_serial(&self) -> i32 {
+		self.stream_serial
+	}
+
+	/// Returns the seek points of this table, sorted by granule position and byte offset.
+	pub fn seek_points(&self) -> &[VorbisSeekPoint] {
+		&self.seek_points
+	}
+
+	/// Serializes this seek table to a compact, portable binary representation, which can
+	/// be deserialized with [`from_bytes`](Self::from_bytes).
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut bytes = Vec::with_capacity(SERIALIZED_MAGIC.len() + 8 + self.seek_points.len() * 6);
+		bytes.extend_from_slice(SERIALIZED_MAGIC);
+		bytes.extend_from_slice(&self.stream_serial.to_le_bytes());
+		write_variable_length(&mut bytes, self.seek_points.len() as u64);
+
+		// Seek points are sorted, so store the differences between them, which are small
+		let mut last_seek_point = VorbisSeekPoint {
+			granule_position: 0,
+			byte_offset: 0
+		};
+		for seek_point in &self.seek_points {
+			write_variable_length(
+				&mut bytes,
+				seek_point.granule_position - last_seek_point.granule_position
+			);
+			write_variable_length(
+				&mut bytes,
+				seek_point.byte_offset - last_seek_point.byte_offset
+			);
+			last_seek_point = *seek_point;
+		}
+
+		bytes
+	}
+
+	/// Deserializes a seek table serialized with [`to_bytes`](Self::to_bytes), returning a
+	/// [`VorbisError::MalformedSeekTable`] error if the bytes are not a valid seek table.
+	pub fn from_bytes(bytes: &[u8]) -> Result<Self, VorbisError> {
+		let mut bytes = bytes
+			.strip_prefix(SERIALIZED_MAGIC)
+			.ok_or(VorbisError::MalformedSeekTable)?;
+		let (stream_serial, rest) = bytes
+			.split_first_chunk()
+			.ok_or(VorbisError::MalformedSeekTable)?;
+		let stream_serial = i32::from_le_bytes(*stream_serial);
+		bytes = rest;
+
+		let seek_point_count =
+			read_variable_length(&mut bytes).ok_or(VorbisError::MalformedSeekTable)?;
+		// Every seek point takes at least two bytes, which bounds the allocation size
+		let mut seek_points =
+			Vec::with_capacity(seek_point_count.min(bytes.len() as u64 / 2) as usize);
+
+		let mut last_seek_point = VorbisSeekPoint {
+			granule_position: 0,
+			byte_offset: 0
+		};
+		for _ in 0..seek_point_count {
+			let (Some(granule_position_delta), Some(byte_offset_delta)) = (
+				read_variable_length(&mut bytes),
+				read_variable_length(&mut bytes)
+			) else {
+				return Err(VorbisError::MalformedSeekTable);
+			};
+
+			last_seek_point = VorbisSeekPoint {
+				granule_position: last_seek_point
+					.granule_position
+					.checked_add(granule_position_delta)
+					.ok_or(VorbisError::MalformedSeekTable)?,
+				byte_offset: last_seek_point
+					.byte_offset
+					.checked_add(byte_offset_delta)
+					.ok_or(VorbisError::MalformedSeekTable)?
+			};
+			seek_points.push(last_seek_point);
+		}
+
+		if !bytes.is_empty() {
+			return Err(VorbisError::MalformedSeekTable);
+		}
+
+		Ok(Self {
+			stream_serial,
+			seek_points
+		})
+	}
+
+	/// Returns the last seek point of this table whose granule position is not after the
+	/// specified one, if any.
+	pub(crate) fn seek_point_before(&self, granule_position: u64) -> Option<VorbisSeekPoint> {
+		let seek_point_count = self
+			.seek_points
+			.partition_point(|seek_point| seek_point.granule_position <= granule_position);
+
+		seek_point_count
+			.checked_sub(1)
+			.map(|seek_point_index| self.seek_points[seek_point_index])
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{VorbisSeekPoint, VorbisSeekTable};
+	use crate::VorbisError;
+
+	#[test]
+	fn seek_tables_round_trip() {
+		let seek_table = VorbisSeekTable {
+			stream_serial: -42,
+			seek_points: vec![
+				VorbisSeekPoint {
+					granule_position: 4096,
+					byte_offset: 4500
+				},
+				VorbisSeekPoint {
+					granule_position: 90_000,
+					byte_offset: 65_000
+				},
+				VorbisSeekPoint {
+					granule_position: u64::MAX,
+					byte_offset: u64::MAX
+				},
+			]
+		};
+		let bytes = seek_table.to_bytes();
+
+		assert_eq!(VorbisSeekTable::from_bytes(&bytes).unwrap(), seek_table);
+		assert_eq!(
+			seek_table.seek_point_before(90_001),
+			Some(seek_table.seek_points[1])
+		);
+		assert_eq!(seek_table.seek_point_before(4095), None);
+
+		for malformed_bytes in [
+			&bytes[..bytes.len() - 1],
+			&[bytes.as_slice(), &[0]].concat()
+		] {
+			assert!(matches!(
+				VorbisSeekTable::from_bytes(malformed_bytes),
+				Err(VorbisError::MalformedSeekTable)
+			));
+		}
+	}
+}
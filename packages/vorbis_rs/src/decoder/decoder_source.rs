@@ -0,0 +1,265 @@
+use std::{
+	collections::VecDeque,
+	io::{self, ErrorKind, Read, Seek, SeekFrom},
+	num::NonZeroUsize
+};
+
+#[allow(unused_imports)]
+use crate::VorbisDecoder;
+
+/// A source of bytes with a known length that can be read at any position, such as an
+/// HTTP client that issues range requests, or a client of a blob store that stores data
+/// in chunks.
+///
+/// Reading these sources is usually expensive, so they are meant to be wrapped in a
+/// [`VorbisCachedSource`], which can be used as the source of a seekable
+/// [`VorbisDecoder`].
+pub trait VorbisRandomAccessSource {
+	/// Returns the length of this source, in bytes.
+	fn len(&self) -> u64;
+
+	/// Checks whether this source has no bytes.
+	fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	/// Reads bytes starting at the specified offset into the specified buffer, returning
+	/// the number of bytes read. Fewer bytes than requested may be read, but zero bytes
+	/// must only be read for empty buffers or offsets at or past the end of this source.
+	fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<usize>;
+}
+
+impl<S: VorbisRandomAccessSource + ?Sized> VorbisRandomAccessSource for &mut S {
+	fn len(&self) -> u64 {
+		(**self).len()
+	}
+
+	fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+		(**self).read_at(offset, buf)
+	}
+}
+
+impl<S: VorbisRandomAccessSource + ?Sized> VorbisRandomAccessSource for Box<S> {
+	fn len(&self) -> u64 {
+		(**self).len()
+	}
+
+	fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+		(**self).read_at(offset, buf)
+	}
+}
+
+/// An adapter that turns a [`VorbisRandomAccessSource`] into a [`Read`] and [`Seek`]
+/// source, reading it in aligned chunks and caching the most recently used ones. Seeking
+/// never reads the source, and reading data that is cached doesn't either, so decoders
+/// that [seek](VorbisDecoder::seek) around the same areas of a stream, as `vorbisfile`
+/// does when bisecting it, read each chunk of the source once.
+///
+/// By default, chunks are 64 KiB long, and up to 16 of them are cached.
+pub struct VorbisCachedSource<S> {
+	source: S,
+	length: u64,
+	position: u64,
+	chunk_size: NonZeroUsize,
+	maximum_chunks: NonZeroUsize,
+	/// The cached chunks and their indices, from least to most recently used.
+	chunks: VecDeque<(u64, Vec<u8>)>
+}
+
+impl<S: VorbisRandomAccessSource> VorbisCachedSource<S> {
+	/// Creates a new cached source that reads from the specified random-access source,
+	/// with the default chunk size and number of cached chunks.
+	pub fn new(source: S) -> Self {
+		Self::with_capacity(
+			source,
+			NonZeroUsize::new(64 * 1024).unwrap(),
+			NonZeroUsize::new(16).unwrap()
+		)
+	}
+
+	/// Creates a new cached source that reads from the specified random-access source in
+	/// chunks of the specified size, in bytes, caching up to the specified number of them.
+	/// Larger chunks make for fewer, larger reads, which suits sources with high latency.
+	pub fn with_capacity(
+		source: S,
+		chunk_size: NonZeroUsize,
+		maximum_chunks: NonZeroUsize
+	) -> Self {
+		Self {
+			length: source.len(),
+			source,
+			position: 0,
+			chunk_size,
+			maximum_chunks,
+			chunks: VecDeque::with_capacity(maximum_chunks.get())
+		}
+	}
+
+	/// Returns a reference to the random-access source this cached source reads from.
+	pub fn get_ref(&self) -> &S {
+		&self.source
+	}
+
+	/// Consumes this cached source, returning the random-access source it reads from.
+	pub fn into_inner(self) -> S {
+		self.source
+	}
+
+	/// Returns the cached chunk with the specified index, reading it from the source if
+	/// necessary, and marks it as the most recently used one.
+	fn chunk(&mut self, chunk_index: u64) -> io::Result<&[u8]> {
+		match self
+			.chunks
+			.iter()
+			.position(|&(cached_chunk_index, _)| cached_chunk_index == chunk_index)
+		{
+			Some(cached_chunk_position) => {
+				let cached_chunk = self.chunks.remove(cached_chunk_position).unwrap();
+				self.chunks.push_back(cached_chunk);
+			}
+			None => {
+				let chunk_offset = chunk_index * self.chunk_size.get() as u64;
+				let chunk_length =
+					(self.length - chunk_offset).min(self.chunk_size.get() as u64) as usize;
+
+				// Reuse the buffer of the least recently used chunk when the cache is full
+				let mut chunk = if self.chunks.len() == self.maximum_chunks.get() {
+					self.chunks.pop_front().unwrap().1
+				} else {
+					Vec::new()
+				};
+				chunk.resize(chunk_length, 0);
+
+				let mut read_length = 0;
+				while read_length < chunk_length {
+					match self
+						.source
+						.read_at(chunk_offset + read_length as u64, &mut chunk[read_length..])
+					{
+						Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+						Ok(bytes_read) => read_length += bytes_read,
+						Err(err) if err.kind() == ErrorKind::Interrupted => {}
+						Err(err) => return Err(err)
+					}
+				}
+
+				self.chunks.push_back((chunk_index, chunk));
+			}
+		}
+
+		Ok(&self.chunks.back().unwrap().1)
+	}
+}
+
+impl<S: VorbisRandomAccessSource> Read for VorbisCachedSource<S> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		if buf.is_empty() || self.position >= self.length {
+			return Ok(0);
+		}
+
+		let chunk_size = self.chunk_size.get() as u64;
+		let chunk_offset = (self.position % chunk_size) as usize;
+		let chunk = &self.chunk(self.position / chunk_size)?[chunk_offset..];
+
+		let bytes_read = chunk.len().min(buf.len());
+		buf[..bytes_read].copy_from_slice(&chunk[..bytes_read]);
+		self.position += bytes_read as u64;
+
+		Ok(bytes_read)
+	}
+}
+
+impl<S: VorbisRandomAccessSource> Seek for VorbisCachedSource<S> {
+	fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+		let position = match pos {
+			SeekFrom::Start(offset) => Some(offset),
+			SeekFrom::End(offset) => self.length.checked_add_signed(offset),
+			SeekFrom::Current(offset) => self.position.checked_add_signed(offset)
+		};
+
+		self.position = position.ok_or_else(|| {
+			io::Error::new(
+				ErrorKind::InvalidInput,
+				"invalid seek to a negative or overflowing position"
+			)
+		})?;
+
+		Ok(self.position)
+	}
+}
+
+/// A random-access source for tests that mocks a server that serves range requests,
+/// recording the requested ranges.
+#[cfg(test)]
+pub(crate) struct MockRangeServer {
+	pub data: Vec<u8>,
+	pub requested_ranges: Vec<(u64, usize)>
+}
+
+#[cfg(test)]
+impl VorbisRandomAccessSource for MockRangeServer {
+	fn len(&self) -> u64 {
+		self.data.len() as u64
+	}
+
+	fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+		self.requested_ranges.push((offset, buf.len()));
+
+		// Serve at most a few bytes per request, like servers that split responses
+		let data = self.data.get(offset as usize..).unwrap_or_default();
+		let bytes_read = data.len().min(buf.len()).min(7);
+		buf[..bytes_read].copy_from_slice(&data[..bytes_read]);
+		Ok(bytes_read)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::{
+		io::{Read, Seek, SeekFrom},
+		num::NonZeroUsize
+	};
+
+	use super::{MockRangeServer, VorbisCachedSource};
+
+	#[test]
+	fn cached_sources_read_and_seek() {
+		let data = (0..100).collect::<Vec<u8>>();
+		let mut cached_source = VorbisCachedSource::with_capacity(
+			MockRangeServer {
+				data: data.clone(),
+				requested_ranges: vec![]
+			},
+			NonZeroUsize::new(16).unwrap(),
+			NonZeroUsize::new(2).unwrap()
+		);
+
+		let mut read_data = vec![];
+		cached_source.read_to_end(&mut read_data).unwrap();
+		assert_eq!(read_data, data);
+
+		// The last two chunks are cached, so reading them again does not read the source
+		let requested_ranges = cached_source.get_ref().requested_ranges.len();
+		assert_eq!(cached_source.seek(SeekFrom::End(-20)).unwrap(), 80);
+		read_data.clear();
+		cached_source.read_to_end(&mut read_data).unwrap();
+		assert_eq!(read_data, data[80..]);
+		assert_eq!(
+			cached_source.get_ref().requested_ranges.len(),
+			requested_ranges
+		);
+
+		assert_eq!(cached_source.seek(SeekFrom::Current(-95)).unwrap(), 5);
+		let mut buf = [0; 4];
+		cached_source.read_exact(&mut buf).unwrap();
+		assert_eq!(buf, data[5..9]);
+		assert_eq!(
+			cached_source.get_ref().requested_ranges[requested_ranges..],
+			[(0, 16), (7, 9), (14, 2)]
+		);
+
+		assert!(cached_source.seek(SeekFrom::Current(-10)).is_err());
+		assert_eq!(cached_source.seek(SeekFrom::Start(200)).unwrap(), 200);
+		assert_eq!(cached_source.read(&mut buf).unwrap(), 0);
+	}
+}
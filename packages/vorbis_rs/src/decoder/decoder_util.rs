@@ -1,9 +1,10 @@
-use std::slice;
+use std::{ptr, slice};
 
 use tinyvec::{TinyVec, tiny_vec};
 
 #[allow(unused_imports)]
 use crate::VorbisDecoder;
+use crate::{VorbisChannelLayout, VorbisChannelOrder};
 
 /// Contains a block of audio samples. This struct is returned by a [`VorbisDecoder`].
 pub struct VorbisAudioSamples {
@@ -51,6 +52,43 @@ impl VorbisAudioSamples {
 		Self { audio_samples }
 	}
 
+	/// Creates a new block of audio samples that borrows the specified buffers of planar
+	/// samples.
+	///
+	/// # Safety
+	/// This function assumes that it is called by trusted code on [`VorbisDecoder`] only,
+	/// which must not modify or drop the buffers until the next audio block is decoded or
+	/// the decoder is dropped, whichever happens first. See [`new`](Self::new) for more
+	/// details.
+	pub(in crate::decoder) fn from_buffers(buffers: &[Vec<f32>]) -> Self {
+		// SAFETY: the lifetime of the buffers is extended as explained above
+		let audio_samples = buffers
+			.iter()
+			.map(|channel_samples| unsafe { &*ptr::from_ref::<[f32]>(channel_samples) })
+			.collect();
+
+		Self { audio_samples }
+	}
+
+	/// Reorders the channels of this block, which are in the Vorbis channel order for the
+	/// specified layout, to the specified channel order.
+	pub(in crate::decoder) fn remap_channels(
+		&mut self,
+		channel_layout: VorbisChannelLayout,
+		channel_order: VorbisChannelOrder
+	) {
+		if channel_order == VorbisChannelOrder::Vorbis {
+			return;
+		}
+
+		let vorbis_audio_samples = self.audio_samples.clone();
+		for (vorbis_channel_index, channel_samples) in vorbis_audio_samples.into_iter().enumerate()
+		{
+			self.audio_samples[channel_layout.channel_index(channel_order, vorbis_channel_index)] =
+				channel_samples;
+		}
+	}
+
 	/// Returns a reference to the buffer of planar, single-precision float samples held
 	/// by this audio block.
 	pub fn samples(&self) -> &[&[f32]] {
@@ -0,0 +1,4 @@
+mod editor_util;
+
+mod editor_impl;
+pub use editor_impl::{VorbisCutBoundary, VorbisRepairReport, VorbisStreamEditor};
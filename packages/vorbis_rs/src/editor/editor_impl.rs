@@ -0,0 +1,613 @@
+use std::{
+	io::{Read, Write},
+	ops::{Bound, Range, RangeBounds}
+};
+
+use crate::{
+	common::VorbisError,
+	editor::editor_util::{
+		VorbisAudioPacket, VorbisPacketReader, VorbisPacketSalvager, VorbisPacketWriter
+	}
+};
+
+/// Represents the boundaries a [`VorbisStreamEditor`] can cut Ogg Vorbis streams at.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum VorbisCutBoundary {
+	/// Cut at Vorbis packet boundaries, and set the granule positions of the first and
+	/// last Ogg pages so that decoders discard any samples outside the requested range,
+	/// making cuts sample-accurate. Discarding samples at the start of a stream relies on
+	/// decoders honoring the start trimming semantics of the Vorbis I specification, which
+	/// `vorbisfile` does.
+	#[default]
+	Packet,
+	/// Cut at the Ogg page boundaries of the source stream, extending the requested range
+	/// of samples as needed. The granule positions of the resulting stream do not signal
+	/// any sample trimming, other than the trimming done by the source stream itself, which
+	/// may be more compatible with simpler decoders.
+	Page
+}
+
+/// Summarizes the damage found and repaired by [`VorbisStreamEditor::repair`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct VorbisRepairReport {
+	/// The number of bytes that were skipped because they did not belong to any intact Ogg
+	/// page, including garbage data, pages that failed their CRC check and any incomplete
+	/// page at the end of the source.
+	pub skipped_bytes: u64,
+	/// The number of corrupt pages that were dropped, i.e., data that began like an Ogg
+	/// page but failed its CRC check or had a malformed header.
+	pub corrupt_pages: u64,
+	/// The number of holes in the audio data, caused by lost or dropped pages, which
+	/// decoders hear as discontinuities.
+	pub holes: u64,
+	/// The number of audio packets that were dropped because they can't be decoded.
+	pub dropped_packets: u64,
+	/// Whether the source stream was truncated, so its last audio packet had to be
+	/// marked as the end of the stream.
+	pub added_end_of_stream: bool
+}
+
+impl VorbisRepairReport {
+	/// Checks whether any damage was found. Streams without damage are still rewritten
+	/// by repairs, but decode to the same samples.
+	pub fn is_damaged(&self) -> bool {
+		*self != Self::default()
+	}
+
+	/// Accounts for data skipped by an Ogg sync state.
+	pub(in crate::editor) fn skip(&mut self, bytes: usize, capture_pattern: bool) {
+		self.skipped_bytes += bytes as u64;
+		self.corrupt_pages += u64::from(capture_pattern);
+	}
+}
+
+/// Edits Ogg Vorbis streams losslessly, i.e., without re-encoding them, by operating
+/// on their Ogg pages and Vorbis packets.
+///
+/// Only the first logical Vorbis stream of each physical Ogg bitstream is considered:
+/// any other multiplexed or chained logical streams are ignored.
+#[derive(Clone, Debug, Default)]
+pub struct VorbisStreamEditor {
+	cut_boundary: VorbisCutBoundary,
+	minimum_page_data_size: Option<u16>
+}
+
+impl VorbisStreamEditor {
+	/// Creates a new Vorbis stream editor with the default options, which cut at
+	/// Vorbis packet boundaries for sample-accurate results.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Sets the boundaries [`cut`](Self::cut) operations will cut at.
+	pub fn cut_boundary(&mut self, cut_boundary: VorbisCutBoundary) -> &mut Self {
+		self.cut_boundary = cut_boundary;
+		self
+	}
+
+	/// Specifies the minimum size of Vorbis stream data to put into each written Ogg
+	/// page, except for some pages that have to be cut short. This option works the same
+	/// as [`VorbisEncoderBuilder::minimum_page_data_size`](crate::VorbisEncoderBuilder::minimum_page_data_size).
+	pub fn minimum_page_data_size(&mut self, minimum_page_data_size: Option<u16>) -> &mut Self {
+		self.minimum_page_data_size = minimum_page_data_size;
+		self
+	}
+
+	/// Copies the specified range of decoded samples of the Ogg Vorbis stream read from
+	/// `source` to a new Ogg Vorbis stream written to `sink`, without re-encoding it.
+	/// Sample positions are counted from the first sample a decoder outputs. Ranges that
+	/// extend beyond the end of the source stream are clamped.
+	///
+	/// Vorbis decodes every audio packet to samples that overlap with its neighbors, so
+	/// the resulting stream contains the packet right before the requested range. The
+	/// granule positions of the resulting stream are rewritten to start at zero and,
+	/// depending on the [cut boundary](Self::cut_boundary), signal decoders to discard any
+	/// samples outside the requested range.
+	///
+	/// On success, returns the range of samples of the source stream the resulting stream
+	/// decodes to, which is the requested range when cutting at packet boundaries. An
+	/// [`UnrepresentableCutRange`](VorbisError::UnrepresentableCutRange) error is returned
+	/// if the range is empty or, when cutting at packet boundaries, if it is too short to
+	/// trim samples at both of its ends.
+	pub fn cut<R: Read, W: Write>(
+		&self,
+		source: R,
+		sink: W,
+		samples: impl RangeBounds<u64>
+	) -> Result<Range<u64>, VorbisError> {
+		let start = match samples.start_bound() {
+			Bound::Included(start) => *start,
+			Bound::Excluded(start) => start.saturating_add(1),
+			Bound::Unbounded => 0
+		};
+		let end = match samples.end_bound() {
+			Bound::Included(end) => Some(end.saturating_add(1)),
+			Bound::Excluded(end) => Some(*end),
+			Bound::Unbounded => None
+		};
+
+		if end.is_some_and(|end| end <= start) {
+			return Err(VorbisError::UnrepresentableCutRange);
+		}
+
+		let mut reader = VorbisPacketReader::new(source)?;
+		let mut writer = VorbisPacketWriter::new(
+			sink,
+			reader.stream_serial(),
+			reader.header_packets(),
+			self.minimum_page_data_size
+		)?;
+
+		// The start trim is only known after reading the first audio packet, so read it
+		// eagerly to convert the requested range to positions that include trimmed samples
+		let first_packet = reader.read_audio_packet()?;
+		let start_trim = reader.start_trim();
+		let mut first_packet = Some(first_packet);
+
+		let range = self.splice(
+			|| match first_packet.take() {
+				Some(first_packet) => Ok(first_packet),
+				None => reader.read_audio_packet()
+			},
+			&mut writer,
+			start.saturating_add(start_trim),
+			end.map(|end| end.saturating_add(start_trim)),
+			start_trim
+		)?;
+
+		writer.finish()?;
+
+		Ok(range.start - start_trim..range.end - start_trim)
+	}
+
+	/// Concatenates the Ogg Vorbis streams read from the specified sources into a single
+	/// physical Ogg Vorbis stream written to `sink`, without re-encoding them.
+	///
+	/// Consecutive streams with identical setup headers and audio parameters, which is
+	/// usually the case when they were generated by the same encoder with the same
+	/// settings, are merged into a single logical stream, as long as the previous stream
+	/// does not signal to discard samples at its end. At each junction, the last packet of
+	/// a stream is lapped with the first packet of the next, blending two unrelated
+	/// encodes over a fraction of their block sizes. This artifact is short, but may be
+	/// audible as a click when the signals are discontinuous. Only the comments of the
+	/// first merged stream are kept, and the comments of the streams merged into it are
+	/// discarded.
+	///
+	/// Any other streams are chained, i.e., written as consecutive logical streams, each
+	/// with its own headers and a unique serial. This includes streams that follow a
+	/// stream with trimmed samples at its end, such as those written by encoders, as
+	/// decoders can only honor end trimming at the end of a logical stream. Be aware that
+	/// [`VorbisDecoder`](crate::VorbisDecoder) does not support chained streams.
+	///
+	/// On success, returns the number of logical streams written, which is one if every
+	/// stream was merged.
+	pub fn concatenate<R: Read, W: Write>(
+		&self,
+		sources: impl IntoIterator<Item = R>,
+		sink: W
+	) -> Result<usize, VorbisError> {
+		let mut sources = sources.into_iter();
+		let Some(first_source) = sources.next() else {
+			return Ok(0);
+		};
+
+		let mut sink = Some(sink);
+		let mut next_reader = Some(VorbisPacketReader::new(first_source)?);
+		let mut stream_serials = vec![];
+
+		while let Some(mut reader) = next_reader.take() {
+			// Chained logical streams must have unique serials
+			let mut stream_serial = reader.stream_serial();
+			while stream_serials.contains(&stream_serial) {
+				stream_serial = stream_serial.wrapping_add(1);
+			}
+			stream_serials.push(stream_serial);
+
+			let mut writer = VorbisPacketWriter::new(
+				sink.take().unwrap(),
+				stream_serial,
+				reader.header_packets(),
+				self.minimum_page_data_size
+			)?;
+
+			let first_packet = reader.read_audio_packet()?;
+			let start_trim = reader.start_trim();
+			let mut first_packet = Some(first_packet);
+
+			// Offset to add to the packet positions of the current reader, so that they
+			// are continuous with the positions of the previous merged readers
+			let mut position_offset = 0;
+			let mut last_packet = (0, 0, false);
+			let mut at_junction = false;
+
+			self.splice(
+				|| {
+					if let Some(first_packet) = first_packet.take() {
+						return Ok(first_packet);
+					}
+
+					loop {
+						if let Some(mut packet) = reader.read_audio_packet()? {
+							// The first packet of a merged stream is lapped with the last
+							// packet of the previous one, so it decodes to some samples
+							if at_junction {
+								let (last_packet_end, last_packet_blocksize, _) = last_packet;
+								position_offset = last_packet_end
+									+ u64::from(last_packet_blocksize / 4 + packet.blocksize / 4)
+									- packet.end_position;
+								at_junction = false;
+							}
+
+							packet.end_position += position_offset;
+							packet.trimmed_end_position = packet
+								.trimmed_end_position
+								.map(|position| position + position_offset);
+							last_packet = (
+								packet.end_position,
+								packet.blocksize,
+								packet
+									.trimmed_end_position
+									.is_some_and(|position| position < packet.end_position)
+							);
+
+							return Ok(Some(packet));
+						}
+
+						let Some(source) = sources.next() else {
+							return Ok(None);
+						};
+						let new_reader = VorbisPacketReader::new(source)?;
+
+						let (_, _, last_packet_trims_end) = last_packet;
+						if !are_mergeable(&reader, &new_reader, last_packet_trims_end) {
+							next_reader = Some(new_reader);
+							return Ok(None);
+						}
+
+						reader = new_reader;
+						at_junction = true;
+					}
+				},
+				&mut writer,
+				start_trim,
+				None,
+				start_trim
+			)?;
+
+			sink = Some(writer.finish()?);
+		}
+
+		Ok(stream_serials.len())
+	}
+
+	/// Repairs the damaged Ogg Vorbis stream read from `source`, writing a valid Ogg Vorbis
+	/// stream with the same audio packets to `sink`, without re-encoding it.
+	///
+	/// Garbage data between pages and pages that fail their CRC check are skipped, as are
+	/// audio packets that can't be decoded. Pages are written with consecutive sequence
+	/// numbers, and their granule positions are recomputed from the block sizes of the
+	/// packets, so that they stay consistent across any holes left by the skipped data.
+	/// Any sample trimming signalled by intact pages at the start and end of the source
+	/// stream is kept. Truncated streams, such as recordings interrupted by a crash, get
+	/// their last complete audio packet marked as the end of the stream.
+	///
+	/// The header packets can't be salvaged, so if any of them is damaged, an error is
+	/// returned. On success, returns a report of the damage that was repaired.
+	pub fn repair<R: Read, W: Write>(
+		&self,
+		source: R,
+		sink: W
+	) -> Result<VorbisRepairReport, VorbisError> {
+		let mut salvager = VorbisPacketSalvager::new(source)?;
+		let mut writer = VorbisPacketWriter::new(
+			sink,
+			salvager.stream_serial(),
+			salvager.header_packets(),
+			self.minimum_page_data_size
+		)?;
+
+		// The start trim is only known after reading the first audio packet
+		let mut next_packet = salvager.read_audio_packet()?;
+		let start_trim = salvager.start_trim();
+
+		// Start trimming requires the first audio page to end at the same packet as in
+		// the source stream, so that its granule position has the intended meaning
+		let mut trims_start = start_trim > 0;
+		while let Some(packet) = next_packet {
+			let end_position = packet.trimmed_end_position.unwrap_or(packet.end_position);
+			let granule_position = end_position as i64 - start_trim as i64;
+			writer.write_audio_packet(
+				packet.data,
+				granule_position.max(-1),
+				trims_start && packet.ends_page
+			)?;
+			trims_start &= !packet.ends_page;
+
+			next_packet = salvager.read_audio_packet()?;
+		}
+
+		let report = *salvager.report();
+		writer.finish()?;
+
+		Ok(report)
+	}
+
+	/// Writes the audio packets returned by `next_packet` to `writer`, keeping the
+	/// specified range of sample positions, which include trimmed samples. The start of
+	/// the range may be extended to be at least `minimum_start` when cutting at page
+	/// boundaries. Returns the range of sample positions that were kept.
+	fn splice<W: Write>(
+		&self,
+		mut next_packet: impl FnMut() -> Result<Option<VorbisAudioPacket>, VorbisError>,
+		writer: &mut VorbisPacketWriter<W>,
+		start: u64,
+		end: Option<u64>,
+		minimum_start: u64
+	) -> Result<Range<u64>, VorbisError> {
+		let cut_at_pages = self.cut_boundary == VorbisCutBoundary::Page;
+
+		// Look for the packet before the first packet that decodes to samples in the range,
+		// which we call priming packet. Its samples will not be decoded, but it is needed
+		// to decode the next packet. When cutting at page boundaries, the priming packet
+		// must end a page, and every packet after it is kept
+		let mut kept_packets = Vec::<VorbisAudioPacket>::new();
+		let first_sample_packet = loop {
+			let packet = next_packet()?.ok_or(VorbisError::UnrepresentableCutRange)?;
+
+			if packet.end_position > start {
+				break packet;
+			}
+
+			// The first packet of a stream is always at a page boundary
+			if !cut_at_pages || packet.ends_page || kept_packets.is_empty() {
+				kept_packets.clear();
+			}
+			kept_packets.push(packet);
+		};
+
+		let priming_packet_end = kept_packets[0].end_position;
+		let start = if cut_at_pages {
+			priming_packet_end.max(minimum_start)
+		} else {
+			start
+		};
+		let trims_start = start > priming_packet_end;
+
+		// Ending the page after the first packet that decodes to samples makes decoders
+		// trim samples from its start, as mandated by the Vorbis I specification. When
+		// cutting at page boundaries, this packet may be kept after the priming packet
+		let first_sample_packet_kept = kept_packets.len() > 1;
+		for (i, packet) in kept_packets.into_iter().enumerate() {
+			// The granule position of the priming packet may be negative when trimming
+			// samples at the start. In that case, signal that it is not known, as negative
+			// granule positions are invalid. It is never the last packet on its page
+			let granule_position = packet.end_position as i64 - start as i64;
+			writer.write_audio_packet(
+				packet.data,
+				granule_position.max(-1),
+				trims_start && i == 1
+			)?;
+		}
+
+		let mut packet = first_sample_packet;
+		let mut is_first_sample_packet = !first_sample_packet_kept;
+		loop {
+			let next = next_packet()?;
+
+			// End trimming can only be honored for the last packet of the stream
+			let packet_end = match next {
+				Some(_) => packet.end_position,
+				None => packet.trimmed_end_position.unwrap_or(packet.end_position)
+			};
+			let reached_end =
+				end.is_some_and(|end| packet_end >= end && (!cut_at_pages || packet.ends_page));
+			let is_last_packet = next.is_none() || reached_end;
+
+			let output_end = match end {
+				Some(end) if is_last_packet && !cut_at_pages => packet_end.min(end),
+				_ => packet_end
+			};
+
+			// Decoders can only trim samples from the first and last packets that decode to
+			// samples, and can't trim samples from both ends of the same packet
+			if output_end <= start
+				|| (is_first_sample_packet && trims_start && output_end < packet.end_position)
+			{
+				return Err(VorbisError::UnrepresentableCutRange);
+			}
+
+			writer.write_audio_packet(
+				packet.data,
+				(output_end - start) as i64,
+				is_first_sample_packet && trims_start
+			)?;
+
+			match next {
+				Some(next) if !is_last_packet => packet = next,
+				_ => return Ok(start..output_end)
+			}
+			is_first_sample_packet = false;
+		}
+	}
+}
+
+/// Checks whether the streams read by the specified readers can be merged into a single
+/// logical stream, which requires them to have the same audio parameters and setup headers.
+/// Streams whose last packet trims samples from its end can't be followed by merged
+/// streams, as end trimming can only be honored for the last packet of a logical stream.
+fn are_mergeable<R: Read>(
+	reader: &VorbisPacketReader<R>,
+	other: &VorbisPacketReader<R>,
+	reader_trims_end: bool
+) -> bool {
+	if reader_trims_end {
+		return false;
+	}
+
+	// Bytes 16 to 27 of identification headers contain informative bitrate fields,
+	// which are allowed to vary
+	let [identification_header, _, setup_header] = reader.header_packets();
+	let [other_identification_header, _, other_setup_header] = other.header_packets();
+
+	identification_header.len() == other_identification_header.len()
+		&& identification_header.get(..16) == other_identification_header.get(..16)
+		&& identification_header.get(28..) == other_identification_header.get(28..)
+		&& setup_header == other_setup_header
+}
+
+#[cfg(test)]
+mod test {
+	use super::{VorbisRepairReport, VorbisStreamEditor};
+	use crate::{
+		VorbisDecoder, VorbisError,
+		common::test_util::{encode_mono, encode_tone, noise, page_offsets},
+		editor::editor_util::VorbisPacketReader
+	};
+
+	fn decoded_sample_count(encoded_ogg: &[u8]) -> usize {
+		let mut decoder = VorbisDecoder::<&[u8]>::new(encoded_ogg).unwrap();
+		let mut sample_count = 0;
+		while let Some(block) = decoder.decode_audio_block().unwrap() {
+			sample_count += block.samples()[0].len();
+		}
+		sample_count
+	}
+
+	#[test]
+	fn cut_is_sample_accurate() {
+		let encoded_ogg = encode_tone(16000, 0);
+
+		let mut cut_ogg = vec![];
+		let range = VorbisStreamEditor::new()
+			.cut(&*encoded_ogg, &mut cut_ogg, 1000..5000)
+			.unwrap();
+
+		assert_eq!(range, 1000..5000);
+		assert_eq!(decoded_sample_count(&cut_ogg), 4000);
+	}
+
+	#[test]
+	fn concatenate_merges_compatible_streams() {
+		// Cut the first stream right at the end of a packet, so that it does not trim
+		// any samples at its end and can be merged with the next
+		let source_ogg = encode_tone(16000, 0);
+		let mut source_reader = VorbisPacketReader::new(&*source_ogg).unwrap();
+		let last_packet = loop {
+			let packet = source_reader.read_audio_packet().unwrap().unwrap();
+			if packet.end_position >= 8000 {
+				break packet;
+			}
+		};
+		let mut first_ogg = vec![];
+		VorbisStreamEditor::new()
+			.cut(
+				&*source_ogg,
+				&mut first_ogg,
+				..last_packet.end_position - source_reader.start_trim()
+			)
+			.unwrap();
+
+		let second_ogg = encode_tone(16000, 1);
+		let second_first_packet = VorbisPacketReader::new(&*second_ogg)
+			.unwrap()
+			.read_audio_packet()
+			.unwrap()
+			.unwrap();
+
+		let mut concatenated_ogg = vec![];
+		let stream_count = VorbisStreamEditor::new()
+			.concatenate([&*first_ogg, &*second_ogg], &mut concatenated_ogg)
+			.unwrap();
+
+		// The packets at the junction are lapped together, decoding to a quarter of
+		// each of their block sizes
+		let junction_sample_count =
+			(last_packet.blocksize / 4 + second_first_packet.blocksize / 4) as usize;
+		assert_eq!(stream_count, 1);
+		assert_eq!(
+			decoded_sample_count(&concatenated_ogg),
+			decoded_sample_count(&first_ogg) + junction_sample_count + 16000
+		);
+	}
+
+	#[test]
+	fn concatenate_chains_streams_with_trimmed_ends() {
+		let first_ogg = encode_tone(16000, 0);
+		let second_ogg = encode_tone(16000, 1);
+
+		let mut concatenated_ogg = vec![];
+		let stream_count = VorbisStreamEditor::new()
+			.concatenate([&*first_ogg, &*second_ogg], &mut concatenated_ogg)
+			.unwrap();
+
+		assert_eq!(stream_count, 2);
+
+		// The first logical stream still honors its end trimming. Decoders report chaining
+		// once they reach the second one
+		let mut decoder = VorbisDecoder::<&[u8]>::new(&*concatenated_ogg).unwrap();
+		let mut sample_count = 0;
+		loop {
+			match decoder.decode_audio_block() {
+				Ok(Some(block)) => sample_count += block.samples()[0].len(),
+				Err(VorbisError::UnsupportedStreamChaining) => break,
+				result => panic!("Unexpected decoding result: {:?}", result.map(|_| ()))
+			}
+		}
+		assert_eq!(sample_count, 16000);
+	}
+
+	#[test]
+	fn repair_keeps_intact_streams() {
+		let encoded_ogg = encode_tone(16000, 0);
+
+		let mut repaired_ogg = vec![];
+		let report = VorbisStreamEditor::new()
+			.repair(&*encoded_ogg, &mut repaired_ogg)
+			.unwrap();
+
+		assert_eq!(report, VorbisRepairReport::default());
+		assert_eq!(decoded_sample_count(&repaired_ogg), 16000);
+	}
+
+	#[test]
+	fn repair_fixes_damaged_streams() {
+		// Noise makes for large enough streams with many pages
+		let mut encoded_ogg = encode_mono(&noise(160_000), 0);
+		let page_offsets = page_offsets(&encoded_ogg);
+
+		// Truncate the stream in the middle of its second to last page, flip a byte of a
+		// page body to make it fail its CRC check, and insert some garbage between pages
+		let truncated_length = page_offsets[page_offsets.len() - 2] + 50;
+		let truncated_bytes = 50;
+		encoded_ogg.truncate(truncated_length);
+		let corrupt_page_offset = page_offsets[page_offsets.len() * 3 / 4];
+		let corrupt_page_segments = usize::from(encoded_ogg[corrupt_page_offset + 26]);
+		encoded_ogg[corrupt_page_offset + 27 + corrupt_page_segments] ^= 0xFF;
+		let garbage_offset = page_offsets[page_offsets.len() / 2];
+		encoded_ogg.splice(garbage_offset..garbage_offset, [0; 100]);
+
+		let mut repaired_ogg = vec![];
+		let report = VorbisStreamEditor::new()
+			.repair(&*encoded_ogg, &mut repaired_ogg)
+			.unwrap();
+
+		assert!(report.is_damaged());
+		assert_eq!(report.corrupt_pages, 1);
+		assert_eq!(report.holes, 1);
+		assert_eq!(report.dropped_packets, 0);
+		assert!(report.added_end_of_stream);
+		assert!(report.skipped_bytes > 100 + truncated_bytes);
+
+		// Repaired streams decode without holes, and are not damaged anymore
+		let decoded_samples = decoded_sample_count(&repaired_ogg);
+		assert!(decoded_samples > 100_000 && decoded_samples < 160_000);
+
+		let mut rerepaired_ogg = vec![];
+		let report = VorbisStreamEditor::new()
+			.repair(&*repaired_ogg, &mut rerepaired_ogg)
+			.unwrap();
+		assert!(!report.is_damaged());
+		assert_eq!(rerepaired_ogg, repaired_ogg);
+	}
+}
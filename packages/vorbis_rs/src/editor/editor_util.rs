@@ -0,0 +1,550 @@
+use std::{
+	collections::VecDeque,
+	io::{self, Read, Write}
+};
+
+use crate::{
+	common::{
+		OggPacket, OggPageSeek, OggStream, OggSync, RawVorbisComments, VorbisError, VorbisInfo,
+		VorbisLibrary, VorbisLibraryError, VorbisLibraryErrorKind
+	},
+	editor::VorbisRepairReport
+};
+
+/// The magic bytes every Vorbis identification header packet begins with.
+const IDENTIFICATION_HEADER_MAGIC: &[u8] = b"\x01vorbis";
+
+/// An audio packet read from a Vorbis stream by a [`VorbisPacketReader`].
+pub(crate) struct VorbisAudioPacket {
+	/// The packet data.
+	pub data: Vec<u8>,
+	/// The block size of this packet, in samples.
+	pub blocksize: u32,
+	/// The position of the sample right after the last sample this packet decodes to,
+	/// counting every sample decoded since the beginning of the stream, including samples
+	/// that decoders should discard due to start or end trimming.
+	pub end_position: u64,
+	/// The end position of this packet after applying end trimming, if the packet is the
+	/// last of its stream and its granule position signals that some samples should be
+	/// discarded. It is never greater than `end_position`.
+	pub trimmed_end_position: Option<u64>,
+	/// Whether this packet was the last packet to end on its page.
+	pub ends_page: bool
+}
+
+/// Reads the headers and audio packets of the first logical Vorbis stream contained in a
+/// physical Ogg bitstream, computing the sample positions of each audio packet. Pages that
+/// belong to other logical streams are ignored.
+pub(crate) struct VorbisPacketReader<R: Read> {
+	source: R,
+	ogg_sync: OggSync,
+	ogg_stream: OggStream,
+	stream_serial: i32,
+	vorbis_info: VorbisInfo,
+	header_packets: [Vec<u8>; 3],
+	previous_blocksize: Option<u32>,
+	position: u64,
+	pending_packets: VecDeque<VorbisAudioPacket>,
+	granule_position_offset: Option<i64>,
+	start_trim: u64,
+	ended: bool
+}
+
+impl<R: Read> VorbisPacketReader<R> {
+	/// Creates a new Vorbis packet reader, reading and validating the Vorbis header packets
+	/// of the first logical Vorbis stream found in the specified source.
+	pub fn new(mut source: R) -> Result<Self, VorbisError> {
+		let mut ogg_sync = OggSync::new();
+
+		// Logical streams begin with a BOS page that only contains their first header.
+		// Multiplexed streams begin with the BOS pages of every logical stream
+		let (mut ogg_stream, stream_serial) = loop {
+			let page = ogg_sync
+				.read_page(&mut source)?
+				.ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+
+			if !page.is_bos() {
+				return Err(VorbisLibraryError {
+					library: VorbisLibrary::Vorbis,
+					function: "vorbis_synthesis_idheader",
+					kind: VorbisLibraryErrorKind::NotVorbis
+				}
+				.into());
+			}
+
+			if page.body().starts_with(IDENTIFICATION_HEADER_MAGIC) {
+				let mut ogg_stream = OggStream::new(page.stream_serial())?;
+				ogg_stream.page_in(&page)?;
+				break (ogg_stream, page.stream_serial());
+			}
+		};
+
+		let mut vorbis_info = VorbisInfo::new();
+		let mut raw_comments = RawVorbisComments::new();
+		let mut header_packets: [Vec<u8>; 3] = Default::default();
+
+		for header_packet_data in &mut header_packets {
+			let header_packet = loop {
+				if let Some(packet) = ogg_stream.packet_out()? {
+					break packet;
+				}
+
+				let page = ogg_sync
+					.read_page(&mut source)?
+					.ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+				if page.stream_serial() == stream_serial {
+					ogg_stream.page_in(&page)?;
+				}
+			};
+
+			vorbis_info.synthesis_headerin(&mut raw_comments, &header_packet)?;
+			header_packet_data.extend_from_slice(header_packet.data());
+		}
+
+		Ok(Self {
+			source,
+			ogg_sync,
+			ogg_stream,
+			stream_serial,
+			vorbis_info,
+			header_packets,
+			previous_blocksize: None,
+			position: 0,
+			pending_packets: VecDeque::new(),
+			granule_position_offset: None,
+			start_trim: 0,
+			ended: false
+		})
+	}
+
+	/// Returns the serial of the logical Vorbis stream read by this reader.
+	pub fn stream_serial(&self) -> i32 {
+		self.stream_serial
+	}
+
+	/// Returns the identification, comment and setup header packets of the stream.
+	pub fn header_packets(&self) -> &[Vec<u8>; 3] {
+		&self.header_packets
+	}
+
+	/// Returns the number of samples that decoders should discard at the beginning of
+	/// the stream, as signalled by the granule position of its first audio page. This
+	/// value is only known after reading the first audio packet.
+	pub fn start_trim(&self) -> u64 {
+		self.start_trim
+	}
+
+	/// Reads the next audio packet of the stream, returning `Ok(None)` when the stream
+	/// ends. Holes in the stream data are reported as errors.
+	pub fn read_audio_packet(&mut self) -> Result<Option<VorbisAudioPacket>, VorbisError> {
+		// The sample positions of the packets are only known for sure after reading the
+		// first granule position, so packets are buffered until then
+		while self.granule_position_offset.is_none() && !self.ended {
+			self.read_next_packet()?;
+		}
+
+		if self.pending_packets.is_empty() && !self.ended {
+			self.read_next_packet()?;
+		}
+
+		let Some(mut packet) = self.pending_packets.pop_front() else {
+			return Ok(None);
+		};
+
+		if let Some(trimmed_end_position) = &mut packet.trimmed_end_position {
+			*trimmed_end_position = trimmed_end_position
+				.saturating_add_signed(-self.granule_position_offset.unwrap_or(0))
+				.min(packet.end_position);
+		}
+
+		Ok(Some(packet))
+	}
+
+	/// Reads the next audio packet of the stream into the pending packets queue, or marks
+	/// the stream as ended if there are no more packets.
+	fn read_next_packet(&mut self) -> Result<(), VorbisError> {
+		let packet = loop {
+			if let Some(packet) = self.ogg_stream.packet_out()? {
+				break packet;
+			}
+
+			let Some(page) = self.ogg_sync.read_page(&mut self.source)? else {
+				self.ended = true;
+				return Ok(());
+			};
+			if page.stream_serial() == self.stream_serial {
+				self.ogg_stream.page_in(&page)?;
+			}
+		};
+
+		let blocksize = self.vorbis_info.packet_blocksize(&packet)?;
+		// The first audio packet does not decode to any samples. Every subsequent packet
+		// decodes to the overlapping halves of its window and the previous one
+		if let Some(previous_blocksize) = self.previous_blocksize {
+			self.position += u64::from(previous_blocksize / 4 + blocksize / 4);
+		}
+		self.previous_blocksize = Some(blocksize);
+
+		let granule_position = packet.granule_position();
+		let ends_page = granule_position != -1;
+
+		if ends_page && self.granule_position_offset.is_none() {
+			// The first granule position tells how many samples to discard at the start
+			// of the stream. However, if the first page is also the last one, the Vorbis
+			// I specification mandates to discard samples at the end instead
+			let offset = if packet.is_eos() {
+				0
+			} else {
+				granule_position - self.position as i64
+			};
+
+			self.granule_position_offset = Some(offset);
+			self.start_trim = offset.min(0).unsigned_abs();
+		}
+
+		let is_eos = packet.is_eos();
+		self.pending_packets.push_back(VorbisAudioPacket {
+			data: packet.data().into(),
+			blocksize,
+			end_position: self.position,
+			trimmed_end_position: (is_eos && ends_page)
+				.then(|| u64::try_from(granule_position).unwrap_or(0)),
+			ends_page
+		});
+
+		// Subsequent logical streams in a chain are not considered
+		self.ended |= is_eos;
+
+		Ok(())
+	}
+}
+
+/// Reads the headers and audio packets of the first logical Vorbis stream contained in a
+/// possibly damaged physical Ogg bitstream, skipping any garbage data, corrupt pages and
+/// undecodable packets, and computing the sample positions of each audio packet from
+/// their block sizes. The damage found is accounted for in a [`VorbisRepairReport`].
+pub(crate) struct VorbisPacketSalvager<R: Read> {
+	source: R,
+	ogg_sync: OggSync,
+	ogg_stream: OggStream,
+	stream_serial: i32,
+	vorbis_info: VorbisInfo,
+	header_packets: [Vec<u8>; 3],
+	previous_blocksize: Option<u32>,
+	position: u64,
+	last_page_end: Option<(i64, u64)>,
+	pending_packets: VecDeque<VorbisAudioPacket>,
+	start_trim: Option<u64>,
+	report: VorbisRepairReport,
+	ended: bool
+}
+
+impl<R: Read> VorbisPacketSalvager<R> {
+	/// Creates a new Vorbis packet salvager, reading and validating the Vorbis header
+	/// packets of the first logical Vorbis stream found in the specified source. Header
+	/// packets can't be salvaged, so any damage to them is reported as an error.
+	pub fn new(source: R) -> Result<Self, VorbisError> {
+		let mut salvager = Self {
+			source,
+			ogg_sync: OggSync::new(),
+			ogg_stream: OggStream::new(0)?,
+			stream_serial: 0,
+			vorbis_info: VorbisInfo::new(),
+			header_packets: Default::default(),
+			previous_blocksize: None,
+			position: 0,
+			// The source stream is assumed to begin at granule position zero
+			last_page_end: Some((0, 0)),
+			pending_packets: VecDeque::new(),
+			start_trim: None,
+			report: VorbisRepairReport::default(),
+			ended: false
+		};
+
+		// The BOS flag of the page with the identification header may be damaged, so
+		// every page is looked at until one with an identification header is found
+		loop {
+			let page = match salvager.ogg_sync.seek_page() {
+				OggPageSeek::Page(page) => page,
+				OggPageSeek::Skipped {
+					bytes,
+					capture_pattern
+				} => {
+					salvager.report.skip(bytes, capture_pattern);
+					continue;
+				}
+				OggPageSeek::NeedData => {
+					if salvager.ogg_sync.read_chunk(&mut salvager.source)? == 0 {
+						return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+					}
+					continue;
+				}
+			};
+
+			if page.body().starts_with(IDENTIFICATION_HEADER_MAGIC) {
+				salvager.stream_serial = page.stream_serial();
+				salvager.ogg_stream = OggStream::new(salvager.stream_serial)?;
+				salvager.ogg_stream.page_in(&page)?;
+				break;
+			}
+		}
+
+		let mut raw_comments = RawVorbisComments::new();
+		for i in 0..salvager.header_packets.len() {
+			let header_packet = loop {
+				if let Some(packet) = salvager.ogg_stream.packet_out()? {
+					break packet;
+				}
+
+				if !salvager.page_in_next_page()? {
+					return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+				}
+			};
+
+			salvager
+				.vorbis_info
+				.synthesis_headerin(&mut raw_comments, &header_packet)?;
+			salvager.header_packets[i].extend_from_slice(header_packet.data());
+		}
+
+		Ok(salvager)
+	}
+
+	/// Returns the serial of the logical Vorbis stream read by this salvager.
+	pub fn stream_serial(&self) -> i32 {
+		self.stream_serial
+	}
+
+	/// Returns the identification, comment and setup header packets of the stream.
+	pub fn header_packets(&self) -> &[Vec<u8>; 3] {
+		&self.header_packets
+	}
+
+	/// Returns the number of samples that decoders should discard at the beginning of
+	/// the stream, as signalled by the granule position of its first audio page, if it
+	/// is intact. This value is only known after reading the first audio packet.
+	pub fn start_trim(&self) -> u64 {
+		self.start_trim.unwrap_or(0)
+	}
+
+	/// Returns a report of the damage found so far.
+	pub fn report(&self) -> &VorbisRepairReport {
+		&self.report
+	}
+
+	/// Reads the next audio packet of the stream, returning `Ok(None)` when the stream
+	/// ends, either because its last packet was read or the source reached its end.
+	pub fn read_audio_packet(&mut self) -> Result<Option<VorbisAudioPacket>, VorbisError> {
+		// The start trim is only known for sure after reading the first granule position,
+		// so packets are buffered until then
+		while self.start_trim.is_none() && !self.ended {
+			self.read_next_packet()?;
+		}
+
+		if self.pending_packets.is_empty() && !self.ended {
+			self.read_next_packet()?;
+		}
+
+		Ok(self.pending_packets.pop_front())
+	}
+
+	/// Reads the next decodable audio packet of the stream into the pending packets
+	/// queue, or marks the stream as ended if there are no more packets.
+	fn read_next_packet(&mut self) -> Result<(), VorbisError> {
+		let (packet, blocksize) = loop {
+			let packet = match self.ogg_stream.packet_out() {
+				Ok(Some(packet)) => packet,
+				Ok(None) => {
+					if !self.page_in_next_page()? {
+						self.ended = true;
+						self.report.added_end_of_stream = true;
+						return Ok(());
+					}
+					continue;
+				}
+				Err(_) => {
+					// Granule positions of the source stream can't be related to the
+					// computed sample positions across a hole
+					self.report.holes += 1;
+					self.last_page_end = None;
+					self.start_trim.get_or_insert(0);
+					continue;
+				}
+			};
+
+			// Empty packets are legal, but decode to nothing, so they are dropped
+			// silently. Other packets that can't be decoded are dropped as damaged
+			match self.vorbis_info.packet_blocksize(&packet) {
+				Ok(blocksize) => break (packet, blocksize),
+				Err(_) if packet.data().is_empty() => {}
+				Err(_) => self.report.dropped_packets += 1
+			}
+		};
+
+		// The first audio packet does not decode to any samples. Every subsequent packet
+		// decodes to the overlapping halves of its window and the previous one
+		if let Some(previous_blocksize) = self.previous_blocksize {
+			self.position += u64::from(previous_blocksize / 4 + blocksize / 4);
+		}
+		self.previous_blocksize = Some(blocksize);
+
+		let granule_position = packet.granule_position();
+		let ends_page = granule_position != -1;
+		let is_eos = packet.is_eos();
+
+		if ends_page && self.start_trim.is_none() {
+			// Like in VorbisPacketReader, a first page that is also the last one
+			// signals end trimming instead
+			self.start_trim = Some(if is_eos {
+				0
+			} else {
+				(granule_position - self.position as i64)
+					.min(0)
+					.unsigned_abs()
+			});
+		}
+
+		// The end trimming signalled by the last page is only honored if the granule
+		// position of the previous page is known, so that it can be told apart from
+		// the effects of any earlier damage
+		let trimmed_end_position = self.last_page_end.filter(|_| is_eos && ends_page).and_then(
+			|(last_granule_position, last_position)| {
+				let source_samples =
+					u64::try_from(granule_position - last_granule_position).ok()?;
+				let samples = self.position - last_position;
+				(source_samples < samples).then(|| self.position - (samples - source_samples))
+			}
+		);
+
+		if ends_page {
+			self.last_page_end = Some((granule_position, self.position));
+		}
+
+		self.pending_packets.push_back(VorbisAudioPacket {
+			data: packet.data().into(),
+			blocksize,
+			end_position: self.position,
+			trimmed_end_position,
+			ends_page
+		});
+
+		// Subsequent logical streams in a chain are not considered
+		self.ended |= is_eos;
+
+		Ok(())
+	}
+
+	/// Submits the next intact page of the Vorbis stream to the Ogg stream, skipping any
+	/// damaged data and pages of other logical streams. Returns `false` if the source
+	/// reached its end, accounting for any incomplete page data left as skipped.
+	fn page_in_next_page(&mut self) -> Result<bool, VorbisError> {
+		loop {
+			match self.ogg_sync.seek_page() {
+				OggPageSeek::Page(page) => {
+					if page.stream_serial() == self.stream_serial {
+						self.ogg_stream.page_in(&page)?;
+						return Ok(true);
+					}
+				}
+				OggPageSeek::Skipped {
+					bytes,
+					capture_pattern
+				} => self.report.skip(bytes, capture_pattern),
+				OggPageSeek::NeedData => {
+					if self.ogg_sync.read_chunk(&mut self.source)? == 0 {
+						let truncated_bytes = self.ogg_sync.buffered_len();
+						if truncated_bytes > 0 {
+							self.report.skip(truncated_bytes, false);
+						}
+						return Ok(false);
+					}
+				}
+			}
+		}
+	}
+}
+
+/// Writes the headers and audio packets of a logical Vorbis stream to a sink.
+///
+/// Audio packets are written with a delay of one packet, so that the last packet
+/// can be marked as the end of the stream when the writer is finished.
+pub(crate) struct VorbisPacketWriter<W: Write> {
+	sink: W,
+	ogg_stream: OggStream,
+	minimum_page_data_size: Option<u16>,
+	pending_packet: Option<(Vec<u8>, i64, bool)>
+}
+
+impl<W: Write> VorbisPacketWriter<W> {
+	/// Creates a new Vorbis packet writer, writing the specified header packets to the
+	/// sink in a new logical stream with the specified serial.
+	pub fn new(
+		mut sink: W,
+		stream_serial: i32,
+		header_packets: &[Vec<u8>; 3],
+		minimum_page_data_size: Option<u16>
+	) -> Result<Self, VorbisError> {
+		let mut ogg_stream = OggStream::new(stream_serial)?;
+
+		for (i, header_packet) in header_packets.iter().enumerate() {
+			OggPacket::from_data(header_packet, 0, i == 0, false).submit(&mut ogg_stream)?;
+		}
+
+		// Header packets must end on their own page, as mandated by the Vorbis I spec
+		ogg_stream.flush(&mut sink)?;
+
+		Ok(Self {
+			sink,
+			ogg_stream,
+			minimum_page_data_size,
+			pending_packet: None
+		})
+	}
+
+	/// Writes an audio packet with the specified granule position. If `end_page` is true,
+	/// the page this packet is written to will end right after it.
+	pub fn write_audio_packet(
+		&mut self,
+		data: Vec<u8>,
+		granule_position: i64,
+		end_page: bool
+	) -> Result<(), VorbisError> {
+		if let Some(pending_packet) =
+			self.pending_packet
+				.replace((data, granule_position, end_page))
+		{
+			self.submit_packet(pending_packet, false)?;
+		}
+
+		Ok(())
+	}
+
+	/// Finishes the logical stream, marking the last written audio packet as the end of the
+	/// stream, and returns the sink back to the caller.
+	pub fn finish(mut self) -> Result<W, VorbisError> {
+		if let Some(pending_packet) = self.pending_packet.take() {
+			self.submit_packet(pending_packet, true)?;
+		}
+
+		self.ogg_stream.flush(&mut self.sink)?;
+
+		Ok(self.sink)
+	}
+
+	/// Submits the specified audio packet to the Ogg stream, writing any pages that
+	/// become complete.
+	fn submit_packet(
+		&mut self,
+		(data, granule_position, end_page): (Vec<u8>, i64, bool),
+		eos: bool
+	) -> Result<(), VorbisError> {
+		OggPacket::from_data(&data, granule_position, false, eos).submit(&mut self.ogg_stream)?;
+
+		if end_page {
+			self.ogg_stream.flush(&mut self.sink)
+		} else {
+			self.ogg_stream
+				.write_pending_pages(&mut self.sink, self.minimum_page_data_size)
+		}
+	}
+}
@@ -1,5 +1,16 @@
 mod encoder_util;
-pub use encoder_util::VorbisBitrateManagementStrategy;
+pub use encoder_util::{VorbisBitrateManagementStrategy, VorbisPageBoundary};
+
+mod encoder_skeleton;
+
+mod encoder_config;
+pub use encoder_config::VorbisEncoderConfig;
+
+mod encoder_preset;
+pub use encoder_preset::VorbisEncoderPreset;
+
+mod encoder_parameters;
+pub use encoder_parameters::VorbisEncodingParameters;
 
 mod encoder_impl;
 pub use encoder_impl::{VorbisEncoder, VorbisEncoderBuilder};
@@ -0,0 +1,639 @@
+use std::{
+	borrow::Cow,
+	io::{Cursor, Seek, Write},
+	num::{NonZeroU8, NonZeroU32, NonZeroU64, NonZeroUsize},
+	sync::atomic::{AtomicUsize, Ordering},
+	thread
+};
+
+#[allow(unused_imports)]
+use crate::VorbisEncoderBuilder;
+use crate::{
+	VorbisEncoder,
+	common::{
+		VorbisChannelLayout, VorbisChannelMatrix, VorbisChannelOrder, VorbisComments, VorbisError,
+		VorbisInfo
+	},
+	encoder::{VorbisBitrateManagementStrategy, VorbisEncoderPreset, VorbisEncodingParameters}
+};
+
+/// The number of samples per channel of the audio blocks
+/// [`VorbisEncoderConfig::encode_batch`] submits to encoders.
+const BATCH_AUDIO_BLOCK_SIZE: usize = 4096;
+
+/// A reusable set of Vorbis encoding and Ogg stream encapsulation options, which builds
+/// [`VorbisEncoder`]s for sinks of any type.
+///
+/// Unlike a [`VorbisEncoderBuilder`], a configuration does not own a sink, so it can be
+/// [validated](Self::validate) up front, cloned, compared, and shared across threads to
+/// build any number of encoders. Its options work like the [`VorbisEncoderBuilder`]
+/// options with the same name, which are documented there.
+///
+/// # Examples
+///
+/// ```
+/// # use std::num::{NonZeroU8, NonZeroU32};
+/// # use vorbis_rs::{VorbisBitrateManagementStrategy, VorbisEncoderConfig};
+/// # fn main() -> Result<(), vorbis_rs::VorbisError> {
+/// let mut config =
+///     VorbisEncoderConfig::new(NonZeroU32::new(44_100).unwrap(), NonZeroU8::new(2).unwrap());
+/// config.bitrate_management_strategy(VorbisBitrateManagementStrategy::QualityVbr {
+///     target_quality: 0.6
+/// });
+/// config.validate()?;
+///
+/// let mut encoded_ogg = vec![];
+/// let encoder = config.build(&mut encoded_ogg)?;
+/// encoder.finish()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct VorbisEncoderConfig {
+	pub(in crate::encoder) sampling_frequency: NonZeroU32,
+	pub(in crate::encoder) channels: NonZeroU8,
+	pub(in crate::encoder) stream_serial: Option<i32>,
+	pub(in crate::encoder) bitrate_management_strategy: VorbisBitrateManagementStrategy,
+	pub(in crate::encoder) lowpass_frequency: Option<NonZeroU32>,
+	pub(in crate::encoder) channel_coupling: bool,
+	pub(in crate::encoder) comments: VorbisComments,
+	pub(in crate::encoder) minimum_page_data_size: Option<u16>,
+	pub(in crate::encoder) maximum_page_duration: Option<NonZeroU32>,
+	pub(in crate::encoder) page_boundaries: Vec<u64>,
+	pub(in crate::encoder) page_boundary_interval: Option<NonZeroU64>,
+	pub(in crate::encoder) priming_samples: u64,
+	pub(in crate::encoder) total_samples: Option<u64>,
+	pub(in crate::encoder) channel_order: VorbisChannelOrder,
+	pub(in crate::encoder) channel_matrix: Option<VorbisChannelMatrix>,
+	pub(in crate::encoder) replay_gain_tags: bool,
+	pub(in crate::encoder) skeleton_index: Option<NonZeroU32>,
+	#[cfg(feature = "resampling")]
+	pub(in crate::encoder) encoding_sampling_frequency: Option<NonZeroU32>
+}
+
+impl VorbisEncoderConfig {
+	/// Creates a new encoder configuration for a signal with the specified sampling
+	/// frequency and channels, with every other option set to its default.
+	pub fn new(sampling_frequency: NonZeroU32, channels: NonZeroU8) -> Self {
+		Self {
+			sampling_frequency,
+			channels,
+			stream_serial: None,
+			bitrate_management_strategy: VorbisBitrateManagementStrategy::default(),
+			lowpass_frequency: None,
+			channel_coupling: true,
+			comments: VorbisComments::new(),
+			minimum_page_data_size: None,
+			maximum_page_duration: None,
+			page_boundaries: Vec::new(),
+			page_boundary_interval: None,
+			priming_samples: 0,
+			total_samples: None,
+			channel_order: VorbisChannelOrder::default(),
+			channel_matrix: None,
+			replay_gain_tags: false,
+			skeleton_index: None,
+			#[cfg(feature = "resampling")]
+			encoding_sampling_frequency: None
+		}
+	}
+
+	/// Sets the sampling frequency of the signal to encode, in Hertz (Hz).
+	pub fn sampling_frequency(&mut self, sampling_frequency: NonZeroU32) -> &mut Self {
+		self.sampling_frequency = sampling_frequency;
+		self
+	}
+
+	/// Sets the number of channels of the signal to encode.
+	pub fn channels(&mut self, channels: NonZeroU8) -> &mut Self {
+		self.channels = channels;
+		self
+	}
+
+	/// Sets the sampling frequency to resample the signal to before encoding it. See
+	/// [`VorbisEncoderBuilder::encoding_sampling_frequency`].
+	#[cfg(feature = "resampling")]
+	pub fn encoding_sampling_frequency(
+		&mut self,
+		encoding_sampling_frequency: Option<NonZeroU32>
+	) -> &mut Self {
+		self.encoding_sampling_frequency = encoding_sampling_frequency;
+		self
+	}
+
+	/// Sets the number of channels of the signal to encode to the number of channels of
+	/// the specified layout.
+	pub fn channel_layout(&mut self, channel_layout: VorbisChannelLayout) -> &mut Self {
+		self.channels(channel_layout.channel_count())
+	}
+
+	/// Sets the order of the channels in the audio blocks that will be submitted to the
+	/// encoders. See [`VorbisEncoderBuilder::channel_order`].
+	pub fn channel_order(&mut self, channel_order: VorbisChannelOrder) -> &mut Self {
+		self.channel_order = channel_order;
+		self
+	}
+
+	/// Sets the matrix to convert the channels of the signal with before encoding it. See
+	/// [`VorbisEncoderBuilder::channel_matrix`].
+	pub fn channel_matrix(&mut self, channel_matrix: Option<VorbisChannelMatrix>) -> &mut Self {
+		self.channel_matrix = channel_matrix;
+		self
+	}
+
+	/// Sets the serial of the Ogg Vorbis streams to be generated, which is the same for
+	/// every built encoder. See [`VorbisEncoderBuilder::stream_serial`].
+	///
+	/// By default, this is `None`, which generates a random serial for every built encoder
+	/// if the `stream-serial-rng` feature is enabled, and uses a serial of zero otherwise.
+	pub fn stream_serial(&mut self, stream_serial: Option<i32>) -> &mut Self {
+		self.stream_serial = stream_serial;
+		self
+	}
+
+	/// Sets the bitrate management strategy to use, determining the tradeoff
+	/// between audio quality and stream size and bandwidth requirements.
+	pub fn bitrate_management_strategy(
+		&mut self,
+		bitrate_management_strategy: VorbisBitrateManagementStrategy
+	) -> &mut Self {
+		self.bitrate_management_strategy = bitrate_management_strategy;
+		self
+	}
+
+	/// Sets the lowpass frequency to encode with, in Hertz (Hz). See
+	/// [`VorbisEncoderBuilder::lowpass_frequency`].
+	pub fn lowpass_frequency(&mut self, lowpass_frequency: Option<NonZeroU32>) -> &mut Self {
+		self.lowpass_frequency = lowpass_frequency;
+		self
+	}
+
+	/// Enables or disables channel coupling. See [`VorbisEncoderBuilder::channel_coupling`].
+	pub fn channel_coupling(&mut self, channel_coupling: bool) -> &mut Self {
+		self.channel_coupling = channel_coupling;
+		self
+	}
+
+	/// Applies the options of the specified preset to this configuration. See
+	/// [`VorbisEncoderPreset`].
+	pub fn preset(&mut self, preset: VorbisEncoderPreset) -> &mut Self {
+		self.bitrate_management_strategy = preset.bitrate_management_strategy();
+		self.lowpass_frequency = preset.lowpass_frequency();
+		self.channel_coupling = preset.channel_coupling();
+		self.maximum_page_duration = preset.maximum_page_duration();
+		self
+	}
+
+	/// Adds a single comment tag to the Vorbis comments header that will be
+	/// generated.
+	pub fn comment_tag<'tag, 'value>(
+		&mut self,
+		tag: impl Into<Cow<'tag, str>>,
+		value: impl Into<Cow<'value, str>>
+	) -> Result<&mut Self, VorbisError> {
+		self.comment_tags([(tag, value)])
+	}
+
+	/// Adds several comment tags to the Vorbis comments header that will be
+	/// generated.
+	pub fn comment_tags<'tag, 'value, T: Into<Cow<'tag, str>>, V: Into<Cow<'value, str>>>(
+		&mut self,
+		tags: impl IntoIterator<Item = (T, V)>
+	) -> Result<&mut Self, VorbisError> {
+		for (tag, value) in tags {
+			self.comments.add(tag, value)?;
+		}
+
+		Ok(self)
+	}
+
+	/// Sets the Vorbis comments that will be written to the comments header, replacing
+	/// any comments added before.
+	pub fn comments(&mut self, comments: VorbisComments) -> &mut Self {
+		self.comments = comments;
+		self
+	}
+
+	/// Returns a mutable reference to the Vorbis comments that will be written to the
+	/// comments header, allowing to edit them in place.
+	pub fn comments_mut(&mut self) -> &mut VorbisComments {
+		&mut self.comments
+	}
+
+	/// Specifies the minimum size of Vorbis stream data to put into each Ogg page. See
+	/// [`VorbisEncoderBuilder::minimum_page_data_size`].
+	pub fn minimum_page_data_size(&mut self, minimum_page_data_size: Option<u16>) -> &mut Self {
+		self.minimum_page_data_size = minimum_page_data_size;
+		self
+	}
+
+	/// Sets the maximum duration of the audio data in each Ogg page, in milliseconds. See
+	/// [`VorbisEncoderBuilder::maximum_page_duration`].
+	pub fn maximum_page_duration(
+		&mut self,
+		maximum_page_duration: Option<NonZeroU32>
+	) -> &mut Self {
+		self.maximum_page_duration = maximum_page_duration;
+		self
+	}
+
+	/// Sets the input sample positions the encoders will start new Ogg pages at. See
+	/// [`VorbisEncoderBuilder::page_boundaries`].
+	pub fn page_boundaries(
+		&mut self,
+		sample_positions: impl IntoIterator<Item = u64>
+	) -> &mut Self {
+		self.page_boundaries = sample_positions.into_iter().collect();
+		self
+	}
+
+	/// Sets the interval between the input sample positions the encoders will start new Ogg
+	/// pages at. See [`VorbisEncoderBuilder::page_boundary_interval`].
+	pub fn page_boundary_interval(
+		&mut self,
+		page_boundary_interval: Option<NonZeroU64>
+	) -> &mut Self {
+		self.page_boundary_interval = page_boundary_interval;
+		self
+	}
+
+	/// Sets the number of samples at the beginning of the encoded signal that decoders should
+	/// discard. See [`VorbisEncoderBuilder::priming_samples`].
+	pub fn priming_samples(&mut self, priming_samples: u64) -> &mut Self {
+		self.priming_samples = priming_samples;
+		self
+	}
+
+	/// Sets the exact number of samples the encoded streams will decode to. See
+	/// [`VorbisEncoderBuilder::total_samples`].
+	pub fn total_samples(&mut self, total_samples: Option<u64>) -> &mut Self {
+		self.total_samples = total_samples;
+		self
+	}
+
+	/// Enables or disables writing ReplayGain track gain and peak tags. See
+	/// [`VorbisEncoderBuilder::replay_gain_tags`]. Encoders with this option enabled can
+	/// only be built with [`build_seekable`](Self::build_seekable).
+	pub fn replay_gain_tags(&mut self, replay_gain_tags: bool) -> &mut Self {
+		self.replay_gain_tags = replay_gain_tags;
+		self
+	}
+
+	/// Sets the maximum number of keypoints of the index of an Ogg Skeleton stream to add to
+	/// the encoded streams. See [`VorbisEncoderBuilder::skeleton_index`]. Encoders with this
+	/// option set can only be built with [`build_seekable`](Self::build_seekable).
+	pub fn skeleton_index(&mut self, maximum_keypoints: Option<NonZeroU32>) -> &mut Self {
+		self.skeleton_index = maximum_keypoints;
+		self
+	}
+
+	/// Checks that encoders can be built with this configuration, returning the error
+	/// building them would return otherwise, such as when `libvorbisenc` does not support
+	/// the configured sampling frequency, channels and bitrate management strategy. Errors
+	/// writing to a sink may still happen when building encoders.
+	pub fn validate(&self) -> Result<(), VorbisError> {
+		self.encoding_vorbis_info().map(|_| ())
+	}
+
+	/// Returns the bitrates, block sizes and other parameters `libvorbisenc` selects for
+	/// this configuration, which can estimate the size of the encoded streams, without
+	/// building any encoder. Like [`validate`](Self::validate), an error is returned if
+	/// encoders can't be built with this configuration.
+	pub fn encoding_parameters(&self) -> Result<VorbisEncodingParameters, VorbisError> {
+		VorbisEncodingParameters::from_config(self)
+	}
+
+	/// Creates a [`VorbisEncoder`] with this configuration, writing header data to the
+	/// specified sink. Errors may be returned when either the configuration is invalid or an
+	/// I/O failure happens.
+	///
+	/// Encoders whose configuration requires a seekable sink, due to enabling
+	/// [ReplayGain tags](Self::replay_gain_tags) or a [Skeleton index](Self::skeleton_index),
+	/// must be built with [`build_seekable`](Self::build_seekable) instead, or a
+	/// [`VorbisError::UnseekableEncoderSink`] error is returned.
+	pub fn build<W: Write>(&self, sink: W) -> Result<VorbisEncoder<W>, VorbisError> {
+		VorbisEncoder::new(self, sink, self.build_stream_serial()?, None)
+	}
+
+	/// Like [`build`](Self::build), but for seekable sinks, which supports every option.
+	pub fn build_seekable<W: Write + Seek>(
+		&self,
+		sink: W
+	) -> Result<VorbisEncoder<W>, VorbisError> {
+		VorbisEncoder::new(self, sink, self.build_stream_serial()?, Some(W::seek))
+	}
+
+	/// Encodes each of the specified planar audio signals to its own Ogg Vorbis stream with
+	/// this configuration, in parallel on the specified number of threads, returning the
+	/// encoded streams or the errors that happened while encoding them, in the same order
+	/// as the signals. By default, as many threads as the available parallelism are used.
+	///
+	/// Each thread [resets](VorbisEncoder::reset) the same encoder from signal to signal,
+	/// so this is much faster than building a new encoder for each of many short signals,
+	/// such as sound effects. The stream serial of each stream is the
+	/// [configured serial](Self::stream_serial), or a random serial drawn once per batch,
+	/// plus the index of its signal, so it does not depend on the number of threads.
+	pub fn encode_batch<C: AsRef<[S]> + Sync, S: AsRef<[f32]>>(
+		&self,
+		audio_signals: &[C],
+		threads: Option<NonZeroUsize>
+	) -> Vec<Result<Vec<u8>, VorbisError>> {
+		#[cfg(feature = "stream-serial-rng")]
+		let base_stream_serial = match self.stream_serial {
+			Some(stream_serial) => stream_serial,
+			None => match generate_random_stream_serial() {
+				Ok(stream_serial) => stream_serial,
+				Err(err) => return audio_signals.iter().map(|_| Err(err.into())).collect()
+			}
+		};
+		#[cfg(not(feature = "stream-serial-rng"))]
+		let base_stream_serial = self.stream_serial.unwrap_or(0);
+		let stream_serial =
+			|audio_signal_index: usize| base_stream_serial.wrapping_add(audio_signal_index as i32);
+
+		let threads = threads
+			.or_else(|| thread::available_parallelism().ok())
+			.map_or(1, NonZeroUsize::get)
+			.min(audio_signals.len());
+		let next_audio_signal_index = AtomicUsize::new(0);
+		let claim_audio_signal = || {
+			let audio_signal_index = next_audio_signal_index.fetch_add(1, Ordering::Relaxed);
+			(audio_signal_index < audio_signals.len()).then_some(audio_signal_index)
+		};
+
+		let mut encoded_streams = thread::scope(|scope| {
+			let workers = (0..threads)
+				.map(|_| {
+					scope.spawn(|| {
+						let mut encoder = None;
+						let mut encoded_streams = vec![];
+
+						let mut audio_signal_index = claim_audio_signal();
+						while let Some(current_audio_signal_index) = audio_signal_index {
+							let signal_encoder = self.encode_batch_audio_signal(
+								encoder.take(),
+								audio_signals[current_audio_signal_index].as_ref(),
+								stream_serial(current_audio_signal_index)
+							);
+
+							// Claim the next signal before finishing this stream, so that
+							// the encoder can be reset with the stream serial of that signal
+							audio_signal_index = claim_audio_signal();
+							let encoded_stream =
+								signal_encoder.and_then(
+									|signal_encoder| match audio_signal_index {
+										Some(next_audio_signal_index) => {
+											let (sink, next_encoder) = signal_encoder
+												.reset_with_serial(
+													Cursor::new(vec![]),
+													stream_serial(next_audio_signal_index)
+												)?;
+											encoder = Some(next_encoder);
+											Ok(sink)
+										}
+										None => signal_encoder.finish()
+									}
+								);
+
+							encoded_streams.push((
+								current_audio_signal_index,
+								encoded_stream.map(Cursor::into_inner)
+							));
+						}
+
+						encoded_streams
+					})
+				})
+				.collect::<Vec<_>>();
+
+			workers
+				.into_iter()
+				.flat_map(|worker| match worker.join() {
+					Ok(encoded_streams) => encoded_streams,
+					Err(panic) => std::panic::resume_unwind(panic)
+				})
+				.collect::<Vec<_>>()
+		});
+
+		encoded_streams.sort_unstable_by_key(|&(audio_signal_index, _)| audio_signal_index);
+		encoded_streams
+			.into_iter()
+			.map(|(_, encoded_stream)| encoded_stream)
+			.collect()
+	}
+
+	/// Encodes the specified planar audio signal for [`encode_batch`](Self::encode_batch)
+	/// with the specified encoder, which is ready to encode a new stream, or a new encoder
+	/// writing a stream with the specified serial if there is none. The returned encoder
+	/// is ready to be finished or reset.
+	fn encode_batch_audio_signal<S: AsRef<[f32]>>(
+		&self,
+		encoder: Option<VorbisEncoder<Cursor<Vec<u8>>>>,
+		audio_signal: &[S],
+		stream_serial: i32
+	) -> Result<VorbisEncoder<Cursor<Vec<u8>>>, VorbisError> {
+		let mut encoder = match encoder {
+			Some(encoder) => encoder,
+			None => {
+				VorbisEncoder::new(self, Cursor::new(vec![]), stream_serial, Some(Cursor::seek))?
+			}
+		};
+
+		let mut channel_blocks = audio_signal
+			.iter()
+			.map(|channel| channel.as_ref().chunks(BATCH_AUDIO_BLOCK_SIZE))
+			.collect::<Vec<_>>();
+		loop {
+			let audio_block = channel_blocks
+				.iter_mut()
+				.filter_map(Iterator::next)
+				.collect::<Vec<_>>();
+			if audio_block.is_empty() {
+				break;
+			}
+
+			encoder.encode_audio_block(audio_block)?;
+		}
+
+		Ok(encoder)
+	}
+
+	/// Returns the number of channels of the encoded signal, which differs from the number of
+	/// channels of the signal to encode when a channel matrix is set.
+	pub(in crate::encoder) fn encoded_channels(&self) -> Result<NonZeroU8, VorbisError> {
+		match &self.channel_matrix {
+			Some(channel_matrix) if channel_matrix.input_channels() != self.channels => {
+				Err(VorbisError::InvalidChannelMatrix)
+			}
+			Some(channel_matrix) => Ok(channel_matrix.output_channels()),
+			None => Ok(self.channels)
+		}
+	}
+
+	/// Returns the sampling frequency of the encoded signal, which differs from the sampling
+	/// frequency of the signal to encode when resampling.
+	pub(in crate::encoder) fn encoded_sampling_frequency(&self) -> NonZeroU32 {
+		#[cfg(feature = "resampling")]
+		return self
+			.encoding_sampling_frequency
+			.unwrap_or(self.sampling_frequency);
+		#[cfg(not(feature = "resampling"))]
+		self.sampling_frequency
+	}
+
+	/// Returns the Vorbis stream information for the encoded signal, initialized for
+	/// encoding it with the configured bitrate management strategy.
+	pub(in crate::encoder) fn encoding_vorbis_info(&self) -> Result<VorbisInfo, VorbisError> {
+		let channels = self.encoded_channels()?;
+		let sampling_frequency = self.encoded_sampling_frequency();
+
+		let mut vorbis_info = VorbisInfo::new();
+		match self.bitrate_management_strategy {
+			VorbisBitrateManagementStrategy::Vbr { target_bitrate } => {
+				vorbis_info.encode_setup_vbr(sampling_frequency, channels, target_bitrate)
+			}
+			VorbisBitrateManagementStrategy::QualityVbr { target_quality } => {
+				vorbis_info.encode_setup_quality_vbr(sampling_frequency, channels, target_quality)
+			}
+			VorbisBitrateManagementStrategy::Abr { average_bitrate } => {
+				vorbis_info.encode_setup_abr(sampling_frequency, channels, average_bitrate)
+			}
+			VorbisBitrateManagementStrategy::ConstrainedAbr { maximum_bitrate } => vorbis_info
+				.encode_setup_constrained_abr(sampling_frequency, channels, maximum_bitrate)
+		}?;
+
+		// Changing the coupling selects another tuning template, which resets the lowpass
+		// frequency, so it must be set first. Coupling is enabled by default
+		if !self.channel_coupling {
+			vorbis_info.encode_set_channel_coupling(false)?;
+		}
+		if let Some(lowpass_frequency) = self.lowpass_frequency {
+			vorbis_info.encode_set_lowpass_frequency(lowpass_frequency)?;
+		}
+		vorbis_info.encode_setup_init()?;
+
+		Ok(vorbis_info)
+	}
+
+	/// Returns the serial of the Ogg Vorbis stream of the next encoder built with this
+	/// configuration.
+	fn build_stream_serial(&self) -> Result<i32, VorbisError> {
+		match self.stream_serial {
+			Some(stream_serial) => Ok(stream_serial),
+			#[cfg(feature = "stream-serial-rng")]
+			None => Ok(generate_random_stream_serial()?),
+			#[cfg(not(feature = "stream-serial-rng"))]
+			None => Ok(0)
+		}
+	}
+}
+
+/// Generates a random serial for a logical Ogg bitstream.
+#[cfg(feature = "stream-serial-rng")]
+pub(in crate::encoder) fn generate_random_stream_serial() -> Result<i32, getrandom::Error> {
+	// We use a RNG seeded with an unknown seed to minimize unintended metadata leakage:
+	// if we set the seed to a value from a better-known source (e.g., the current timestamp),
+	// an attacker could more practically make educated guesses about the seed value range
+	// and the RNG algorithm used to bruteforce the seed value from the serial. Note that
+	// the security in this scenario comes from how costly and unpredictable the RNG is,
+	// not whether it's cryptographically-secure
+	Ok(getrandom::u32()? as i32)
+}
+
+#[cfg(test)]
+mod test {
+	use std::{
+		io::Cursor,
+		num::{NonZeroU8, NonZeroU32, NonZeroUsize}
+	};
+
+	use super::VorbisEncoderConfig;
+	use crate::{VorbisChannelMatrix, VorbisDecoder, VorbisError, common::test_util::tone};
+
+	#[test]
+	fn encoder_configs_build_encoders() {
+		let mut config =
+			VorbisEncoderConfig::new(NonZeroU32::new(8000).unwrap(), NonZeroU8::new(1).unwrap());
+		config.stream_serial(Some(42)).replay_gain_tags(true);
+		config.validate().unwrap();
+
+		let shared_config = config.clone();
+		assert_eq!(shared_config, config);
+
+		assert!(matches!(
+			config.build(vec![]),
+			Err(VorbisError::UnseekableEncoderSink)
+		));
+		let encoded_ogg = config
+			.build_seekable(Cursor::new(vec![]))
+			.unwrap()
+			.finish()
+			.unwrap()
+			.into_inner();
+		assert_eq!(
+			i32::from_le_bytes(encoded_ogg[14..18].try_into().unwrap()),
+			42
+		);
+
+		config.replay_gain_tags(false);
+		assert_ne!(config, shared_config);
+		config.build(vec![]).unwrap().finish().unwrap();
+
+		config.channel_matrix(Some(
+			VorbisChannelMatrix::from_coefficients(NonZeroU8::new(2).unwrap(), &[[0.5, 0.5]])
+				.unwrap()
+		));
+		assert!(matches!(
+			config.validate(),
+			Err(VorbisError::InvalidChannelMatrix)
+		));
+	}
+
+	#[test]
+	fn encoder_configs_encode_batches() {
+		let mut config =
+			VorbisEncoderConfig::new(NonZeroU32::new(8000).unwrap(), NonZeroU8::new(2).unwrap());
+		config.stream_serial(Some(7));
+
+		let audio_signals = (1..=5)
+			.map(|length| {
+				let channel = tone(length * 1500, 0.5);
+				[channel.clone(), channel]
+			})
+			.chain([[vec![0.0; 10], vec![0.0; 20]]])
+			.collect::<Vec<_>>();
+		let encoded_streams = config.encode_batch(&audio_signals, NonZeroUsize::new(2));
+		assert_eq!(encoded_streams.len(), audio_signals.len());
+
+		for (audio_signal_index, (encoded_stream, audio_signal)) in
+			encoded_streams.iter().zip(&audio_signals[..5]).enumerate()
+		{
+			let encoded_stream = encoded_stream.as_ref().unwrap();
+
+			// Stream serials follow the order of the signals, whatever thread encoded them
+			assert_eq!(
+				i32::from_le_bytes(encoded_stream[14..18].try_into().unwrap()),
+				7 + audio_signal_index as i32
+			);
+
+			let mut decoder = VorbisDecoder::new_from_bytes(encoded_stream).unwrap();
+			while decoder.decode_audio_block().unwrap().is_some() {}
+			assert_eq!(decoder.decoded_samples(), audio_signal[0].len() as u64);
+		}
+		// Channels of different lengths can't be encoded
+		assert!(encoded_streams[5].is_err());
+
+		// Reset encoders write consecutive stream serials
+		let encoder = config.build(vec![]).unwrap();
+		let (first_stream, encoder) = encoder.reset(vec![]).unwrap();
+		let second_stream = encoder.finish().unwrap();
+		assert_eq!(
+			i32::from_le_bytes(first_stream[14..18].try_into().unwrap()),
+			7
+		);
+		assert_eq!(
+			i32::from_le_bytes(second_stream[14..18].try_into().unwrap()),
+			8
+		);
+	}
+}
@@ -1,34 +1,48 @@
 use std::{
 	borrow::Cow,
-	io::Write,
-	mem::MaybeUninit,
-	num::{NonZeroU8, NonZeroU32},
-	ptr, slice
+	io::{self, Seek, SeekFrom, Write},
+	mem::{self, MaybeUninit},
+	num::{NonZeroU8, NonZeroU32, NonZeroU64},
+	ptr, slice,
+	sync::Arc
 };
 
 use aotuv_lancer_vorbis_sys::{
 	vorbis_analysis, vorbis_analysis_blockout, vorbis_analysis_buffer, vorbis_analysis_wrote,
 	vorbis_bitrate_addblock, vorbis_bitrate_flushpacket
 };
+use tinyvec::TinyVec;
 
+#[cfg(feature = "resampling")]
+use crate::common::AudioResampler;
+#[cfg(feature = "stream-serial-rng")]
+use crate::encoder::encoder_config::generate_random_stream_serial;
 use crate::{
-	common::{OggPacket, OggStream, VorbisComments, VorbisError, VorbisInfo},
-	encoder::{VorbisBitrateManagementStrategy, encoder_util::VorbisEncodingState}
+	common::{
+		ChannelMixer, OggPacket, OggStream, RawVorbisComments, VorbisChannelLayout,
+		VorbisChannelMatrix, VorbisChannelOrder, VorbisComments, VorbisError, VorbisInfo,
+		VorbisLoudnessMeter, VorbisReplayGain
+	},
+	encoder::{
+		VorbisBitrateManagementStrategy, VorbisEncoderConfig, VorbisEncoderPreset,
+		encoder_skeleton::SkeletonWriter,
+		encoder_util::{
+			AudioPacketState, PageBoundaryPositions, ReplayGainTagWriter, VorbisEncodingState,
+			VorbisPageBoundary
+		}
+	}
 };
 
 /// Builds a [`VorbisEncoder`] with configurable Vorbis encoding and
 /// Ogg stream encapsulation options.
 pub struct VorbisEncoderBuilder<W: Write> {
-	sampling_frequency: NonZeroU32,
-	channels: NonZeroU8,
+	config: VorbisEncoderConfig,
 	sink: Option<W>,
 	stream_serial: i32,
 	stream_serial_is_fresh: bool,
 	#[cfg(feature = "stream-serial-rng")]
 	randomize_stream_serials: bool,
-	bitrate_management_strategy: VorbisBitrateManagementStrategy,
-	comments: VorbisComments,
-	minimum_page_data_size: Option<u16>
+	seek: Option<fn(&mut W, SeekFrom) -> io::Result<u64>>
 }
 
 impl<W: Write> VorbisEncoderBuilder<W> {
@@ -48,7 +62,7 @@ impl<W: Write> VorbisEncoderBuilder<W> {
 			sampling_frequency,
 			channels,
 			sink,
-			Self::generate_random_stream_serial()?,
+			generate_random_stream_serial()?,
 			true
 		))
 	}
@@ -82,28 +96,72 @@ impl<W: Write> VorbisEncoderBuilder<W> {
 		#[cfg(feature = "stream-serial-rng")] randomize_stream_serials: bool
 	) -> Self {
 		Self {
-			sampling_frequency,
-			channels,
+			config: VorbisEncoderConfig::new(sampling_frequency, channels),
 			sink: Some(sink),
 			stream_serial,
 			stream_serial_is_fresh: true,
 			#[cfg(feature = "stream-serial-rng")]
 			randomize_stream_serials,
-			bitrate_management_strategy: VorbisBitrateManagementStrategy::default(),
-			comments: VorbisComments::new(),
-			minimum_page_data_size: None
+			seek: None
 		}
 	}
 
 	/// Sets the sampling frequency of the signal to encode, in Hertz (Hz).
 	pub fn sampling_frequency(&mut self, sampling_frequency: NonZeroU32) -> &mut Self {
-		self.sampling_frequency = sampling_frequency;
+		self.config.sampling_frequency = sampling_frequency;
 		self
 	}
 
 	/// Sets the number of channels of the signal to encode.
 	pub fn channels(&mut self, channels: NonZeroU8) -> &mut Self {
-		self.channels = channels;
+		self.config.channels = channels;
+		self
+	}
+
+	/// Sets the sampling frequency to resample the signal to before encoding it, in Hertz
+	/// (Hz), which will be the sampling frequency of the encoded stream. This is useful to
+	/// encode signals with sampling frequencies `libvorbisenc` has no tuned modes for, which
+	/// it rejects with a [`NotImplemented`](crate::VorbisLibraryErrorKind::NotImplemented)
+	/// error, by choosing a supported one, such as 44.1 or 48 kHz.
+	///
+	/// The configured [priming](Self::priming_samples) and [total](Self::total_samples)
+	/// sample counts are interpreted at the sampling frequency of the signal to encode, and
+	/// converted accordingly. By default, this is `None`, which disables resampling.
+	#[cfg(feature = "resampling")]
+	pub fn encoding_sampling_frequency(
+		&mut self,
+		encoding_sampling_frequency: Option<NonZeroU32>
+	) -> &mut Self {
+		self.config.encoding_sampling_frequency = encoding_sampling_frequency;
+		self
+	}
+
+	/// Sets the number of channels of the signal to encode to the number of channels of
+	/// the specified layout. Layouts with up to eight channels are defined by the Vorbis I
+	/// specification, so this is equivalent to calling [`channels`](Self::channels).
+	pub fn channel_layout(&mut self, channel_layout: VorbisChannelLayout) -> &mut Self {
+		self.channels(channel_layout.channel_count())
+	}
+
+	/// Sets the order of the channels in the audio blocks that will be submitted to the
+	/// encoder, which are remapped to the Vorbis channel order as needed. This is useful to
+	/// encode audio from sources that use other channel orders, such as WAV files.
+	///
+	/// By default, audio blocks are expected to be in the Vorbis channel order.
+	pub fn channel_order(&mut self, channel_order: VorbisChannelOrder) -> &mut Self {
+		self.config.channel_order = channel_order;
+		self
+	}
+
+	/// Sets the matrix to convert the channels of the signal with before encoding it, which is
+	/// useful to downmix multichannel signals to stereo or mono, or to upmix mono signals to
+	/// stereo. The input layout of the matrix must have as many [channels](Self::channels) as
+	/// the signal to encode, and its output layout will be the layout of the encoded stream.
+	/// The [channel order](Self::channel_order) applies to its input channels.
+	///
+	/// By default, this is `None`, which disables channel conversion.
+	pub fn channel_matrix(&mut self, channel_matrix: Option<VorbisChannelMatrix>) -> &mut Self {
+		self.config.channel_matrix = channel_matrix;
 		self
 	}
 
@@ -153,7 +211,39 @@ impl<W: Write> VorbisEncoderBuilder<W> {
 		&mut self,
 		bitrate_management_strategy: VorbisBitrateManagementStrategy
 	) -> &mut Self {
-		self.bitrate_management_strategy = bitrate_management_strategy;
+		self.config.bitrate_management_strategy = bitrate_management_strategy;
+		self
+	}
+
+	/// Sets the frequency above which the signal is discarded when encoding it, in Hertz
+	/// (Hz). Lower frequencies save bits for the rest of the spectrum, which is worthwhile
+	/// for band-limited content such as speech. `libvorbisenc` clamps it to the
+	/// [2, 99] kHz range.
+	///
+	/// By default, this is `None`, which uses the lowpass frequency `libvorbisenc` tuned
+	/// for the selected encoding mode.
+	pub fn lowpass_frequency(&mut self, lowpass_frequency: Option<NonZeroU32>) -> &mut Self {
+		self.config.lowpass_frequency = lowpass_frequency;
+		self
+	}
+
+	/// Enables or disables channel coupling, which encodes multichannel signals more
+	/// efficiently by exploiting the redundancy between channels. Disabling it keeps
+	/// channels fully independent, at a notable cost in bitrate, which is only worthwhile
+	/// for signals whose channels are unrelated, such as dual-language audio. Not every
+	/// encoding mode supports disabling it.
+	///
+	/// By default, channel coupling is enabled.
+	pub fn channel_coupling(&mut self, channel_coupling: bool) -> &mut Self {
+		self.config.channel_coupling = channel_coupling;
+		self
+	}
+
+	/// Applies the bitrate management strategy, lowpass frequency, channel coupling and
+	/// paging options of the specified preset, which is tuned for a common type of
+	/// content. Options set after applying the preset override it.
+	pub fn preset(&mut self, preset: VorbisEncoderPreset) -> &mut Self {
+		self.config.preset(preset);
 		self
 	}
 
@@ -178,12 +268,28 @@ impl<W: Write> VorbisEncoderBuilder<W> {
 		tags: impl IntoIterator<Item = (T, V)>
 	) -> Result<&mut Self, VorbisError> {
 		for (tag, value) in tags {
-			self.comments.add_tag(tag, value)?;
+			self.config.comments.add(tag, value)?;
 		}
 
 		Ok(self)
 	}
 
+	/// Sets the Vorbis comments that will be written to the comments header, replacing
+	/// any comments added before. This is useful to carry over the comments of a
+	/// [`VorbisDecoder`](crate::VorbisDecoder) when transcoding.
+	///
+	/// The vendor string of the comments is ignored, as `libvorbis` always writes its own.
+	pub fn comments(&mut self, comments: VorbisComments) -> &mut Self {
+		self.config.comments = comments;
+		self
+	}
+
+	/// Returns a mutable reference to the Vorbis comments that will be written to the
+	/// comments header, allowing to edit them in place.
+	pub fn comments_mut(&mut self) -> &mut VorbisComments {
+		&mut self.config.comments
+	}
+
 	/// Specifies the minimum size of Vorbis stream data to put into each Ogg page, except
 	/// for some header pages, which have to be cut short to conform to the Ogg Vorbis
 	/// specification.
@@ -192,7 +298,80 @@ impl<W: Write> VorbisEncoderBuilder<W> {
 	/// seeking and packet loss concealment. By default, it is set to `None`, which lets
 	/// the encoder decide.
 	pub fn minimum_page_data_size(&mut self, minimum_page_data_size: Option<u16>) -> &mut Self {
-		self.minimum_page_data_size = minimum_page_data_size;
+		self.config.minimum_page_data_size = minimum_page_data_size;
+		self
+	}
+
+	/// Sets the maximum duration of the audio data in each Ogg page, in milliseconds. Pages
+	/// are ended as soon as they reach this duration, and before adding a packet would make
+	/// them exceed it, regardless of the [minimum page data size](Self::minimum_page_data_size).
+	///
+	/// Pages can only be written once their data was encoded, so this bounds the delay
+	/// between encoding some audio and having it available in the sink, which is useful for
+	/// low-latency live streaming. Shorter durations increase the Ogg encapsulation overhead.
+	/// By default, it is set to `None`, which does not limit page durations.
+	pub fn maximum_page_duration(
+		&mut self,
+		maximum_page_duration: Option<NonZeroU32>
+	) -> &mut Self {
+		self.config.maximum_page_duration = maximum_page_duration;
+		self
+	}
+
+	/// Sets the input sample positions the encoder will start new Ogg pages at, counted
+	/// since the first sample submitted to it, in any order. This is useful for placing
+	/// chapter marks or cue points at page boundaries, which are addressable by byte offset.
+	///
+	/// Vorbis audio packets decode to several samples, so each page boundary is placed
+	/// right before the first packet that decodes to samples after the requested position.
+	/// The placed boundaries are reported by [`VorbisEncoder::drain_page_boundaries`] and
+	/// [`VorbisEncoder::finish_with_page_boundaries`]. Positions after the end of the
+	/// stream, or so close to its end that no packet follows them, are not reported. By
+	/// default, no positions are set.
+	pub fn page_boundaries(
+		&mut self,
+		sample_positions: impl IntoIterator<Item = u64>
+	) -> &mut Self {
+		self.config.page_boundaries = sample_positions.into_iter().collect();
+		self
+	}
+
+	/// Sets the interval between the input sample positions the encoder will start new Ogg
+	/// pages at, in addition to the ones set with [`page_boundaries`](Self::page_boundaries).
+	/// Boundaries are placed and reported like those, at every multiple of the interval,
+	/// starting with zero. This is useful for splitting the encoded stream into segments of
+	/// a fixed duration. By default, it is set to `None`, which does not place boundaries
+	/// at regular intervals.
+	pub fn page_boundary_interval(
+		&mut self,
+		page_boundary_interval: Option<NonZeroU64>
+	) -> &mut Self {
+		self.config.page_boundary_interval = page_boundary_interval;
+		self
+	}
+
+	/// Sets the number of samples at the beginning of the encoded signal that decoders should
+	/// discard, such as the encoder delay of a lossy source the signal was decoded from. This
+	/// is useful for gapless playback.
+	///
+	/// Samples are discarded by dropping the audio packets that only decode to them, and then
+	/// signalling decoders to trim the remaining ones through the granule position of the
+	/// first audio page, as defined by the Vorbis I specification. By default, no samples are
+	/// discarded.
+	pub fn priming_samples(&mut self, priming_samples: u64) -> &mut Self {
+		self.config.priming_samples = priming_samples;
+		self
+	}
+
+	/// Sets the exact number of samples the encoded stream will decode to, not counting any
+	/// [priming samples](Self::priming_samples). Any samples encoded after that count are
+	/// discarded, and the stream ends as soon as possible. If fewer samples are encoded, the
+	/// stream decodes to all of them.
+	///
+	/// By default, this is `None`, which makes the stream decode to every encoded sample.
+	/// Setting it is useful for gapless playback when the encoded signal is padded.
+	pub fn total_samples(&mut self, total_samples: Option<u64>) -> &mut Self {
+		self.config.total_samples = total_samples;
 		self
 	}
 
@@ -209,68 +388,99 @@ impl<W: Write> VorbisEncoderBuilder<W> {
 	/// This behavior can be controlled by calling the [`stream_serial`](Self::stream_serial)
 	/// method with a custom serial.
 	pub fn build(&mut self) -> Result<VorbisEncoder<W>, VorbisError> {
-		let mut sink = self
+		// Reject invalid channel matrices before consuming the sink
+		self.config.encoded_channels()?;
+
+		let sink = self
 			.sink
 			.take()
 			.ok_or(VorbisError::ConsumedEncoderBuilderSink)?;
 
 		self.renew_stream_serial();
 
-		// Tear up the Ogg stream
-		let mut ogg_stream = OggStream::new(self.stream_serial)?;
+		let encoder = VorbisEncoder::new(&self.config, sink, self.stream_serial, self.seek)?;
 
-		// Tear up the Vorbis encoder
-		let mut vorbis_info = VorbisInfo::new();
-		match self.bitrate_management_strategy {
-			VorbisBitrateManagementStrategy::Vbr { target_bitrate } => {
-				vorbis_info.encode_init_vbr(self.sampling_frequency, self.channels, target_bitrate)
-			}
-			VorbisBitrateManagementStrategy::QualityVbr { target_quality } => vorbis_info
-				.encode_init_quality_vbr(self.sampling_frequency, self.channels, target_quality),
-			VorbisBitrateManagementStrategy::Abr { average_bitrate } => {
-				vorbis_info.encode_init_abr(self.sampling_frequency, self.channels, average_bitrate)
-			}
-			VorbisBitrateManagementStrategy::ConstrainedAbr { maximum_bitrate } => vorbis_info
-				.encode_init_constrained_abr(
-					self.sampling_frequency,
-					self.channels,
-					maximum_bitrate
-				)
-		}?;
+		// The Ogg stream serial we've just used is no longer fresh: it must be renewed
+		// for the next encoder we build
+		self.stream_serial_is_fresh = false;
 
-		let mut vorbis_encoding_state = VorbisEncodingState::new(vorbis_info)?;
+		Ok(encoder)
+	}
 
-		// Get the Vorbis header packets and submit them for encapsulation
-		for mut header_packet in vorbis_encoding_state.get_header_packets(&mut self.comments)? {
-			header_packet.submit(&mut ogg_stream)?;
+	/// Returns the configuration of the encoders this builder builds, which can build
+	/// encoders for other sinks. The stream serials of this builder are not part of it.
+	pub fn config(&self) -> &VorbisEncoderConfig {
+		&self.config
+	}
+
+	/// Encodes an audio signal normalized to the specified integrated loudness, in LUFS,
+	/// as measured per [EBU R 128](VorbisLoudnessMeter), returning the sink back to the
+	/// caller. For example, a target of -16 LUFS is commonly used for podcasts, while
+	/// broadcast programmes target -23 LUFS.
+	///
+	/// This is done in two passes: `signal` is called once to get the audio blocks to
+	/// measure, and again to get the same audio blocks to encode, after applying the gain
+	/// that makes the encoded signal reach the target loudness. Audio blocks are expected
+	/// to be in the same format as for [`VorbisEncoder::encode_audio_block`], and any
+	/// configured [channel matrix](Self::channel_matrix) is applied before measuring.
+	/// Signals that are too quiet to measure are encoded without gain changes.
+	///
+	/// Raising the loudness of a signal may make it exceed full scale, so callers that want
+	/// to avoid clipping should check its [true peak](VorbisLoudnessMeter::true_peak) in
+	/// advance. Like [`build`](Self::build), this method consumes the sink of this builder.
+	pub fn encode_normalized<F, I, B, S>(
+		&mut self,
+		target_loudness: f64,
+		mut signal: F
+	) -> Result<W, VorbisError>
+	where
+		F: FnMut() -> Result<I, VorbisError>,
+		I: IntoIterator<Item = Result<B, VorbisError>>,
+		B: AsRef<[S]>,
+		S: AsRef<[f32]>
+	{
+		let channel_matrix = self
+			.config
+			.channel_matrix
+			.clone()
+			.unwrap_or_else(|| VorbisChannelMatrix::identity(self.config.channels));
+		if channel_matrix.input_channels() != self.config.channels {
+			return Err(VorbisError::InvalidChannelMatrix);
 		}
+		let input_layout = channel_matrix.input_layout();
+		let mut channel_mixer = ChannelMixer::new(channel_matrix);
 
-		// Force the header packets we submitted to be written, and the first audio packet to begin
-		// on its own page, as mandated by the Vorbis I spec
-		ogg_stream.flush(&mut sink)?;
+		// First pass: measure the loudness of the signal as it would be encoded
+		let mut loudness_meter = VorbisLoudnessMeter::new(
+			self.config.sampling_frequency,
+			channel_mixer.matrix().output_layout(),
+			VorbisChannelOrder::Vorbis
+		);
+		for audio_block in signal()? {
+			let audio_block = audio_block?;
+			let vorbis_audio_block = vorbis_ordered_audio_block(
+				audio_block.as_ref(),
+				input_layout,
+				self.config.channel_order
+			)?;
+			loudness_meter.process(channel_mixer.process(&vorbis_audio_block))?;
+		}
 
-		// The Ogg stream serial we've just used is no longer fresh: it must be renewed
-		// for the next encoder we build
-		self.stream_serial_is_fresh = false;
+		let gain = loudness_meter
+			.integrated_loudness()
+			.map_or(0.0, |loudness| target_loudness - loudness);
 
-		Ok(VorbisEncoder {
-			ogg_stream,
-			vorbis_encoding_state,
-			sink: Some(sink),
-			minimum_page_data_size: self.minimum_page_data_size
-		})
-	}
+		// Second pass: encode the signal with that gain applied by the channel mixer
+		let mut channel_matrix = channel_mixer.matrix().clone();
+		channel_matrix.scale(10.0_f64.powf(gain / 20.0) as f32);
 
-	/// Generates a random serial for a logical Ogg bitstream.
-	#[cfg(feature = "stream-serial-rng")]
-	fn generate_random_stream_serial() -> Result<i32, getrandom::Error> {
-		// We use a RNG seeded with an unknown seed to minimize unintended metadata leakage:
-		// if we set the seed to a value from a better-known source (e.g., the current timestamp),
-		// an attacker could more practically make educated guesses about the seed value range
-		// and the RNG algorithm used to bruteforce the seed value from the serial. Note that
-		// the security in this scenario comes from how costly and unpredictable the RNG is,
-		// not whether it's cryptographically-secure
-		Ok(getrandom::u32()? as i32)
+		let mut encoder = self.build()?;
+		encoder.channel_mixer = Some(ChannelMixer::new(channel_matrix));
+		for audio_block in signal()? {
+			encoder.encode_audio_block(audio_block?)?;
+		}
+
+		encoder.finish()
 	}
 
 	/// Replaces the current stream serial with a new one if the current stream serial
@@ -282,7 +492,7 @@ impl<W: Write> VorbisEncoderBuilder<W> {
 
 			#[cfg(feature = "stream-serial-rng")]
 			if self.randomize_stream_serials {
-				if let Ok(random_stream_serial) = Self::generate_random_stream_serial() {
+				if let Ok(random_stream_serial) = generate_random_stream_serial() {
 					self.stream_serial = random_stream_serial;
 					return;
 				}
@@ -296,23 +506,257 @@ impl<W: Write> VorbisEncoderBuilder<W> {
 	}
 }
 
+impl<W: Write + Seek> VorbisEncoderBuilder<W> {
+	/// Enables or disables measuring the loudness of the encoded signal to write
+	/// [ReplayGain](crate::VorbisReplayGain) track gain and peak tags, which players can use
+	/// to play it at a consistent loudness. The gain is computed as defined by ReplayGain
+	/// 2.0, targeting a loudness of -18 LUFS.
+	///
+	/// The comment header is written before any audio is encoded, so it gets placeholder
+	/// tags, replacing any existing `REPLAYGAIN_TRACK_GAIN` and `REPLAYGAIN_TRACK_PEAK`
+	/// comments, which the encoder rewrites in place with the final values when it finishes.
+	/// This is why this option requires a seekable sink. By default, this is disabled.
+	pub fn replay_gain_tags(&mut self, replay_gain_tags: bool) -> &mut Self {
+		self.config.replay_gain_tags = replay_gain_tags;
+		self.seek = Some(W::seek);
+		self
+	}
+
+	/// Adds an [Ogg Skeleton 4.0](https://wiki.xiph.org/Ogg_Skeleton_4) logical stream to
+	/// the encoded physical stream, which describes the Vorbis stream and indexes up to the
+	/// specified number of keypoints, i.e., byte offsets of Ogg pages and the time of the
+	/// audio after them. Players can use the index to seek with few requests, which is
+	/// especially useful for streams served over HTTP.
+	///
+	/// The keypoints are evenly spread over the duration of the stream. About eight bytes
+	/// are reserved for each keypoint in the index, which is written before any audio is
+	/// encoded and rewritten in place when the encoder finishes, so this option requires a
+	/// seekable sink. Keypoints are thinned out if they don't fit in the reserved space,
+	/// and byte offsets reported by the encoder account for the Skeleton pages. By default,
+	/// this is `None`, which does not add a Skeleton stream.
+	pub fn skeleton_index(&mut self, maximum_keypoints: Option<NonZeroU32>) -> &mut Self {
+		self.config.skeleton_index = maximum_keypoints;
+		self.seek = Some(W::seek);
+		self
+	}
+}
+
 /// An encoder that transforms blocks of planar, single-precision float audio
 /// samples to a perceptually-encoded Ogg Vorbis stream. Instances of this
 /// encoder can be obtained from a [`VorbisEncoderBuilder`].
+///
+/// Encoders are [`Send`] and [`Sync`] as long as their sink is, so they can be moved
+/// to other threads, such as the workers of a thread pool.
 pub struct VorbisEncoder<W: Write> {
 	ogg_stream: OggStream,
 	vorbis_encoding_state: VorbisEncodingState,
 	sink: Option<W>,
-	minimum_page_data_size: Option<u16>
+	minimum_page_data_size: Option<u16>,
+	maximum_page_samples: Option<u64>,
+	page_boundary_positions: PageBoundaryPositions,
+	page_boundaries: Vec<VorbisPageBoundary>,
+	priming_samples: u64,
+	total_samples: Option<u64>,
+	channel_order: VorbisChannelOrder,
+	channel_mixer: Option<ChannelMixer>,
+	replay_gain_tag_writer: Option<ReplayGainTagWriter<W>>,
+	skeleton_writer: Option<SkeletonWriter<W>>,
+	#[cfg(feature = "resampling")]
+	resampler: Option<AudioResampler>,
+	audio_packet_state: AudioPacketState,
+	config: VorbisEncoderConfig,
+	stream_serial: i32,
+	seek: Option<fn(&mut W, SeekFrom) -> io::Result<u64>>
 }
 
 impl<W: Write> VorbisEncoder<W> {
+	/// Creates a new encoder with the specified configuration and stream serial, writing
+	/// header data to the specified sink. Options that require a seekable sink use the
+	/// specified `Seek::seek` method of the sink, if any.
+	pub(in crate::encoder) fn new(
+		config: &VorbisEncoderConfig,
+		sink: W,
+		stream_serial: i32,
+		seek: Option<fn(&mut W, SeekFrom) -> io::Result<u64>>
+	) -> Result<Self, VorbisError> {
+		// Check that the sink is seekable enough before initializing libvorbisenc
+		if seek.is_none() && (config.replay_gain_tags || config.skeleton_index.is_some()) {
+			return Err(VorbisError::UnseekableEncoderSink);
+		}
+
+		// SAFETY: the Vorbis stream information is not shared with anything else
+		unsafe {
+			Self::with_vorbis_info(
+				config,
+				sink,
+				stream_serial,
+				seek,
+				Arc::new(config.encoding_vorbis_info()?)
+			)
+		}
+	}
+
+	/// Like [`new`](Self::new), but encodes with the specified Vorbis stream information,
+	/// which must have been initialized for encoding with the specified configuration.
+	///
+	/// # Safety
+	///
+	/// See [`VorbisEncodingState::new`].
+	unsafe fn with_vorbis_info(
+		config: &VorbisEncoderConfig,
+		mut sink: W,
+		stream_serial: i32,
+		seek: Option<fn(&mut W, SeekFrom) -> io::Result<u64>>,
+		vorbis_info: Arc<VorbisInfo>
+	) -> Result<Self, VorbisError> {
+		let replay_gain_seek = seek.filter(|_| config.replay_gain_tags);
+		let skeleton_seek = seek.filter(|_| config.skeleton_index.is_some());
+
+		let channels = config.encoded_channels()?;
+		let sampling_frequency = config.encoded_sampling_frequency();
+
+		// Tear up the Ogg stream
+		let mut ogg_stream = OggStream::new(stream_serial)?;
+
+		#[cfg(feature = "resampling")]
+		let resampler = (sampling_frequency != config.sampling_frequency).then(|| {
+			AudioResampler::new(
+				config.sampling_frequency,
+				sampling_frequency,
+				channels.get().into()
+			)
+		});
+		// Sample counts are converted to the encoding sampling frequency
+		#[cfg(feature = "resampling")]
+		let resampled_count = |count: u64| {
+			resampler.as_ref().map_or(count, |resampler| {
+				(count as f64 * resampler.ratio()).round() as u64
+			})
+		};
+		#[cfg(not(feature = "resampling"))]
+		let resampled_count = |count: u64| count;
+
+		// Tear up the Vorbis encoder
+		// SAFETY: upheld by our caller
+		let mut vorbis_encoding_state = unsafe { VorbisEncodingState::new(vorbis_info)? };
+
+		// Reserve space for the ReplayGain tags in the comment header, which are rewritten
+		// when the encoder finishes
+		let mut comments = Cow::Borrowed(&config.comments);
+		if replay_gain_seek.is_some() {
+			VorbisReplayGain {
+				gain: 0.0,
+				peak: Some(0.0)
+			}
+			.set_track_tags(comments.to_mut())?;
+		}
+
+		// The BOS pages of every logical stream come first, and the Skeleton BOS page must
+		// be the first of them
+		let mut skeleton_writer = config
+			.skeleton_index
+			.zip(skeleton_seek)
+			.map(|(maximum_keypoints, seek)| {
+				SkeletonWriter::new(
+					&mut sink,
+					seek,
+					stream_serial,
+					sampling_frequency,
+					maximum_keypoints
+				)
+			})
+			.transpose()?;
+
+		// Get the Vorbis header packets and submit them for encapsulation. The
+		// identification header is alone on the BOS page
+		let mut raw_comments = RawVorbisComments::from_comments(&comments)?;
+		let mut header_packets = vorbis_encoding_state.get_header_packets(&mut raw_comments)?;
+		let header_packets_data = header_packets
+			.each_ref()
+			.map(|header_packet| header_packet.data().to_vec());
+		let [identification_header_packet, other_header_packets @ ..] = &mut header_packets;
+		identification_header_packet.submit(&mut ogg_stream)?;
+		ogg_stream.flush(&mut sink)?;
+
+		if let Some(skeleton_writer) = &mut skeleton_writer {
+			skeleton_writer.write_header_pages(&mut sink, ogg_stream.written_bytes())?;
+		}
+
+		let header_position = replay_gain_seek
+			.map(|seek| seek(&mut sink, SeekFrom::Current(0)))
+			.transpose()?;
+		for header_packet in other_header_packets {
+			header_packet.submit(&mut ogg_stream)?;
+		}
+
+		// Force the header packets we submitted to be written, and the first audio packet to begin
+		// on its own page, as mandated by the Vorbis I spec
+		ogg_stream.flush(&mut sink)?;
+
+		if let Some(skeleton_writer) = &mut skeleton_writer {
+			skeleton_writer.write_eos_page(&mut sink, ogg_stream.written_bytes())?;
+		}
+
+		let mut encoder = Self {
+			ogg_stream,
+			vorbis_encoding_state,
+			sink: Some(sink),
+			minimum_page_data_size: config.minimum_page_data_size,
+			maximum_page_samples: config.maximum_page_duration.map(|maximum_page_duration| {
+				(u64::from(maximum_page_duration.get()) * u64::from(sampling_frequency.get())
+					/ 1000)
+					.max(1)
+			}),
+			page_boundary_positions: PageBoundaryPositions::new(
+				config.page_boundaries.clone(),
+				config.page_boundary_interval
+			),
+			page_boundaries: Vec::new(),
+			priming_samples: resampled_count(config.priming_samples),
+			total_samples: config.total_samples.map(resampled_count),
+			channel_order: config.channel_order,
+			channel_mixer: config.channel_matrix.clone().map(ChannelMixer::new),
+			replay_gain_tag_writer: replay_gain_seek.zip(header_position).map(
+				|(seek, header_position)| ReplayGainTagWriter {
+					loudness_meter: VorbisLoudnessMeter::new(
+						config.sampling_frequency,
+						VorbisChannelLayout::from_channel_count(channels),
+						VorbisChannelOrder::Vorbis
+					),
+					comments: comments.into_owned(),
+					header_packets: header_packets_data,
+					header_position,
+					stream_serial,
+					seek
+				}
+			),
+			skeleton_writer,
+			#[cfg(feature = "resampling")]
+			resampler,
+			audio_packet_state: AudioPacketState::Priming {
+				priming_packet: None
+			},
+			config: config.clone(),
+			stream_serial,
+			seek
+		};
+
+		// Page boundaries at or before the first sample to decode are placed right after
+		// the header pages
+		encoder.end_pages_at_boundaries(1)?;
+
+		Ok(encoder)
+	}
+
 	/// Submits the specified audio block for encoding by Vorbis. Encoded data will be written
 	/// to the configured sink automatically as it becomes available.
 	///
 	/// The audio block is expected to be in planar format (i.e., one vector of samples per
-	/// channel). The order of channels is defined by the Vorbis I specification. Conventionally,
-	/// samples are in the [0, 1] interval, but Vorbis does not enforce this.
+	/// channel). The order of channels is defined by the Vorbis I specification, unless
+	/// another [channel order](VorbisEncoderBuilder::channel_order) was configured. When a
+	/// [channel matrix](VorbisEncoderBuilder::channel_matrix) was configured, the block must
+	/// have its input channels.
+	/// Conventionally, samples are in the [0, 1] interval, but Vorbis does not enforce this.
 	///
 	/// ## Block size and performance
 	///
@@ -342,44 +786,76 @@ impl<W: Write> VorbisEncoder<W> {
 		&mut self,
 		audio_block: B
 	) -> Result<(), VorbisError> {
-		let audio_block = audio_block.as_ref();
-		let audio_channels = self.vorbis_encoding_state.vorbis_info.channels().get() as usize;
+		let vorbis_audio_block = vorbis_ordered_audio_block(
+			audio_block.as_ref(),
+			self.input_channel_layout(),
+			self.channel_order
+		)?;
 
-		if audio_channels != audio_block.len() {
-			return Err(VorbisError::InvalidAudioBlockChannelCount {
-				actual: audio_channels,
-				expected: audio_block.len()
-			});
+		if let Some(mut channel_mixer) = self.channel_mixer.take() {
+			let result = self.encode_vorbis_audio_block(channel_mixer.process(&vorbis_audio_block));
+			self.channel_mixer = Some(channel_mixer);
+			return result;
+		}
+
+		self.encode_vorbis_audio_block(&vorbis_audio_block)
+	}
+
+	/// Encodes the specified planar audio block, whose channels are in the Vorbis channel
+	/// order and match the channels of the encoded stream, resampling it if necessary.
+	fn encode_vorbis_audio_block<S: AsRef<[f32]>>(
+		&mut self,
+		audio_block: &[S]
+	) -> Result<(), VorbisError> {
+		if let Some(replay_gain_tag_writer) = &mut self.replay_gain_tag_writer {
+			replay_gain_tag_writer.loudness_meter.process(audio_block)?;
+		}
+
+		#[cfg(feature = "resampling")]
+		if let Some(resampler) = &mut self.resampler {
+			let resampled_audio_block = resampler.process(audio_block)?;
+			Self::submit_audio_block(&self.vorbis_encoding_state, resampled_audio_block)?;
+			return self.write_pending_blocks();
+		}
+
+		Self::submit_audio_block(&self.vorbis_encoding_state, audio_block)?;
+		self.write_pending_blocks()
+	}
+
+	/// Copies the specified planar audio block, whose channels are in the Vorbis channel
+	/// order and have the same number of samples, to the buffers of the low-level Vorbis
+	/// encoder. Empty blocks are ignored, as submitting them would signal the end of the
+	/// stream.
+	fn submit_audio_block<S: AsRef<[f32]>>(
+		vorbis_encoding_state: &VorbisEncodingState,
+		audio_block: &[S]
+	) -> Result<(), VorbisError> {
+		let sample_count = audio_block
+			.first()
+			.map_or(0, |samples| samples.as_ref().len());
+		if sample_count == 0 {
+			return Ok(());
 		}
 
-		let sample_count = audio_block[0].as_ref().len();
 		let encoder_buffer = unsafe {
 			slice::from_raw_parts_mut(
 				vorbis_analysis_buffer(
-					self.vorbis_encoding_state.vorbis_dsp_state,
+					vorbis_encoding_state.vorbis_dsp_state,
 					sample_count.try_into()?
 				),
-				audio_channels
+				audio_block.len()
 			)
 		};
 
 		for (channel_samples, channel_encode_buffer) in
 			audio_block.iter().zip(encoder_buffer.iter_mut())
 		{
-			let channel_samples = channel_samples.as_ref();
-
-			if channel_samples.len() != sample_count {
-				return Err(VorbisError::InvalidAudioBlockSampleCount {
-					actual: channel_samples.len(),
-					expected: sample_count
-				});
-			}
-
 			// SAFETY: both the source and destination locations are valid.
 			// They do not overlap each other because they belong to different
 			// memory allocations
 			unsafe {
 				channel_samples
+					.as_ref()
 					.as_ptr()
 					.copy_to_nonoverlapping(*channel_encode_buffer, sample_count);
 			}
@@ -388,20 +864,54 @@ impl<W: Write> VorbisEncoder<W> {
 		// SAFETY: we assume vorbis_analysis_wrote follows its documented contract
 		unsafe {
 			libvorbis_return_value_to_result!(vorbis_analysis_wrote(
-				self.vorbis_encoding_state.vorbis_dsp_state,
+				vorbis_encoding_state.vorbis_dsp_state,
 				sample_count as i32
 			))?;
 		}
 
-		self.write_pending_blocks()
+		Ok(())
+	}
+
+	/// Signals the end of the audio signal to the low-level Vorbis encoder, after submitting
+	/// any samples still buffered by the resampler, so that the stream can be finished.
+	fn finish_audio_signal(&mut self) -> Result<(), VorbisError> {
+		#[cfg(feature = "resampling")]
+		if let Some(resampler) = &mut self.resampler {
+			let resampled_audio_block = resampler.finish()?;
+			Self::submit_audio_block(&self.vorbis_encoding_state, resampled_audio_block)?;
+			self.write_pending_blocks()?;
+		}
+
+		// SAFETY: we assume that vorbis_analysis_wrote follows its documented contract
+		unsafe {
+			libvorbis_return_value_to_result!(vorbis_analysis_wrote(
+				self.vorbis_encoding_state.vorbis_dsp_state,
+				0
+			))?;
+		}
+
+		Ok(())
+	}
+
+	/// Returns the layout of the channels of the encoded stream, which is the output layout
+	/// of the [channel matrix](VorbisEncoderBuilder::channel_matrix), if any.
+	pub fn channel_layout(&self) -> VorbisChannelLayout {
+		VorbisChannelLayout::from_channel_count(self.vorbis_encoding_state.vorbis_info.channels())
+	}
+
+	/// Returns the layout of the channels of the audio blocks submitted to this encoder,
+	/// before any channel conversion.
+	fn input_channel_layout(&self) -> VorbisChannelLayout {
+		match &self.channel_mixer {
+			Some(channel_mixer) => channel_mixer.matrix().input_layout(),
+			None => self.channel_layout()
+		}
 	}
 
 	/// Asks the low-level Vorbis encoder for pending packets, and writes them out
 	/// to Ogg pages as they become available. This method expects that `self.sink`
 	/// is `Some` (i.e., `finish` was not run).
 	fn write_pending_blocks(&mut self) -> Result<(), VorbisError> {
-		let sink = self.sink.as_mut().unwrap();
-
 		// SAFETY: we assume the functions inside this unsafe block follow their
 		// documented contract
 		unsafe {
@@ -424,10 +934,7 @@ impl<W: Write> VorbisEncoder<W> {
 					ogg_packet.as_mut_ptr()
 				))? == 1
 				{
-					OggPacket::new(ogg_packet.assume_init()).submit(&mut self.ogg_stream)?;
-
-					self.ogg_stream
-						.write_pending_pages(&mut *sink, self.minimum_page_data_size)?;
+					self.write_audio_packet(&OggPacket::new(ogg_packet.assume_init()))?;
 				}
 			}
 		}
@@ -435,6 +942,219 @@ impl<W: Write> VorbisEncoder<W> {
 		Ok(())
 	}
 
+	/// Writes an audio packet generated by libvorbis to the Ogg stream, dropping packets and
+	/// rewriting granule positions as needed to make the stream decode to the configured
+	/// priming and total samples. This method expects that `self.sink` is `Some`.
+	fn write_audio_packet(&mut self, packet: &OggPacket<'_>) -> Result<(), VorbisError> {
+		// libvorbis sets the granule position of every packet to the position of the sample
+		// right after the last sample it decodes to, except for the last packet, whose
+		// granule position already accounts for the padding added to the end of the signal
+		let end_position = u64::try_from(packet.granule_position()).unwrap_or(0);
+		let is_eos = packet.is_eos();
+
+		if let AudioPacketState::Priming { priming_packet } = &mut self.audio_packet_state {
+			// Decoders can only discard samples from the first packet that decodes to
+			// samples, and the packet before it is needed to decode it. Therefore, keep the
+			// last packet that does not decode to samples after the priming ones, and drop
+			// every packet before it
+			if !is_eos && (priming_packet.is_none() || end_position <= self.priming_samples) {
+				*priming_packet = Some((packet.data().into(), end_position));
+				return Ok(());
+			}
+
+			let mut trims_start = false;
+			if let Some((priming_packet_data, priming_packet_end)) = priming_packet.take() {
+				trims_start = priming_packet_end < self.priming_samples;
+
+				// The priming packet must not be the last one on its page when trimming,
+				// as its granule position would be negative, which is invalid
+				self.submit_audio_packet(
+					&priming_packet_data,
+					if trims_start { -1 } else { 0 },
+					false,
+					false
+				)?;
+			}
+
+			self.audio_packet_state = AudioPacketState::Writing {
+				first_sample_packet: true,
+				trims_start
+			};
+		}
+
+		let AudioPacketState::Writing {
+			first_sample_packet,
+			trims_start
+		} = self.audio_packet_state
+		else {
+			// Packets after the last packet to keep are discarded
+			return Ok(());
+		};
+
+		let end_limit = self
+			.total_samples
+			.map(|total_samples| self.priming_samples.saturating_add(total_samples));
+		let trims_first_packet_start = first_sample_packet && trims_start;
+
+		// Decoders can't trim samples from both ends of the same packet, so in that case
+		// the stream ends on the next packet instead
+		let is_last_packet = is_eos
+			|| end_limit.is_some_and(|end_limit| {
+				end_position == end_limit || (end_position > end_limit && !trims_first_packet_start)
+			});
+		let output_end = match end_limit {
+			Some(end_limit) if is_last_packet => end_position.min(end_limit),
+			_ => end_position
+		};
+
+		// Ending the page after the first packet that decodes to samples makes decoders
+		// discard samples from its start according to its granule position
+		self.submit_audio_packet(
+			packet.data(),
+			output_end.saturating_sub(self.priming_samples) as i64,
+			is_last_packet,
+			trims_first_packet_start || is_last_packet
+		)?;
+
+		self.audio_packet_state = if is_last_packet {
+			AudioPacketState::Ended
+		} else {
+			AudioPacketState::Writing {
+				first_sample_packet: false,
+				trims_start
+			}
+		};
+
+		Ok(())
+	}
+
+	/// Submits an audio packet with the specified data, granule position and end of stream
+	/// flag to the Ogg stream, and writes the pages that become complete to the sink. If
+	/// `end_page` is true, the page this packet is on is ended and written right away.
+	fn submit_audio_packet(
+		&mut self,
+		data: &[u8],
+		granule_position: i64,
+		eos: bool,
+		end_page: bool
+	) -> Result<(), VorbisError> {
+		// The first audio page must end right after the first packet that decodes to
+		// samples when trimming them, so it is never ended before that packet
+		let trims_page_start = matches!(
+			self.audio_packet_state,
+			AudioPacketState::Writing {
+				first_sample_packet: true,
+				trims_start: true
+			}
+		);
+		if !trims_page_start {
+			self.end_pages_at_boundaries(granule_position)?;
+		}
+
+		let sink = self.sink.as_mut().unwrap();
+		let page_samples = |ogg_stream: &OggStream| {
+			granule_position.saturating_sub(ogg_stream.written_granule_position())
+		};
+		let maximum_page_samples = self
+			.maximum_page_samples
+			.map_or(i64::MAX, |samples| samples as i64);
+		if !trims_page_start && page_samples(&self.ogg_stream) > maximum_page_samples {
+			self.ogg_stream.flush(&mut *sink)?;
+		}
+
+		OggPacket::from_data(data, granule_position, false, eos).submit(&mut self.ogg_stream)?;
+
+		if end_page || page_samples(&self.ogg_stream) >= maximum_page_samples {
+			self.ogg_stream.flush(sink)?;
+		} else {
+			self.ogg_stream
+				.write_pending_pages(sink, self.minimum_page_data_size)?;
+		}
+
+		self.add_skeleton_keypoint();
+
+		Ok(())
+	}
+
+	/// Returns the number of bytes written to the sink since this encoder was built.
+	fn written_bytes(&self) -> u64 {
+		self.ogg_stream.written_bytes()
+			+ self
+				.skeleton_writer
+				.as_ref()
+				.map_or(0, SkeletonWriter::written_bytes)
+	}
+
+	/// Records the boundary after the last Ogg page written as a candidate keypoint for
+	/// the Skeleton index, if enabled.
+	fn add_skeleton_keypoint(&mut self) {
+		let written_bytes = self.written_bytes();
+		if let Some(skeleton_writer) = &mut self.skeleton_writer {
+			skeleton_writer.add_keypoint(written_bytes, self.ogg_stream.written_granule_position());
+		}
+	}
+
+	/// Ends the current Ogg page before submitting an audio packet with the specified granule
+	/// position if any page boundary was requested before that packet, and reports the
+	/// placed boundaries. This method expects that `self.sink` is `Some`.
+	fn end_pages_at_boundaries(&mut self, granule_position: i64) -> Result<(), VorbisError> {
+		while let Some(sample_position) = self.page_boundary_positions.next() {
+			#[cfg(feature = "resampling")]
+			let resampled_position = self
+				.resampler
+				.as_ref()
+				.map_or(sample_position, |resampler| {
+					(sample_position as f64 * resampler.ratio()).round() as u64
+				});
+			#[cfg(not(feature = "resampling"))]
+			let resampled_position = sample_position;
+
+			// Packets that end right at the boundary are kept before it
+			if resampled_position.saturating_sub(self.priming_samples) as i64 >= granule_position {
+				break;
+			}
+
+			self.ogg_stream.flush(self.sink.as_mut().unwrap())?;
+			self.page_boundaries.push(VorbisPageBoundary {
+				sample_position,
+				byte_offset: self.written_bytes(),
+				granule_position: self.ogg_stream.written_granule_position().max(0) as u64
+			});
+			self.page_boundary_positions.advance();
+		}
+
+		Ok(())
+	}
+
+	/// Returns the page boundaries placed in the stream since the last call to this method,
+	/// as requested with [`VorbisEncoderBuilder::page_boundaries`] and
+	/// [`VorbisEncoderBuilder::page_boundary_interval`], in stream order. Boundaries are
+	/// placed once the audio packet after them is encoded, so the last ones may only be
+	/// available from [`finish_with_page_boundaries`](Self::finish_with_page_boundaries).
+	pub fn drain_page_boundaries(&mut self) -> impl Iterator<Item = VorbisPageBoundary> + '_ {
+		self.page_boundaries.drain(..)
+	}
+
+	/// Ends the current Ogg page, writing any audio packets encoded so far to the sink right
+	/// away, and then flushes the sink. This is useful for live streaming, as it makes the
+	/// encoded audio available to listeners without waiting for a page to fill up.
+	///
+	/// The Vorbis encoder analyzes the signal ahead of the samples it encodes, so audio
+	/// blocks are not fully encoded until some more samples are submitted or the stream is
+	/// finished. Also, when [priming samples](VorbisEncoderBuilder::priming_samples) are
+	/// discarded, packets are held back until the first packet that decodes to samples is
+	/// encoded. Ending pages too often increases the Ogg encapsulation overhead.
+	pub fn flush(&mut self) -> Result<(), VorbisError> {
+		let sink = self.sink.as_mut().unwrap();
+
+		self.ogg_stream.flush(&mut *sink)?;
+		sink.flush()?;
+
+		self.add_skeleton_keypoint();
+
+		Ok(())
+	}
+
 	/// Finishes the encoded Vorbis stream, signalling its end and writing any pending
 	/// data to the configured sink.
 	///
@@ -443,16 +1163,84 @@ impl<W: Write> VorbisEncoder<W> {
 	///
 	/// Returns the owned sink back to the caller.
 	pub fn finish(mut self) -> Result<W, VorbisError> {
-		// SAFETY: we assume that vorbis_analysis_wrote follows its documented contract
-		unsafe {
-			libvorbis_return_value_to_result!(vorbis_analysis_wrote(
-				self.vorbis_encoding_state.vorbis_dsp_state,
-				0
-			))?
+		self.finish_stream().map(|()| self.sink.take().unwrap())
+	}
+
+	/// Like [`finish`](Self::finish), but also returns the page boundaries placed in the
+	/// stream that were not returned by [`drain_page_boundaries`](Self::drain_page_boundaries)
+	/// yet.
+	pub fn finish_with_page_boundaries(
+		mut self
+	) -> Result<(W, Vec<VorbisPageBoundary>), VorbisError> {
+		self.finish_stream()?;
+
+		Ok((
+			self.sink.take().unwrap(),
+			mem::take(&mut self.page_boundaries)
+		))
+	}
+
+	/// Finishes the encoded Vorbis stream like [`finish`](Self::finish), and returns its sink
+	/// together with a new encoder with the same configuration, which writes a new stream to
+	/// the specified sink. The serial of the new stream is the serial of the finished one
+	/// plus one, even if no serial was configured: unlike
+	/// [`VorbisEncoderConfig::build`], resetting never draws a random serial, so that the
+	/// streams written by a reset encoder have distinct serials when chained.
+	///
+	/// Resetting is faster than building a new encoder, as the new encoder reuses the
+	/// `libvorbisenc` setup and codebooks of this one, which makes a difference when
+	/// encoding many short signals, such as sound effects.
+	pub fn reset(self, sink: W) -> Result<(W, Self), VorbisError> {
+		let stream_serial = self.stream_serial.wrapping_add(1);
+		self.reset_with_serial(sink, stream_serial)
+	}
+
+	/// Like [`reset`](Self::reset), but the new stream has the specified serial.
+	pub(in crate::encoder) fn reset_with_serial(
+		mut self,
+		sink: W,
+		stream_serial: i32
+	) -> Result<(W, Self), VorbisError> {
+		self.finish_stream()?;
+		let finished_sink = self.sink.take().unwrap();
+
+		// SAFETY: the Vorbis stream information is only shared with this encoder, which we
+		// own on this thread, and is dropped when this method returns, before the new one
+		// can be sent to other threads
+		let encoder = unsafe {
+			Self::with_vorbis_info(
+				&self.config,
+				sink,
+				stream_serial,
+				self.seek,
+				Arc::clone(&self.vorbis_encoding_state.vorbis_info)
+			)?
 		};
 
-		self.write_pending_blocks()
-			.map(|()| self.sink.take().unwrap())
+		Ok((finished_sink, encoder))
+	}
+
+	/// Signals the end of the audio signal, writes the remaining data to the sink, and
+	/// rewrites the header pages with ReplayGain tags and the Skeleton index if enabled.
+	/// This method expects that `self.sink` is `Some`.
+	fn finish_stream(&mut self) -> Result<(), VorbisError> {
+		self.finish_audio_signal()?;
+		self.write_pending_blocks()?;
+
+		if let Some(replay_gain_tag_writer) = &self.replay_gain_tag_writer {
+			replay_gain_tag_writer.write_tags(self.sink.as_mut().unwrap())?;
+		}
+
+		let written_bytes = self.written_bytes();
+		if let Some(skeleton_writer) = &self.skeleton_writer {
+			skeleton_writer.write_index(
+				self.sink.as_mut().unwrap(),
+				written_bytes,
+				self.ogg_stream.written_granule_position()
+			)?;
+		}
+
+		Ok(())
 	}
 }
 
@@ -462,29 +1250,56 @@ impl<W: Write> Drop for VorbisEncoder<W> {
 		// so check whether we have a sink to finish: the sink is set to None if
 		// and only if the stream was finished explicitly
 		if self.sink.is_some() {
-			// SAFETY: we assume that vorbis_analysis_wrote follows its documented contract
-			if unsafe {
-				libvorbis_return_value_to_result!(vorbis_analysis_wrote(
-					self.vorbis_encoding_state.vorbis_dsp_state,
-					0
-				))
-			}
-			.is_ok()
-			{
-				self.write_pending_blocks().ok();
-			}
+			self.finish_stream().ok();
+		}
+	}
+}
+
+/// Borrows the channels of the specified planar audio block, which are in the specified
+/// order for the specified layout, in the Vorbis channel order, validating that the block
+/// has the channels of that layout and that all of them have the same sample count.
+fn vorbis_ordered_audio_block<S: AsRef<[f32]>>(
+	audio_block: &[S],
+	channel_layout: VorbisChannelLayout,
+	channel_order: VorbisChannelOrder
+) -> Result<TinyVec<[&[f32]; 8]>, VorbisError> {
+	let audio_channels = channel_layout.channel_count().get() as usize;
+
+	if audio_channels != audio_block.len() {
+		return Err(VorbisError::InvalidAudioBlockChannelCount {
+			actual: audio_block.len(),
+			expected: audio_channels
+		});
+	}
+
+	let sample_count = audio_block[0].as_ref().len();
+	let mut vorbis_audio_block = TinyVec::<[&[f32]; 8]>::with_capacity(audio_channels);
+	for vorbis_channel_index in 0..audio_channels {
+		let channel_samples =
+			audio_block[channel_layout.channel_index(channel_order, vorbis_channel_index)].as_ref();
+
+		if channel_samples.len() != sample_count {
+			return Err(VorbisError::InvalidAudioBlockSampleCount {
+				actual: channel_samples.len(),
+				expected: sample_count
+			});
 		}
+
+		vorbis_audio_block.push(channel_samples);
 	}
+
+	Ok(vorbis_audio_block)
 }
 
 #[cfg(test)]
 mod test {
 	use std::{
-		io,
-		num::{NonZeroU8, NonZeroU32}
+		io::{self, Cursor},
+		num::{NonZeroU8, NonZeroU32, NonZeroU64}
 	};
 
-	use super::VorbisEncoderBuilder;
+	use super::{VorbisEncoder, VorbisEncoderBuilder, VorbisPageBoundary};
+	use crate::common::test_util::{mono_encoder_builder, noise, page_offsets};
 
 	#[cfg(feature = "stream-serial-rng")]
 	#[test]
@@ -513,4 +1328,171 @@ mod test {
 			"Unexpected renewed serial after encoder building: {second_stream_serial} != {first_stream_serial} + 1"
 		);
 	}
+
+	/// Returns the granule positions of the pages of the specified Ogg stream.
+	fn page_granule_positions(encoded_ogg: &[u8]) -> Vec<i64> {
+		page_offsets(encoded_ogg)
+			.into_iter()
+			.map(|page_offset| {
+				i64::from_le_bytes(encoded_ogg[page_offset + 6..][..8].try_into().unwrap())
+			})
+			.collect()
+	}
+
+	fn encode_noise(
+		configure_builder: impl FnOnce(&mut VorbisEncoderBuilder<Vec<u8>>),
+		configure_encoder: impl FnOnce(&mut VorbisEncoder<Vec<u8>>)
+	) -> (Vec<u8>, Vec<VorbisPageBoundary>) {
+		let samples = noise(40_000);
+
+		let mut builder = mono_encoder_builder(vec![], 0);
+		configure_builder(&mut builder);
+		let mut encoder = builder.build().unwrap();
+		encoder.encode_audio_block([&samples[..20_000]]).unwrap();
+		configure_encoder(&mut encoder);
+		encoder.encode_audio_block([&samples[20_000..]]).unwrap();
+		encoder.finish_with_page_boundaries().unwrap()
+	}
+
+	#[test]
+	fn encoder_limits_page_durations() {
+		let (encoded_ogg, _) = encode_noise(
+			|builder| {
+				builder.maximum_page_duration(NonZeroU32::new(500));
+			},
+			|_| {}
+		);
+
+		// 500 ms at 8 kHz are 4000 samples
+		let granule_positions = page_granule_positions(&encoded_ogg);
+		for page_granule_positions in granule_positions.windows(2) {
+			assert!(
+				page_granule_positions[1] - page_granule_positions[0] <= 4000,
+				"Unexpected page granule positions: {granule_positions:?}"
+			);
+		}
+		assert_eq!(*granule_positions.last().unwrap(), 40_000);
+	}
+
+	#[test]
+	fn encoder_flushes_pages() {
+		// Large pages make it unlikely for the flushed page to end where a page would
+		// end anyway
+		let configure_builder = |builder: &mut VorbisEncoderBuilder<Vec<u8>>| {
+			builder.minimum_page_data_size(Some(u16::MAX));
+		};
+		let (encoded_ogg, _) = encode_noise(configure_builder, |_| {});
+		let (flushed_encoded_ogg, _) =
+			encode_noise(configure_builder, |encoder| encoder.flush().unwrap());
+
+		assert!(
+			page_granule_positions(&flushed_encoded_ogg).len()
+				> page_granule_positions(&encoded_ogg).len()
+		);
+	}
+
+	#[test]
+	fn encoder_places_page_boundaries() {
+		let mut drained_page_boundaries = vec![];
+		let (encoded_ogg, page_boundaries) = encode_noise(
+			|builder| {
+				builder
+					.page_boundaries([25_000, 10_000])
+					.page_boundary_interval(NonZeroU64::new(16_000));
+			},
+			|encoder| drained_page_boundaries.extend(encoder.drain_page_boundaries())
+		);
+		drained_page_boundaries.extend(page_boundaries);
+
+		assert_eq!(
+			drained_page_boundaries
+				.iter()
+				.map(|page_boundary| page_boundary.sample_position)
+				.collect::<Vec<_>>(),
+			[0, 10_000, 16_000, 25_000, 32_000]
+		);
+
+		let page_offsets = page_offsets(&encoded_ogg);
+		let granule_positions = page_granule_positions(&encoded_ogg);
+		for page_boundary in drained_page_boundaries {
+			let page_index = page_offsets
+				.iter()
+				.position(|&page_offset| page_offset as u64 == page_boundary.byte_offset)
+				.expect("Page boundaries should be at page offsets");
+
+			assert_eq!(
+				granule_positions[page_index - 1],
+				page_boundary.granule_position as i64
+			);
+			assert!(page_boundary.granule_position <= page_boundary.sample_position);
+			assert!(granule_positions[page_index] > page_boundary.sample_position as i64);
+		}
+	}
+
+	#[test]
+	fn encoder_writes_skeleton_index() {
+		let samples = (0..40_000)
+			.map(|i| (i as f32 * 0.05).sin() * 0.5)
+			.collect::<Vec<_>>();
+
+		let mut builder = mono_encoder_builder(Cursor::new(vec![]), 0);
+		builder
+			.skeleton_index(NonZeroU32::new(16))
+			.maximum_page_duration(NonZeroU32::new(250));
+		let mut encoder = builder.build().unwrap();
+		encoder.encode_audio_block([&samples]).unwrap();
+		let encoded_ogg = encoder.finish().unwrap().into_inner();
+
+		let page_offsets = page_offsets(&encoded_ogg);
+		let page_body = |page_offset: usize| {
+			let page = &encoded_ogg[page_offset..];
+			&page[27 + usize::from(page[26])..]
+		};
+
+		// The Skeleton BOS page comes first, followed by the Vorbis BOS page
+		assert_eq!(encoded_ogg[5] & 0x02, 0x02);
+		assert!(page_body(0).starts_with(b"fishead\0"));
+		assert!(page_body(page_offsets[1]).starts_with(b"\x01vorbis"));
+
+		let fishead = page_body(0);
+		assert_eq!(
+			u64::from_le_bytes(fishead[64..72].try_into().unwrap()),
+			encoded_ogg.len() as u64
+		);
+
+		let index = page_offsets
+			.iter()
+			.map(|&page_offset| page_body(page_offset))
+			.find(|page_body| page_body.starts_with(b"index\0"))
+			.expect("The Skeleton stream should have an index");
+		let keypoint_count = u64::from_le_bytes(index[10..18].try_into().unwrap());
+		assert!((2..=16).contains(&keypoint_count));
+		assert_eq!(
+			u64::from_le_bytes(index[34..42].try_into().unwrap()),
+			40_000
+		);
+
+		let mut encoded_keypoints = index[42..].iter();
+		let mut read_variable_length = || {
+			let mut number = 0;
+			for shift in (0..).step_by(7) {
+				let byte = *encoded_keypoints.next().unwrap();
+				number |= u64::from(byte & 0x7F) << shift;
+				if byte & 0x80 != 0 {
+					break;
+				}
+			}
+			number
+		};
+
+		let mut byte_offset = 0;
+		for _ in 0..keypoint_count {
+			byte_offset += read_variable_length();
+			read_variable_length();
+			assert!(
+				page_offsets.contains(&(byte_offset as usize)),
+				"Keypoints should point to page offsets"
+			);
+		}
+	}
 }
@@ -0,0 +1,258 @@
+use std::{
+	borrow::Cow,
+	io,
+	num::{NonZeroU8, NonZeroU32},
+	sync::Arc,
+	time::Duration
+};
+
+use crate::{
+	common::{OggStream, RawVorbisComments, VorbisError, VorbisReplayGain},
+	encoder::{
+		VorbisBitrateManagementStrategy, VorbisEncoderConfig, encoder_util::VorbisEncodingState
+	}
+};
+
+/// The typical size of the data of the Ogg pages `libogg` writes when no minimum page
+/// data size is configured, in bytes.
+const DEFAULT_PAGE_DATA_SIZE: u64 = 4096;
+
+/// The size of the header of an Ogg page without its segment table, in bytes.
+const PAGE_HEADER_SIZE: u64 = 27;
+
+/// The encoding parameters `libvorbisenc` selects for a [`VorbisEncoderConfig`], which
+/// are known before encoding any audio, and can estimate the size of the encoded streams.
+///
+/// # Examples
+///
+/// ```
+/// # use std::{num::{NonZeroU8, NonZeroU32}, time::Duration};
+/// # use vorbis_rs::{VorbisBitrateManagementStrategy, VorbisEncodingParameters};
+/// # fn main() -> Result<(), vorbis_rs::VorbisError> {
+/// let parameters = VorbisEncodingParameters::new(
+///     NonZeroU32::new(44_100).unwrap(),
+///     NonZeroU8::new(2).unwrap(),
+///     VorbisBitrateManagementStrategy::QualityVbr {
+///         target_quality: 0.5
+///     }
+/// )?;
+/// println!(
+///     "Nominal bitrate: {:?} bit/s, expected size of 3 minutes: {} bytes",
+///     parameters.nominal_bitrate(),
+///     parameters.estimated_size(Duration::from_secs(180))
+/// );
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct VorbisEncodingParameters {
+	sampling_frequency: NonZeroU32,
+	channels: NonZeroU8,
+	maximum_bitrate: Option<NonZeroU32>,
+	nominal_bitrate: Option<NonZeroU32>,
+	minimum_bitrate: Option<NonZeroU32>,
+	short_block_size: u16,
+	long_block_size: u16,
+	header_size: u64,
+	page_data_size: u64,
+	maximum_page_duration: Option<NonZeroU32>
+}
+
+impl VorbisEncodingParameters {
+	/// Returns the encoding parameters for a signal with the specified sampling frequency
+	/// and channels, encoded with the specified bitrate management strategy and the default
+	/// value of every other option. An error is returned if `libvorbisenc` does not support
+	/// encoding such a signal.
+	pub fn new(
+		sampling_frequency: NonZeroU32,
+		channels: NonZeroU8,
+		bitrate_management_strategy: VorbisBitrateManagementStrategy
+	) -> Result<Self, VorbisError> {
+		VorbisEncoderConfig::new(sampling_frequency, channels)
+			.bitrate_management_strategy(bitrate_management_strategy)
+			.encoding_parameters()
+	}
+
+	/// Returns the encoding parameters for encoders built with the specified configuration,
+	/// without building any encoder or writing anything to a sink. This is what
+	/// [`VorbisEncoderConfig::encoding_parameters`] does.
+	pub(in crate::encoder) fn from_config(
+		config: &VorbisEncoderConfig
+	) -> Result<Self, VorbisError> {
+		let vorbis_info = config.encoding_vorbis_info()?;
+		let [maximum_bitrate, nominal_bitrate, minimum_bitrate] = vorbis_info.bitrates();
+		let [short_block_size, long_block_size] = vorbis_info.block_sizes();
+
+		// The header packets are known before encoding any audio, so measure them exactly,
+		// including the ReplayGain tags reserved for encoders that write them
+		let mut comments = Cow::Borrowed(&config.comments);
+		if config.replay_gain_tags {
+			VorbisReplayGain {
+				gain: 0.0,
+				peak: Some(0.0)
+			}
+			.set_track_tags(comments.to_mut())?;
+		}
+
+		// SAFETY: the Vorbis stream information is not shared with anything else
+		let mut vorbis_encoding_state = unsafe { VorbisEncodingState::new(Arc::new(vorbis_info))? };
+		let mut raw_comments = RawVorbisComments::from_comments(&comments)?;
+		let mut ogg_stream = OggStream::new(0)?;
+		let [identification_header_packet, other_header_packets @ ..] =
+			&mut vorbis_encoding_state.get_header_packets(&mut raw_comments)?;
+		identification_header_packet.submit(&mut ogg_stream)?;
+		ogg_stream.flush(io::sink())?;
+		for header_packet in other_header_packets {
+			header_packet.submit(&mut ogg_stream)?;
+		}
+		ogg_stream.flush(io::sink())?;
+
+		Ok(Self {
+			sampling_frequency: config.encoded_sampling_frequency(),
+			channels: config.encoded_channels()?,
+			maximum_bitrate,
+			nominal_bitrate,
+			minimum_bitrate,
+			short_block_size,
+			long_block_size,
+			header_size: ogg_stream.written_bytes(),
+			page_data_size: config
+				.minimum_page_data_size
+				.map_or(DEFAULT_PAGE_DATA_SIZE, |minimum_page_data_size| {
+					u64::from(minimum_page_data_size).max(1)
+				}),
+			maximum_page_duration: config.maximum_page_duration
+		})
+	}
+
+	/// Returns the sampling frequency of the encoded signal, in Hertz (Hz).
+	pub fn sampling_frequency(&self) -> NonZeroU32 {
+		self.sampling_frequency
+	}
+
+	/// Returns the number of channels of the encoded signal.
+	pub fn channels(&self) -> NonZeroU8 {
+		self.channels
+	}
+
+	/// Returns the maximum bitrate of the encoded stream, in bit/s, which is only set for
+	/// the [constrained ABR](VorbisBitrateManagementStrategy::ConstrainedAbr) strategy.
+	pub fn maximum_bitrate(&self) -> Option<NonZeroU32> {
+		self.maximum_bitrate
+	}
+
+	/// Returns the nominal bitrate of the encoded stream, in bit/s. For quality-based
+	/// strategies, this is the typical bitrate of the selected quality, which varies with
+	/// the signal.
+	pub fn nominal_bitrate(&self) -> Option<NonZeroU32> {
+		self.nominal_bitrate
+	}
+
+	/// Returns the minimum bitrate of the encoded stream, in bit/s, which is not set for any
+	/// strategy `vorbis_rs` supports, but is still reported for completeness.
+	pub fn minimum_bitrate(&self) -> Option<NonZeroU32> {
+		self.minimum_bitrate
+	}
+
+	/// Returns the size of the short blocks of the encoded stream, in samples.
+	pub fn short_block_size(&self) -> u16 {
+		self.short_block_size
+	}
+
+	/// Returns the size of the long blocks of the encoded stream, in samples.
+	pub fn long_block_size(&self) -> u16 {
+		self.long_block_size
+	}
+
+	/// Returns the exact size of the header pages of the encoded stream, in bytes.
+	pub fn header_size(&self) -> u64 {
+		self.header_size
+	}
+
+	/// Estimates the size of the encoded stream for a signal of the specified duration, in
+	/// bytes, including its header pages and Ogg encapsulation overhead. The audio data is
+	/// estimated from the nominal bitrate, so the actual size of streams encoded with
+	/// quality-based strategies may differ noticeably, depending on how hard the signal is
+	/// to encode. Ogg Skeleton streams are not accounted for.
+	pub fn estimated_size(&self, duration: Duration) -> u64 {
+		let seconds = duration.as_secs_f64();
+		let audio_size =
+			(self.nominal_bitrate.map_or(0, NonZeroU32::get) as f64 * seconds / 8.0).round() as u64;
+
+		// Packets are about half a long block apart, as consecutive blocks overlap by half
+		let packets = (seconds * f64::from(self.sampling_frequency.get())
+			/ (f64::from(self.long_block_size) / 2.0))
+			.ceil() as u64;
+		let maximum_duration_pages =
+			self.maximum_page_duration
+				.map_or(0, |maximum_page_duration| {
+					(duration.as_millis() / u128::from(maximum_page_duration.get())) as u64
+				});
+		let pages = audio_size
+			.div_ceil(self.page_data_size)
+			.max(maximum_duration_pages);
+		// Each page has a header, and its segment table has one lacing value per 255 bytes
+		// of each packet, rounded up
+		let encapsulation_overhead = pages * PAGE_HEADER_SIZE + audio_size / 255 + packets;
+
+		self.header_size + audio_size + encapsulation_overhead
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::{
+		num::{NonZeroU8, NonZeroU32},
+		time::Duration
+	};
+
+	use super::VorbisEncodingParameters;
+	use crate::{VorbisBitrateManagementStrategy, VorbisEncoderBuilder, common::test_util::noise};
+
+	#[test]
+	fn encoding_parameters_estimate_sizes() {
+		let sampling_frequency = NonZeroU32::new(44_100).unwrap();
+		let channels = NonZeroU8::new(2).unwrap();
+		let average_bitrate = NonZeroU32::new(128_000).unwrap();
+		let parameters = VorbisEncodingParameters::new(
+			sampling_frequency,
+			channels,
+			VorbisBitrateManagementStrategy::Abr { average_bitrate }
+		)
+		.unwrap();
+
+		assert_eq!(parameters.nominal_bitrate(), Some(average_bitrate));
+		assert_eq!(parameters.maximum_bitrate(), None);
+		assert_eq!(parameters.short_block_size(), 256);
+		assert_eq!(parameters.long_block_size(), 2048);
+
+		let noise = noise(sampling_frequency.get() as usize * 10);
+		let mut encoded_ogg = vec![];
+		let mut encoder = VorbisEncoderBuilder::new_with_serial(
+			sampling_frequency,
+			channels,
+			&mut encoded_ogg,
+			0
+		)
+		.bitrate_management_strategy(VorbisBitrateManagementStrategy::Abr { average_bitrate })
+		.build()
+		.unwrap();
+		for block in noise.chunks(4096) {
+			encoder.encode_audio_block([block, block]).unwrap();
+		}
+		encoder.finish().unwrap();
+
+		// The bitrate of ABR streams is close to the average over long enough signals
+		let estimated_size = parameters.estimated_size(Duration::from_secs(10));
+		let size_error = (encoded_ogg.len() as f64 - estimated_size as f64).abs();
+		assert!(
+			size_error < estimated_size as f64 * 0.1,
+			"Estimated {estimated_size} bytes, got {}",
+			encoded_ogg.len()
+		);
+		assert_eq!(
+			parameters.estimated_size(Duration::ZERO),
+			parameters.header_size()
+		);
+	}
+}
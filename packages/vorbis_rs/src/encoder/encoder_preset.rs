@@ -0,0 +1,147 @@
+use std::num::NonZeroU32;
+
+use crate::encoder::VorbisBitrateManagementStrategy;
+#[allow(unused_imports)]
+use crate::{VorbisEncoderBuilder, VorbisEncoderConfig};
+
+/// A curated set of encoding options tuned for a common type of content, following the
+/// advice of the aoTuV tunings `libvorbisenc` is built with. Presets are applied with
+/// [`VorbisEncoderBuilder::preset`] or [`VorbisEncoderConfig::preset`], which set the
+/// options a preset bundles, and leave any other option alone. Options set after applying
+/// a preset override it.
+///
+/// | Preset | Bitrate management strategy | Lowpass | Coupling | Maximum page duration |
+/// |:-:|:-:|:-:|:-:|:-:|
+/// | [`Speech`](Self::Speech) | Quality VBR, 0.1 | 11 kHz | Yes | Default |
+/// | [`VoiceChat`](Self::VoiceChat) | Quality VBR, -0.1 | 8 kHz | Yes | 40 ms |
+/// | [`MusicTransparent`](Self::MusicTransparent) | Quality VBR, 0.6 | Default | Yes | Default |
+/// | [`GameSoundEffects`](Self::GameSoundEffects) | Quality VBR, 0.4 | Default | Yes | Default |
+/// | [`LowBandwidthStreaming`](Self::LowBandwidthStreaming) | Constrained ABR, 64 kbit/s | 14 kHz | Yes | 500 ms |
+///
+/// The options of each preset can be inspected with the methods of this type. Like any
+/// other options, `libvorbisenc` may not support them for uncommon sampling frequencies,
+/// which [`VorbisEncoderConfig::validate`] checks.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum VorbisEncoderPreset {
+	/// Spoken word content, such as podcasts, audiobooks and dialogue. Speech carries
+	/// little energy above 11 kHz, so cutting it frees bits for the frequencies that
+	/// matter for intelligibility.
+	Speech,
+	/// Real-time voice communication, which favors low bandwidth and latency over fidelity:
+	/// the narrow band of telephony is kept, and pages are kept short so that they can be
+	/// sent as soon as possible.
+	VoiceChat,
+	/// Music meant to be indistinguishable from the original signal for most listeners.
+	/// aoTuV regards quality 6 as transparent for virtually every kind of music.
+	MusicTransparent,
+	/// Short sound effects for games and other interactive applications, which are often
+	/// layered on top of other sounds that mask artifacts, and should stay compact.
+	GameSoundEffects,
+	/// Music and other content streamed over constrained networks, where a hard bitrate
+	/// limit keeps playback smooth. Pages are kept short so that listeners tuning in can
+	/// start decoding soon.
+	LowBandwidthStreaming
+}
+
+impl VorbisEncoderPreset {
+	/// Returns the bitrate management strategy this preset encodes with.
+	pub const fn bitrate_management_strategy(self) -> VorbisBitrateManagementStrategy {
+		match self {
+			Self::Speech => VorbisBitrateManagementStrategy::QualityVbr {
+				target_quality: 0.1
+			},
+			Self::VoiceChat => VorbisBitrateManagementStrategy::QualityVbr {
+				target_quality: -0.1
+			},
+			Self::MusicTransparent => VorbisBitrateManagementStrategy::QualityVbr {
+				target_quality: 0.6
+			},
+			Self::GameSoundEffects => VorbisBitrateManagementStrategy::QualityVbr {
+				target_quality: 0.4
+			},
+			Self::LowBandwidthStreaming => VorbisBitrateManagementStrategy::ConstrainedAbr {
+				maximum_bitrate: NonZeroU32::new(64_000).unwrap()
+			}
+		}
+	}
+
+	/// Returns the lowpass frequency this preset encodes with, in Hertz (Hz), or `None` if
+	/// it uses the lowpass frequency of the encoding mode.
+	pub const fn lowpass_frequency(self) -> Option<NonZeroU32> {
+		match self {
+			Self::Speech => NonZeroU32::new(11_000),
+			Self::VoiceChat => NonZeroU32::new(8000),
+			Self::MusicTransparent | Self::GameSoundEffects => None,
+			Self::LowBandwidthStreaming => NonZeroU32::new(14_000)
+		}
+	}
+
+	/// Returns whether this preset encodes with channel coupling.
+	pub const fn channel_coupling(self) -> bool {
+		true
+	}
+
+	/// Returns the maximum duration of the Ogg pages this preset encodes to, in
+	/// milliseconds, or `None` if it lets the encoder decide.
+	pub const fn maximum_page_duration(self) -> Option<NonZeroU32> {
+		match self {
+			Self::Speech | Self::MusicTransparent | Self::GameSoundEffects => None,
+			Self::VoiceChat => NonZeroU32::new(40),
+			Self::LowBandwidthStreaming => NonZeroU32::new(500)
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::num::{NonZeroU8, NonZeroU32};
+
+	use super::VorbisEncoderPreset;
+	use crate::{VorbisBitrateManagementStrategy, VorbisEncoderConfig};
+
+	#[test]
+	fn encoder_presets_apply_and_validate() {
+		for preset in [
+			VorbisEncoderPreset::Speech,
+			VorbisEncoderPreset::VoiceChat,
+			VorbisEncoderPreset::MusicTransparent,
+			VorbisEncoderPreset::GameSoundEffects,
+			VorbisEncoderPreset::LowBandwidthStreaming
+		] {
+			for (sampling_frequency, channels) in [(48_000, 2), (44_100, 1)] {
+				let mut config = VorbisEncoderConfig::new(
+					NonZeroU32::new(sampling_frequency).unwrap(),
+					NonZeroU8::new(channels).unwrap()
+				);
+				config.preset(preset);
+
+				assert_eq!(
+					config.bitrate_management_strategy,
+					preset.bitrate_management_strategy()
+				);
+				assert_eq!(config.lowpass_frequency, preset.lowpass_frequency());
+				assert_eq!(config.maximum_page_duration, preset.maximum_page_duration());
+				config
+					.validate()
+					.unwrap_or_else(|err| panic!("{preset:?} at {sampling_frequency} Hz: {err}"));
+			}
+		}
+
+		// Options set after a preset override it
+		let mut config =
+			VorbisEncoderConfig::new(NonZeroU32::new(48_000).unwrap(), NonZeroU8::new(2).unwrap());
+		config
+			.preset(VorbisEncoderPreset::Speech)
+			.lowpass_frequency(None)
+			.channel_coupling(false);
+		assert_eq!(
+			config.bitrate_management_strategy,
+			VorbisBitrateManagementStrategy::QualityVbr {
+				target_quality: 0.1
+			}
+		);
+		assert_eq!(config.lowpass_frequency, None);
+		config.validate().unwrap();
+	}
+}
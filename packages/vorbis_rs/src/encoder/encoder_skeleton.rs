@@ -0,0 +1,272 @@
+use std::{
+	io::{self, SeekFrom, Write},
+	num::NonZeroU32
+};
+
+use crate::common::{OggPacket, OggStream, VorbisError, write_variable_length};
+
+/// The size of the fields of an Ogg Skeleton 4.0 index packet before its keypoints.
+const INDEX_HEADER_SIZE: usize = 42;
+
+/// The number of bytes reserved for each keypoint in the index packet. Keypoint fields
+/// are encoded with a variable length, and this is enough for typical keypoint spacings.
+/// Keypoints are thinned out if they don't fit.
+const RESERVED_KEYPOINT_SIZE: usize = 8;
+
+/// The message header fields of the Vorbis stream bone, as mandated by Skeleton 4.0.
+const VORBIS_MESSAGE_HEADERS: &[u8] =
+	b"Content-Type: audio/vorbis\r\nRole: audio/main\r\nName: audio_0\r\n";
+
+/// Writes an Ogg Skeleton 4.0 logical stream that describes the Vorbis stream written by
+/// a [`VorbisEncoder`](crate::VorbisEncoder) to its seekable sink, including a keypoint
+/// index. The index and the segment fields of the Skeleton header are only known once the
+/// encoder finishes, so their pages are written with placeholder values first, and then
+/// rewritten in place.
+pub(crate) struct SkeletonWriter<W> {
+	ogg_stream: OggStream,
+	stream_serial: i32,
+	vorbis_stream_serial: i32,
+	sampling_frequency: NonZeroU32,
+	maximum_keypoints: NonZeroU32,
+	/// The position of the sink where the first Skeleton page was written, which is the
+	/// beginning of the segment described by the Skeleton stream.
+	segment_position: u64,
+	/// The offset of the index page from the beginning of the segment.
+	index_offset: u64,
+	/// The offset of the first audio page from the beginning of the segment.
+	content_offset: u64,
+	/// The byte offsets and granule positions of the candidate keypoints.
+	keypoints: Vec<(u64, u64)>,
+	/// The `Seek::seek` method of the sink.
+	seek: fn(&mut W, SeekFrom) -> io::Result<u64>
+}
+
+impl<W: Write> SkeletonWriter<W> {
+	/// Creates a new Skeleton writer for the Vorbis stream with the specified serial and
+	/// sampling frequency, and writes the Skeleton BOS page to the specified sink, which
+	/// must be written before the Vorbis BOS page.
+	pub fn new(
+		sink: &mut W,
+		seek: fn(&mut W, SeekFrom) -> io::Result<u64>,
+		vorbis_stream_serial: i32,
+		sampling_frequency: NonZeroU32,
+		maximum_keypoints: NonZeroU32
+	) -> Result<Self, VorbisError> {
+		// Any serial different from the Vorbis one will do
+		let stream_serial = !vorbis_stream_serial;
+		let mut skeleton_writer = Self {
+			ogg_stream: OggStream::new(stream_serial)?,
+			stream_serial,
+			vorbis_stream_serial,
+			sampling_frequency,
+			maximum_keypoints,
+			segment_position: seek(sink, SeekFrom::Current(0))?,
+			index_offset: 0,
+			content_offset: 0,
+			keypoints: Vec::new(),
+			seek
+		};
+
+		OggPacket::from_data(&fishead_packet(0, 0), 0, true, false)
+			.submit(&mut skeleton_writer.ogg_stream)?;
+		skeleton_writer.ogg_stream.flush(sink)?;
+
+		Ok(skeleton_writer)
+	}
+
+	/// Writes the Skeleton secondary header pages, which must be written after the Vorbis
+	/// BOS page, whose size is specified, and before the Skeleton EOS page.
+	pub fn write_header_pages(
+		&mut self,
+		sink: &mut W,
+		vorbis_bytes: u64
+	) -> Result<(), VorbisError> {
+		OggPacket::from_data(&self.fisbone_packet(), 0, false, false)
+			.submit(&mut self.ogg_stream)?;
+		self.ogg_stream.flush(&mut *sink)?;
+
+		self.index_offset = self.ogg_stream.written_bytes() + vorbis_bytes;
+		OggPacket::from_data(&self.index_packet(&[], 0), 0, false, false)
+			.submit(&mut self.ogg_stream)?;
+		self.ogg_stream.flush(sink)
+	}
+
+	/// Writes the Skeleton EOS page, which must be written after every header page of the
+	/// Vorbis stream, whose size is specified, and before its first audio page.
+	pub fn write_eos_page(&mut self, sink: &mut W, vorbis_bytes: u64) -> Result<(), VorbisError> {
+		OggPacket::from_data(&[], 0, false, true).submit(&mut self.ogg_stream)?;
+		self.ogg_stream.flush(sink)?;
+
+		// The first audio page is always a keypoint
+		self.content_offset = self.ogg_stream.written_bytes() + vorbis_bytes;
+		self.keypoints.push((self.content_offset, 0));
+
+		Ok(())
+	}
+
+	/// Returns the number of bytes of the Skeleton pages written.
+	pub fn written_bytes(&self) -> u64 {
+		self.ogg_stream.written_bytes()
+	}
+
+	/// Records a page boundary of the Vorbis stream as a candidate keypoint, specifying
+	/// its offset from the beginning of the segment and the granule position of the page
+	/// before it, which is the time of the first sample that can be decoded after it.
+	pub fn add_keypoint(&mut self, byte_offset: u64, granule_position: i64) {
+		if self
+			.keypoints
+			.last()
+			.is_none_or(|&(last_byte_offset, _)| byte_offset > last_byte_offset)
+		{
+			self.keypoints
+				.push((byte_offset, granule_position.max(0) as u64));
+		}
+	}
+
+	/// Rewrites the Skeleton header and index pages written to the specified sink with the
+	/// final segment length and keypoints, once every page of the segment was written. The
+	/// sink position is left unchanged.
+	pub fn write_index(
+		&self,
+		sink: &mut W,
+		segment_length: u64,
+		end_granule_position: i64
+	) -> Result<(), VorbisError> {
+		// Keypoints at the end of the segment don't point to any page
+		let keypoint_count = self
+			.keypoints
+			.partition_point(|&(byte_offset, _)| byte_offset < segment_length);
+
+		// Encapsulate the header packets exactly like before, with the final values
+		let mut ogg_stream = OggStream::new(self.stream_serial)?;
+		let mut fishead_page = vec![];
+		OggPacket::from_data(
+			&fishead_packet(self.content_offset, segment_length),
+			0,
+			true,
+			false
+		)
+		.submit(&mut ogg_stream)?;
+		ogg_stream.flush(&mut fishead_page)?;
+
+		OggPacket::from_data(&self.fisbone_packet(), 0, false, false).submit(&mut ogg_stream)?;
+		ogg_stream.flush(io::sink())?;
+
+		let mut index_pages = vec![];
+		OggPacket::from_data(
+			&self.index_packet(
+				&self.keypoints[..keypoint_count],
+				end_granule_position.max(0) as u64
+			),
+			0,
+			false,
+			false
+		)
+		.submit(&mut ogg_stream)?;
+		ogg_stream.flush(&mut index_pages)?;
+
+		let end_position = (self.seek)(sink, SeekFrom::Current(0))?;
+		(self.seek)(sink, SeekFrom::Start(self.segment_position))?;
+		sink.write_all(&fishead_page)?;
+		(self.seek)(
+			sink,
+			SeekFrom::Start(self.segment_position + self.index_offset)
+		)?;
+		sink.write_all(&index_pages)?;
+		(self.seek)(sink, SeekFrom::Start(end_position))?;
+
+		Ok(())
+	}
+
+	/// Returns the Skeleton fisbone packet that describes the Vorbis stream.
+	fn fisbone_packet(&self) -> Vec<u8> {
+		let mut packet = Vec::with_capacity(52 + VORBIS_MESSAGE_HEADERS.len());
+		packet.extend_from_slice(b"fisbone\0");
+		// Offset of the message header fields from this field
+		packet.extend_from_slice(&44_u32.to_le_bytes());
+		packet.extend_from_slice(&self.vorbis_stream_serial.to_le_bytes());
+		// Number of header packets
+		packet.extend_from_slice(&3_u32.to_le_bytes());
+		// Granule rate, as a fraction
+		packet.extend_from_slice(&i64::from(self.sampling_frequency.get()).to_le_bytes());
+		packet.extend_from_slice(&1_i64.to_le_bytes());
+		// Base granule
+		packet.extend_from_slice(&0_i64.to_le_bytes());
+		// Preroll: Vorbis packets can only be decoded after the previous one
+		packet.extend_from_slice(&2_u32.to_le_bytes());
+		// Granule shift and padding
+		packet.extend_from_slice(&[0; 4]);
+		packet.extend_from_slice(VORBIS_MESSAGE_HEADERS);
+		packet
+	}
+
+	/// Returns the Skeleton index packet for the Vorbis stream, with as many of the
+	/// specified keypoints as fit in the reserved space for them, evenly spread over the
+	/// duration of the stream. Its size is always the same.
+	fn index_packet(&self, keypoints: &[(u64, u64)], end_granule_position: u64) -> Vec<u8> {
+		let index_size =
+			INDEX_HEADER_SIZE + self.maximum_keypoints.get() as usize * RESERVED_KEYPOINT_SIZE;
+		let mut maximum_keypoints = keypoints.len().min(self.maximum_keypoints.get() as usize);
+
+		let (keypoint_count, encoded_keypoints) = loop {
+			let spacing = end_granule_position / maximum_keypoints.max(1) as u64;
+			let mut encoded_keypoints = vec![];
+			let mut keypoint_count = 0;
+			let (mut last_byte_offset, mut last_granule_position) = (0, 0);
+
+			for &(byte_offset, granule_position) in keypoints {
+				if keypoint_count == maximum_keypoints
+					|| (keypoint_count > 0 && granule_position < last_granule_position + spacing)
+				{
+					continue;
+				}
+
+				write_variable_length(&mut encoded_keypoints, byte_offset - last_byte_offset);
+				write_variable_length(
+					&mut encoded_keypoints,
+					granule_position - last_granule_position
+				);
+				keypoint_count += 1;
+				(last_byte_offset, last_granule_position) = (byte_offset, granule_position);
+			}
+
+			if INDEX_HEADER_SIZE + encoded_keypoints.len() <= index_size {
+				break (keypoint_count, encoded_keypoints);
+			}
+			maximum_keypoints = maximum_keypoints * 3 / 4;
+		};
+
+		let mut packet = Vec::with_capacity(index_size);
+		packet.extend_from_slice(b"index\0");
+		packet.extend_from_slice(&self.vorbis_stream_serial.to_le_bytes());
+		packet.extend_from_slice(&(keypoint_count as u64).to_le_bytes());
+		// Timestamp denominator, and first and last sample time numerators
+		packet.extend_from_slice(&i64::from(self.sampling_frequency.get()).to_le_bytes());
+		packet.extend_from_slice(&0_i64.to_le_bytes());
+		packet.extend_from_slice(&end_granule_position.to_le_bytes());
+		packet.extend_from_slice(&encoded_keypoints);
+		// Readers stop reading after the last keypoint, so the rest is padding
+		packet.resize(index_size, 0);
+		packet
+	}
+}
+
+/// Returns a Skeleton 4.0 fishead packet for a segment with the specified content offset
+/// and length, in bytes. The presentation time of the segment begins at zero.
+fn fishead_packet(content_offset: u64, segment_length: u64) -> Vec<u8> {
+	let mut packet = Vec::with_capacity(80);
+	packet.extend_from_slice(b"fishead\0");
+	// Version 4.0
+	packet.extend_from_slice(&4_u16.to_le_bytes());
+	packet.extend_from_slice(&0_u16.to_le_bytes());
+	// Presentation time and base time, as fractions of milliseconds
+	for _ in 0..2 {
+		packet.extend_from_slice(&0_i64.to_le_bytes());
+		packet.extend_from_slice(&1000_i64.to_le_bytes());
+	}
+	// UTC time, which is not set
+	packet.extend_from_slice(&[0; 20]);
+	packet.extend_from_slice(&segment_length.to_le_bytes());
+	packet.extend_from_slice(&content_offset.to_le_bytes());
+	packet
+}
@@ -1,23 +1,47 @@
-use std::{mem::MaybeUninit, num::NonZeroU32};
+use std::{
+	collections::VecDeque,
+	io::{self, ErrorKind, SeekFrom, Write},
+	mem::MaybeUninit,
+	num::{NonZeroU32, NonZeroU64},
+	sync::Arc
+};
 
 use aotuv_lancer_vorbis_sys::{
 	vorbis_analysis_headerout, vorbis_analysis_init, vorbis_block, vorbis_block_clear,
 	vorbis_block_init, vorbis_dsp_clear, vorbis_dsp_state
 };
 
-use crate::common::{OggPacket, VorbisComments, VorbisError, VorbisInfo};
+use crate::common::{
+	OggPacket, OggStream, RawVorbisComments, VorbisComments, VorbisError, VorbisInfo,
+	VorbisLoudnessMeter, VorbisReplayGain
+};
 
 /// A high-level abstraction that holds all the needed state for a Vorbis encoder.
 pub(crate) struct VorbisEncodingState {
-	pub vorbis_info: VorbisInfo,
+	pub vorbis_info: Arc<VorbisInfo>,
 	pub vorbis_dsp_state: *mut vorbis_dsp_state,
 	pub vorbis_block: *mut vorbis_block
 }
 
+// SAFETY: the pointed-to structs are owned by this value, and libvorbis keeps no
+// thread-local or global mutable encoding state, so they can be used from any thread.
+// They are only accessed through mutable references to this value
+unsafe impl Send for VorbisEncodingState {}
+unsafe impl Sync for VorbisEncodingState {}
+
 impl VorbisEncodingState {
 	/// Creates a new Vorbis encoder state from the specified Vorbis stream
 	/// information, which should be initialized for encoding.
-	pub fn new(vorbis_info: VorbisInfo) -> Result<Self, VorbisError> {
+	///
+	/// `libvorbis` builds the encoding codebooks for the stream information when the first
+	/// encoder state is created from it, and keeps them there, so creating further
+	/// encoder states from the same stream information is faster.
+	///
+	/// # Safety
+	///
+	/// Building the codebooks writes to the shared stream information, so no other thread
+	/// may access it while this function runs.
+	pub unsafe fn new(vorbis_info: Arc<VorbisInfo>) -> Result<Self, VorbisError> {
 		let vorbis_dsp_state = Box::into_raw(Box::<vorbis_dsp_state>::new_uninit());
 		let vorbis_block = Box::into_raw(Box::<vorbis_block>::new_uninit());
 
@@ -27,9 +51,9 @@ impl VorbisEncodingState {
 		// internal libvorbis encoding state stores pointers to such addresses, and
 		// eagerly extracted from `Box`es to guarantee that no lack of aliasing and
 		// interior mutability assumptions made by `Box` are violated (i.e., `Box` is
-		// an owned unique pointer, see #22). We take the ownership of vorbis_info even
-		// though we don't use it to ensure its lifetime is as long as the lifetime of
-		// the encoding state and that client code does not corrupt it
+		// an owned unique pointer, see #22). We take a shared ownership of vorbis_info
+		// even though we don't use it to ensure its lifetime is as long as the lifetime
+		// of the encoding state and that client code does not corrupt it
 		let init_error = 'init: {
 			if let Err(err) = unsafe {
 				libvorbis_return_value_to_result!(vorbis_analysis_init(
@@ -76,8 +100,8 @@ impl VorbisEncodingState {
 	/// metadata to it, in the form of user comments.
 	pub fn get_header_packets(
 		&mut self,
-		vorbis_comments: &mut VorbisComments
-	) -> Result<[OggPacket; 3], VorbisError> {
+		vorbis_comments: &mut RawVorbisComments
+	) -> Result<[OggPacket<'_>; 3], VorbisError> {
 		let mut identification_header = MaybeUninit::uninit();
 		let mut comment_header = MaybeUninit::uninit();
 		let mut setup_header = MaybeUninit::uninit();
@@ -120,8 +144,161 @@ impl Drop for VorbisEncodingState {
 	}
 }
 
+/// Tracks which of the audio packets generated by libvorbis are written to the Ogg
+/// stream of a [`VorbisEncoder`](crate::VorbisEncoder), so that it decodes to the
+/// configured priming and total samples.
+pub(crate) enum AudioPacketState {
+	/// Looking for the last packet that does not decode to any sample after the priming
+	/// samples, which is held here with its end position until a later packet is generated.
+	Priming {
+		priming_packet: Option<(Vec<u8>, u64)>
+	},
+	/// Writing packets to the Ogg stream. `first_sample_packet` is true for the first
+	/// packet that decodes to samples, and `trims_start` tells whether decoders must
+	/// discard some of its samples.
+	Writing {
+		first_sample_packet: bool,
+		trims_start: bool
+	},
+	/// The last packet to keep was written, so any remaining packets are discarded.
+	Ended
+}
+
+/// A page boundary requested from a [`VorbisEncoder`](crate::VorbisEncoder) with
+/// [`VorbisEncoderBuilder::page_boundaries`](crate::VorbisEncoderBuilder::page_boundaries)
+/// or [`VorbisEncoderBuilder::page_boundary_interval`](crate::VorbisEncoderBuilder::page_boundary_interval),
+/// as placed in the encoded stream.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct VorbisPageBoundary {
+	/// The requested sample position, counted in input samples since the first sample
+	/// submitted to the encoder.
+	pub sample_position: u64,
+	/// The offset of the first page after the boundary, in bytes since the position of
+	/// the sink when the encoder was built.
+	pub byte_offset: u64,
+	/// The granule position of the last page before the boundary, which is the position
+	/// of the last sample that can be decoded from the pages before it. It is not after the
+	/// requested sample position, once converted to the sampling frequency of the stream
+	/// and excluding any discarded priming samples, unless discarding them also makes
+	/// decoders trim the first audio packet that position is decoded from.
+	pub granule_position: u64
+}
+
+/// Yields the input sample positions a [`VorbisEncoder`](crate::VorbisEncoder) must start
+/// new Ogg pages at, in ascending order.
+pub(crate) struct PageBoundaryPositions {
+	positions: VecDeque<u64>,
+	interval: Option<NonZeroU64>,
+	next_interval_position: Option<u64>
+}
+
+impl PageBoundaryPositions {
+	/// Creates the page boundary positions for the specified positions, in any order,
+	/// and every multiple of the specified interval.
+	pub fn new(mut positions: Vec<u64>, interval: Option<NonZeroU64>) -> Self {
+		positions.sort_unstable();
+		positions.dedup();
+
+		Self {
+			positions: positions.into(),
+			interval,
+			next_interval_position: interval.map(|_| 0)
+		}
+	}
+
+	/// Returns the next page boundary position, if any.
+	pub fn next(&self) -> Option<u64> {
+		match (self.positions.front(), self.next_interval_position) {
+			(Some(&position), Some(interval_position)) => Some(position.min(interval_position)),
+			(position, interval_position) => position.copied().or(interval_position)
+		}
+	}
+
+	/// Moves past the next page boundary position.
+	pub fn advance(&mut self) {
+		let Some(next) = self.next() else {
+			return;
+		};
+
+		if self.positions.front() == Some(&next) {
+			self.positions.pop_front();
+		}
+
+		if self.next_interval_position == Some(next) {
+			self.next_interval_position = self
+				.interval
+				.and_then(|interval| next.checked_add(interval.get()));
+		}
+	}
+}
+
+/// Measures the loudness of the signal encoded by a [`VorbisEncoder`](crate::VorbisEncoder),
+/// and rewrites the header pages it wrote to its seekable sink with the resulting
+/// ReplayGain track tags when it finishes.
+pub(crate) struct ReplayGainTagWriter<W> {
+	pub loudness_meter: VorbisLoudnessMeter,
+	/// The comments written to the comment header, with placeholder ReplayGain tags.
+	pub comments: VorbisComments,
+	/// The data of the identification, comment and setup header packets.
+	pub header_packets: [Vec<u8>; 3],
+	/// The position of the sink where the comment header page was written.
+	pub header_position: u64,
+	pub stream_serial: i32,
+	/// The `Seek::seek` method of the sink.
+	pub seek: fn(&mut W, SeekFrom) -> io::Result<u64>
+}
+
+impl<W: Write> ReplayGainTagWriter<W> {
+	/// Rewrites the comment and setup header pages written to the specified sink with the
+	/// ReplayGain track tags for the signal measured so far. The sink position is left
+	/// unchanged.
+	pub fn write_tags(&self, sink: &mut W) -> Result<(), VorbisError> {
+		// Silent signals have no defined loudness, so leave their gain alone
+		let sample_peak = self.loudness_meter.sample_peak();
+		let replay_gain = self.loudness_meter.integrated_loudness().map_or(
+			VorbisReplayGain {
+				gain: 0.0,
+				peak: Some(sample_peak)
+			},
+			|loudness| VorbisReplayGain::from_loudness(loudness, sample_peak)
+		);
+
+		let mut comments = self.comments.clone();
+		replay_gain.set_track_tags(&mut comments)?;
+		let comment_header = RawVorbisComments::from_comments(&comments)?.header_packet_data()?;
+
+		// The header pages can only be rewritten in place if their size does not change,
+		// which the fixed width of the placeholder tag values guarantees
+		if comment_header.len() != self.header_packets[1].len() {
+			return Err(io::Error::new(
+				ErrorKind::InvalidData,
+				"The comment header size changed after writing ReplayGain tags"
+			)
+			.into());
+		}
+
+		// Encapsulate the header packets exactly like the encoder did. The identification
+		// header page does not change, and other streams' pages may follow it
+		let mut ogg_stream = OggStream::new(self.stream_serial)?;
+		OggPacket::from_data(&self.header_packets[0], 0, true, false).submit(&mut ogg_stream)?;
+		ogg_stream.flush(io::sink())?;
+
+		OggPacket::from_data(&comment_header, 0, false, false).submit(&mut ogg_stream)?;
+		OggPacket::from_data(&self.header_packets[2], 0, false, false).submit(&mut ogg_stream)?;
+		let mut header_pages = vec![];
+		ogg_stream.flush(&mut header_pages)?;
+
+		let end_position = (self.seek)(sink, SeekFrom::Current(0))?;
+		(self.seek)(sink, SeekFrom::Start(self.header_position))?;
+		sink.write_all(&header_pages)?;
+		(self.seek)(sink, SeekFrom::Start(end_position))?;
+
+		Ok(())
+	}
+}
+
 /// Represents a bitrate management strategy that a Vorbis encoder can use.
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum VorbisBitrateManagementStrategy {
 	/// Pure VBR quality mode, selected by a target bitrate (in bit/s). The
 	/// bitrate management engine is not enabled. The average bitrate will
@@ -0,0 +1,7 @@
+mod icecast_connection;
+pub use icecast_connection::{
+	VorbisIcecastConnection, VorbisIcecastProtocol, VorbisIcecastSettings
+};
+
+mod icecast_source;
+pub use icecast_source::VorbisIcecastSource;
@@ -0,0 +1,186 @@
+use crate::{
+	VorbisComments, VorbisEncoder, VorbisEncoderBuilder, VorbisError, VorbisIcecastConnection
+};
+
+/// Broadcasts a live audio signal to an Icecast server mount, encoding it to Ogg Vorbis
+/// with the encoders built by a [`VorbisEncoderBuilder`] whose sink is a
+/// [`VorbisIcecastConnection`].
+///
+/// Track metadata, such as titles, is sent as the Vorbis comments of the stream. As
+/// comments can't change in the middle of a logical Ogg Vorbis stream, changing them
+/// ends the current stream and starts a new one, chained after it, from which Icecast
+/// and players pick up the new metadata. Be aware that the end of the previous stream
+/// is padded to a whole Vorbis block, so there may be a short gap in the audio.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use std::num::{NonZeroU8, NonZeroU32};
+/// # use vorbis_rs::{
+/// #     VorbisComments, VorbisEncoderBuilder, VorbisError, VorbisIcecastConnection,
+/// #     VorbisIcecastSettings, VorbisIcecastSource
+/// # };
+/// # fn main() -> Result<(), VorbisError> {
+/// let settings = VorbisIcecastSettings::new("localhost", 8000, "/live.ogg", "hackme");
+/// let connection = VorbisIcecastConnection::connect(&settings)?;
+/// let mut source = VorbisIcecastSource::new(VorbisEncoderBuilder::new(
+///     NonZeroU32::new(44100).unwrap(),
+///     NonZeroU8::new(2).unwrap(),
+///     connection
+/// )?)?;
+///
+/// let mut comments = VorbisComments::new();
+/// comments.add("TITLE", "Opening theme")?;
+/// source.set_comments(comments)?;
+///
+/// let silence = [[0.0; 4410]; 2];
+/// source.encode_audio_block(silence)?;
+/// source.finish()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct VorbisIcecastSource {
+	encoder_builder: VorbisEncoderBuilder<VorbisIcecastConnection>,
+	encoder: Option<VorbisEncoder<VorbisIcecastConnection>>
+}
+
+impl VorbisIcecastSource {
+	/// Starts broadcasting a new stream, built by the specified encoder builder, which is
+	/// kept to build the streams chained after it when [comments](Self::set_comments)
+	/// change.
+	pub fn new(
+		mut encoder_builder: VorbisEncoderBuilder<VorbisIcecastConnection>
+	) -> Result<Self, VorbisError> {
+		let encoder = encoder_builder.build()?;
+
+		Ok(Self {
+			encoder_builder,
+			encoder: Some(encoder)
+		})
+	}
+
+	/// Submits the specified audio block for encoding and broadcasting. This works the
+	/// same as [`VorbisEncoder::encode_audio_block`].
+	pub fn encode_audio_block<B: AsRef<[S]>, S: AsRef<[f32]>>(
+		&mut self,
+		audio_block: B
+	) -> Result<(), VorbisError> {
+		self.encoder()?.encode_audio_block(audio_block)
+	}
+
+	/// Ends the current Ogg page, sending the audio encoded so far to the server right
+	/// away. This works the same as [`VorbisEncoder::flush`].
+	pub fn flush(&mut self) -> Result<(), VorbisError> {
+		self.encoder()?.flush()
+	}
+
+	/// Changes the Vorbis comments of the broadcast stream, such as its `TITLE` and
+	/// `ARTIST`, by finishing the current logical stream and starting a new one with the
+	/// specified comments.
+	///
+	/// If finishing the current stream or starting the new one fails, the connection is
+	/// lost, and every further operation on this source returns an
+	/// [`IcecastConnectionLost`](VorbisError::IcecastConnectionLost) error.
+	pub fn set_comments(&mut self, comments: VorbisComments) -> Result<(), VorbisError> {
+		let connection = self
+			.encoder
+			.take()
+			.ok_or(VorbisError::IcecastConnectionLost)?
+			.finish()?;
+
+		self.encoder_builder.sink(connection).comments(comments);
+		self.encoder = Some(self.encoder_builder.build()?);
+
+		Ok(())
+	}
+
+	/// Finishes the broadcast stream, returning the connection it was sent through.
+	pub fn finish(mut self) -> Result<VorbisIcecastConnection, VorbisError> {
+		self.encoder
+			.take()
+			.ok_or(VorbisError::IcecastConnectionLost)?
+			.finish()
+	}
+
+	/// Returns the encoder of the current logical stream. The connection is lost if the
+	/// previous stream could not be finished, or a new encoder could not be built, after
+	/// changing comments.
+	fn encoder(&mut self) -> Result<&mut VorbisEncoder<VorbisIcecastConnection>, VorbisError> {
+		self.encoder
+			.as_mut()
+			.ok_or(VorbisError::IcecastConnectionLost)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::VorbisIcecastSource;
+	use crate::{
+		VorbisComments, VorbisError, VorbisIcecastConnection, VorbisIcecastSettings,
+		common::test_util::{mono_encoder_builder, tone},
+		icecast::icecast_connection::test::{spawn_hanging_up_server, spawn_server}
+	};
+
+	#[test]
+	fn source_chains_streams_on_comment_changes() {
+		let (port, server) = spawn_server("HTTP/1.0 200 OK\r\n\r\n");
+
+		let settings = VorbisIcecastSettings::new("127.0.0.1", port, "/live.ogg", "hackme");
+		let mut source = VorbisIcecastSource::new(mono_encoder_builder(
+			VorbisIcecastConnection::connect(&settings).unwrap(),
+			0
+		))
+		.unwrap();
+
+		let samples = tone(8000, 0.5);
+		source.encode_audio_block([&samples]).unwrap();
+		let mut comments = VorbisComments::new();
+		comments.add("TITLE", "Second track").unwrap();
+		source.set_comments(comments).unwrap();
+		source.encode_audio_block([&samples]).unwrap();
+		drop(source.finish().unwrap());
+
+		let (_, data) = server.join().unwrap();
+
+		// Every chained stream begins with a BOS page
+		let bos_pages = data
+			.windows(6)
+			.filter(|window| window.starts_with(b"OggS\0") && window[5] & 2 != 0)
+			.count();
+		assert_eq!(bos_pages, 2);
+		assert!(
+			data.windows(18)
+				.any(|window| window == b"TITLE=Second track")
+		);
+	}
+	#[test]
+	fn source_reports_lost_connections_after_failed_comment_changes() {
+		let (port, server) = spawn_hanging_up_server();
+
+		let settings = VorbisIcecastSettings::new("127.0.0.1", port, "/live.ogg", "hackme");
+		let mut source = VorbisIcecastSource::new(mono_encoder_builder(
+			VorbisIcecastConnection::connect(&settings).unwrap(),
+			0
+		))
+		.unwrap();
+		server.join().unwrap();
+
+		// Writing to a closed connection may succeed until the peer resets it
+		let samples = tone(8000, 0.5);
+		for _ in 0..100 {
+			if source.encode_audio_block([&samples]).is_err() || source.flush().is_err() {
+				break;
+			}
+		}
+
+		assert!(source.set_comments(VorbisComments::new()).is_err());
+		assert!(matches!(
+			source.encode_audio_block([&samples]),
+			Err(VorbisError::IcecastConnectionLost)
+		));
+		assert!(matches!(
+			source.finish(),
+			Err(VorbisError::IcecastConnectionLost)
+		));
+	}
+}
@@ -15,7 +15,12 @@
 //! supported for now. These are seldom found, however. Feel free to file an issue or open a pull
 //! request if you are interested in chained stream support.
 //!
-//! Seeking is also not supported, although it'd be a welcome addition.
+//! Seeking is only supported for decoders created with a seekable constructor:
+//! [`VorbisDecoder::new_seekable`] (or [`VorbisDecoder::new_seekable_with_limits`]), which
+//! requires a source that implements [`Seek`](std::io::Seek);
+//! [`VorbisDecoder::new_from_bytes`] (or [`VorbisDecoder::new_from_bytes_with_limits`]), for
+//! in-memory bytes and memory maps; and [`VorbisDecoder::new_random_access`], for sources
+//! that implement [`VorbisRandomAccessSource`].
 //!
 //! # Features
 //!
@@ -23,6 +28,15 @@
 //!   method, which automatically configures such a builder with suitable random
 //!   Ogg stream serial numbers. This feature pulls dependencies on random number
 //!   generation crates.
+//! - `resampling`: adds high-quality sample rate conversion stages to encoders and decoders,
+//!   via the `VorbisEncoderBuilder::encoding_sampling_frequency` and
+//!   `VorbisDecoder::set_output_sampling_frequency` methods. This is useful to encode
+//!   signals with sampling frequencies `libvorbisenc` has no tuned modes for, and to
+//!   decode streams at a fixed sampling frequency. This feature pulls a dependency on the
+//!   `rubato` resampling crate.
+//! - `icecast`: adds a source client for broadcasting live streams to Icecast servers, via the
+//!   `VorbisIcecastConnection` and `VorbisIcecastSource` types, which start a new chained
+//!   stream whenever track metadata changes. This feature does not pull any dependencies.
 //!
 //! # WebAssembly targets compatibility
 //!
@@ -63,6 +77,7 @@
 //!     decoder.channels(),
 //!     &mut transcoded_ogg
 //! )?
+//! .comments(decoder.comments().clone())
 //! .build()?;
 //!
 //! while let Some(decoded_block) = decoder.decode_audio_block()? {
@@ -99,3 +114,11 @@ pub use encoder::*;
 
 mod decoder;
 pub use decoder::*;
+
+mod editor;
+pub use editor::*;
+
+#[cfg(feature = "icecast")]
+mod icecast;
+#[cfg(feature = "icecast")]
+pub use icecast::*;